@@ -0,0 +1,115 @@
+//! Content-defined chunking for large snapshot files.
+//!
+//! Chunk boundaries are chosen with a gear rolling hash, so an edit only
+//! changes the chunks around it and the rest deduplicate against earlier
+//! snapshots. Files are streamed, so memory use is bounded by `MAX_CHUNK`.
+
+use anyhow::Result;
+use std::io::{ErrorKind, Read};
+
+const MIN_CHUNK: usize = 512 * 1024;
+const MAX_CHUNK: usize = 8 * 1024 * 1024;
+/// 21 mask bits give an average chunk of roughly 2 MiB past `MIN_CHUNK`.
+const BOUNDARY_MASK: u64 = ((1 << 21) - 1) << 43;
+const READ_BUFFER: usize = 64 * 1024;
+
+const GEAR: [u64; 256] = gear_table();
+
+/// Deterministic pseudo-random gear values (splitmix64), fixed forever so
+/// chunk boundaries stay stable across agt versions.
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0_u64; 256];
+    let mut state: u64 = 0x6167_745f_6364_6331;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// Reads `reader` to the end and calls `on_chunk` once per chunk, in order.
+pub fn for_each_chunk(
+    mut reader: impl Read,
+    mut on_chunk: impl FnMut(&[u8]) -> Result<()>,
+) -> Result<()> {
+    let mut chunk = Vec::with_capacity(MAX_CHUNK);
+    let mut block = vec![0_u8; READ_BUFFER];
+    let mut hash: u64 = 0;
+
+    loop {
+        let read = match reader.read(&mut block) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        for &byte in &block[..read] {
+            chunk.push(byte);
+            hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+            if chunk.len() >= MAX_CHUNK || (chunk.len() >= MIN_CHUNK && hash & BOUNDARY_MASK == 0) {
+                on_chunk(&chunk)?;
+                chunk.clear();
+                hash = 0;
+            }
+        }
+    }
+
+    if !chunk.is_empty() {
+        on_chunk(&chunk)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{for_each_chunk, MAX_CHUNK, MIN_CHUNK};
+    use anyhow::Result;
+
+    fn pseudo_random_bytes(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state.to_le_bytes()[0]
+            })
+            .collect()
+    }
+
+    fn chunks_of(data: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let mut chunks = Vec::new();
+        for_each_chunk(data, |chunk| {
+            chunks.push(chunk.to_vec());
+            Ok(())
+        })?;
+        Ok(chunks)
+    }
+
+    #[test]
+    fn chunks_reassemble_and_survive_local_edits() -> Result<()> {
+        let original = pseudo_random_bytes(24 * 1024 * 1024, 7);
+        let chunks = chunks_of(&original)?;
+        assert!(chunks.len() > 2);
+        assert_eq!(chunks.concat(), original);
+        for chunk in &chunks[..chunks.len() - 1] {
+            assert!(chunk.len() >= MIN_CHUNK && chunk.len() <= MAX_CHUNK);
+        }
+
+        let mut edited = original.clone();
+        edited[12 * 1024 * 1024] ^= 0xff;
+        let edited_chunks = chunks_of(&edited)?;
+        assert_eq!(edited_chunks.concat(), edited);
+        let shared = edited_chunks
+            .iter()
+            .filter(|chunk| chunks.contains(chunk))
+            .count();
+        assert!(shared >= edited_chunks.len() - 2);
+        Ok(())
+    }
+}
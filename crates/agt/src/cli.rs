@@ -1,5 +1,5 @@
 use clap::ArgAction;
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -30,6 +30,9 @@ pub enum Commands {
         /// Snapshot store directory to create
         #[arg(long)]
         store: Option<PathBuf>,
+        /// Create the shared snapshot store used by `--shared`
+        #[arg(long)]
+        shared: bool,
     },
 
     /// Clone a remote repository into agt-managed structure
@@ -47,9 +50,10 @@ pub enum Commands {
 
     /// Auto-commit all modified files to the agent session branch
     Autocommit {
-        /// Session identifier
+        /// Session identifier (default: from `AGT_SESSION_ID` or the
+        /// current directory)
         #[arg(long)]
-        session_id: String,
+        session_id: Option<String>,
         /// Override scan timestamp (Unix epoch) for testing
         #[arg(long)]
         timestamp: Option<i64>,
@@ -59,16 +63,79 @@ pub enum Commands {
         /// Comma-separated list of sibling directories to archive (e.g. "xdg,config")
         #[arg(long, value_delimiter = ',')]
         siblings: Option<Vec<String>>,
+        /// Label the shadow commit; commits even when nothing changed
+        #[arg(long)]
+        label: Option<String>,
+    },
+
+    /// Label the current state of a session so it can be rewound to later
+    ///
+    /// Run from inside a sandbox, the session is the one that sandbox
+    /// belongs to.
+    #[command(args_conflicts_with_subcommands = true)]
+    Checkpoint {
+        #[command(subcommand)]
+        command: Option<CheckpointCommands>,
+        /// Label of the checkpoint
+        #[arg(short = 'm', long = "message", value_name = "LABEL")]
+        label: Option<String>,
+        /// Session to checkpoint (default: from `AGT_SESSION_ID` or the
+        /// current directory)
+        #[arg(long)]
+        session_id: Option<String>,
+        /// Move an existing checkpoint with this label
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Restore a session to a checkpoint; undo with agt session restore --undo
+    Rewind {
+        /// Checkpoint label
+        label: String,
+        /// Session to rewind (default: from `AGT_SESSION_ID` or the
+        /// current directory)
+        #[arg(long)]
+        session_id: Option<String>,
     },
 
     /// Snapshot commands for generated output and restore
     #[command(subcommand)]
     Snapshot(SnapshotCommands),
 
+    /// Compare snapshots, git revisions, session shadow commits or the filesystem
+    ///
+    /// Sides are written as snapshot:<tag>, git:<rev>, session:<id>[~n] or
+    /// fs[:<path>]. Any other arguments are passed to `git diff`.
+    Diff {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
     /// Show agt-specific status
     Status,
 }
 
+/// Arguments of `agt diff` when comparing agt specs.
+#[derive(Parser, Clone)]
+#[command(name = "agt diff")]
+pub struct CrossDiffArgs {
+    /// First side: snapshot:<tag>, git:<rev>, session:<id>[~n] or fs[:<path>]
+    #[arg(value_name = "spec-a")]
+    pub spec_a: String,
+    /// Second side, in the same forms
+    #[arg(value_name = "spec-b")]
+    pub spec_b: String,
+    /// Also show content differences
+    #[arg(short = 'p', long)]
+    pub patch: bool,
+    /// Override snapshot store location
+    #[arg(long)]
+    pub store: Option<PathBuf>,
+    /// Use the shared snapshot store, namespaced by target directory
+    #[arg(long)]
+    pub shared: bool,
+}
+
 #[derive(Subcommand, Clone)]
 pub enum SnapshotCommands {
     /// Save a filesystem snapshot into the snapshot store
@@ -79,9 +146,15 @@ pub enum SnapshotCommands {
         /// Override snapshot store location
         #[arg(long)]
         store: Option<PathBuf>,
+        /// Use the shared snapshot store, namespaced by target directory
+        #[arg(long)]
+        shared: bool,
         /// Message stored with the snapshot tag
         #[arg(short = 'm', long)]
         message: Option<String>,
+        /// Reuse the latest snapshot instead of saving when nothing changed
+        #[arg(long)]
+        if_changed: bool,
     },
 
     /// Compare two saved snapshots and report deleted, modified, and added paths
@@ -92,16 +165,28 @@ pub enum SnapshotCommands {
         /// Later snapshot tag (or older if you want deletions reported as additions)
         #[arg(value_name = "snapshot-b")]
         after: String,
+        /// Directory used to locate the nearest snapshot store
+        #[arg(long, default_value = ".")]
+        target: PathBuf,
         /// Override snapshot store location
         #[arg(long)]
         store: Option<PathBuf>,
+        /// Use the shared snapshot store, namespaced by target directory
+        #[arg(long)]
+        shared: bool,
     },
 
     /// Compare the current filesystem state against the latest snapshot
     Status {
+        /// Directory to compare (default: the latest snapshot's target)
+        #[arg(long)]
+        target: Option<PathBuf>,
         /// Override snapshot store location
         #[arg(long)]
         store: Option<PathBuf>,
+        /// Use the shared snapshot store, namespaced by target directory
+        #[arg(long)]
+        shared: bool,
         /// Reduce output; repeat for no output and exit status only
         #[arg(short = 'q', action = ArgAction::Count)]
         quiet: u8,
@@ -109,12 +194,103 @@ pub enum SnapshotCommands {
 
     /// List saved standalone snapshots
     List {
+        /// Directory used to locate the nearest snapshot store
+        #[arg(long, default_value = ".")]
+        target: PathBuf,
         /// Override snapshot store location
         #[arg(long)]
         store: Option<PathBuf>,
+        /// Use the shared snapshot store, namespaced by target directory
+        #[arg(long)]
+        shared: bool,
+        /// List snapshots of every target in a shared store, grouped by target
+        #[arg(long)]
+        all_targets: bool,
         /// Print only snapshot tags
         #[arg(short = 'q', long)]
         quiet: bool,
+        /// Show provenance recorded with each snapshot
+        #[arg(short = 'v', long)]
+        verbose: bool,
+    },
+
+    /// Stream changes against a snapshot as they happen
+    Watch {
+        /// Baseline snapshot tag (default: the latest snapshot)
+        #[arg(long)]
+        snapshot: Option<String>,
+        /// Directory to watch (default: the baseline snapshot's target)
+        #[arg(long)]
+        target: Option<PathBuf>,
+        /// Override snapshot store location
+        #[arg(long)]
+        store: Option<PathBuf>,
+        /// Use the shared snapshot store, namespaced by target directory
+        #[arg(long)]
+        shared: bool,
+        /// Glob of paths that may change; repeatable
+        #[arg(long = "allow", value_name = "glob")]
+        allow: Vec<String>,
+        /// Print one JSON object per change
+        #[arg(long)]
+        json: bool,
+        /// Exit non-zero on the first change outside the allowed paths
+        #[arg(long)]
+        exit_on_violation: bool,
+    },
+
+    /// Write one file from a snapshot to stdout
+    Cat {
+        /// Snapshot tag (a bare timestamp is resolved in the target's namespace)
+        #[arg(value_name = "spec")]
+        spec: String,
+        /// File path relative to the snapshot target
+        #[arg(value_name = "path")]
+        path: PathBuf,
+        /// Directory used to locate the nearest snapshot store
+        #[arg(long, default_value = ".")]
+        target: PathBuf,
+        /// Override snapshot store location
+        #[arg(long)]
+        store: Option<PathBuf>,
+        /// Use the shared snapshot store, namespaced by target directory
+        #[arg(long)]
+        shared: bool,
+    },
+
+    /// Write every file of a snapshot into a new directory
+    Export {
+        /// Snapshot tag (a bare timestamp is resolved in the target's namespace)
+        #[arg(value_name = "spec")]
+        spec: String,
+        /// Directory to create; it must not exist or be empty
+        #[arg(long)]
+        to: PathBuf,
+        /// Directory used to locate the nearest snapshot store
+        #[arg(long, default_value = ".")]
+        target: PathBuf,
+        /// Override snapshot store location
+        #[arg(long)]
+        store: Option<PathBuf>,
+        /// Use the shared snapshot store, namespaced by target directory
+        #[arg(long)]
+        shared: bool,
+    },
+
+    /// Show details and provenance of one snapshot
+    Show {
+        /// Snapshot tag (a bare timestamp is resolved in the target's namespace)
+        #[arg(value_name = "spec")]
+        spec: String,
+        /// Directory used to locate the nearest snapshot store
+        #[arg(long, default_value = ".")]
+        target: PathBuf,
+        /// Override snapshot store location
+        #[arg(long)]
+        store: Option<PathBuf>,
+        /// Use the shared snapshot store, namespaced by target directory
+        #[arg(long)]
+        shared: bool,
     },
 
     /// Restore files from a saved snapshot
@@ -131,6 +307,40 @@ pub enum SnapshotCommands {
         /// Override snapshot store location
         #[arg(long)]
         store: Option<PathBuf>,
+        /// Use the shared snapshot store, namespaced by target directory
+        #[arg(long)]
+        shared: bool,
+    },
+}
+
+#[derive(Subcommand, Clone)]
+pub enum CheckpointCommands {
+    /// List the checkpoints of a session, oldest first
+    List {
+        /// Session (default: from `AGT_SESSION_ID` or the current directory)
+        #[arg(long)]
+        session_id: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Clone)]
+pub enum MetaCommands {
+    /// Print one field of a session, or all of them as KEY=VALUE lines
+    Get {
+        /// `ticket`, `title`, `labels` or a free-form field
+        key: Option<String>,
+        /// Session (default: from `AGT_SESSION_ID` or the current directory)
+        #[arg(long)]
+        session_id: Option<String>,
+    },
+    /// Set a field of a session; an empty value removes it
+    Set {
+        /// `ticket`, `title`, `labels` (comma separated) or a free-form field
+        key: String,
+        value: String,
+        /// Session (default: from `AGT_SESSION_ID` or the current directory)
+        #[arg(long)]
+        session_id: Option<String>,
     },
 }
 
@@ -138,6 +348,7 @@ pub enum SnapshotCommands {
 pub enum SessionCommands {
     /// Create a new agent session for a fresh ticket
     New {
+        /// Session ID (default: derived from `--ticket`, or generated)
         #[arg(long)]
         id: Option<String>,
         /// Starting point: branch name, commit, or session ID
@@ -146,6 +357,22 @@ pub enum SessionCommands {
         /// Tool profile for folder setup
         #[arg(long, default_value = "default")]
         profile: String,
+        /// Ticket the session works on, e.g. ABC-123
+        #[arg(long)]
+        ticket: Option<String>,
+        /// One-line description of the session
+        #[arg(long)]
+        title: Option<String>,
+        /// Label for grouping sessions. Repeatable
+        #[arg(long = "label", value_name = "LABEL")]
+        labels: Vec<String>,
+        /// Free-form field. Repeatable
+        #[arg(long, value_name = "KEY=VALUE")]
+        meta: Vec<String>,
+        /// Use a user branch named after the ticket, creating it at the
+        /// starting point if it does not exist
+        #[arg(long, requires = "ticket")]
+        ticket_branch: bool,
     },
 
     /// Export session's user branch to remote
@@ -156,29 +383,259 @@ pub enum SessionCommands {
 
     /// Remove a session
     Remove {
-        #[arg(long)]
-        id: String,
+        /// Session to remove (default: from `AGT_SESSION_ID` or the
+        /// current directory)
+        #[arg(long, alias = "id")]
+        session_id: Option<String>,
         #[arg(long)]
         delete_branch: bool,
     },
 
-    /// Fork a session for parallel work
+    /// Fork a session for parallel work, cloning its whole session folder
     Fork {
         #[arg(long)]
         from: String,
         #[arg(long)]
         id: Option<String>,
+        /// Shadow commit to fork from (default: the source session's latest)
+        #[arg(long)]
+        commit: Option<String>,
     },
 
     /// Restore session to a prior shadow commit state
     Restore {
+        /// Session to restore (default: from `AGT_SESSION_ID` or the
+        /// current directory)
+        #[arg(long)]
+        session_id: Option<String>,
+        #[command(flatten)]
+        select: ShadowSelector,
+        /// Restore into a new session with this ID, leaving the original untouched
+        #[arg(long, value_name = "ID")]
+        as_new: Option<String>,
+        /// Go back to the state saved before the last restore
+        #[arg(long, conflicts_with_all = ["target", "as_new"])]
+        undo: bool,
+        /// Do not ask for confirmation of the selected shadow commit
+        #[arg(short, long)]
+        yes: bool,
+        /// Restore only these files or directories, relative to the session folder
+        #[arg(long, conflicts_with_all = ["as_new", "undo"])]
+        path: Vec<PathBuf>,
+    },
+
+    /// Save a session in a shadow commit, then remove its folder and sandbox
+    Archive {
+        /// Session to archive (default: from `AGT_SESSION_ID` or the
+        /// current directory)
+        #[arg(long)]
+        session_id: Option<String>,
+    },
+
+    /// Recreate a session folder and sandbox from its shadow branch
+    Rehydrate {
+        /// Session to rehydrate (default: from `AGT_SESSION_ID`)
+        #[arg(long)]
+        session_id: Option<String>,
+        /// Shadow commit to rehydrate (default: the latest)
+        #[arg(long)]
+        commit: Option<String>,
+    },
+
+    /// Rebuild missing session metadata from shadow branches
+    Recover,
+
+    /// Integrate a session's work into a branch in the main worktree, then
+    /// archive or remove the session
+    Land {
+        /// Session to land (default: from `AGT_SESSION_ID` or the
+        /// current directory)
+        #[arg(long)]
+        session_id: Option<String>,
+        /// Session to land, as an alternative to --session-id
+        #[arg(value_name = "ID", conflicts_with = "session_id")]
+        id: Option<String>,
+        /// Replay the session's commits onto the branch
+        #[arg(long, conflicts_with_all = ["squash", "merge"])]
+        rebase: bool,
+        /// Land the session's work as a single commit
+        #[arg(long, conflicts_with = "merge")]
+        squash: bool,
+        /// Merge the session's commits with a merge commit (default)
+        #[arg(long)]
+        merge: bool,
+        /// Branch to land into (default: the session's user branch); it must
+        /// be checked out in the main worktree
+        #[arg(long, value_name = "BRANCH")]
+        into: Option<String>,
+        /// Fold commits authored by agentEmail into the next commit kept
+        #[arg(long)]
+        strip_agent_commits: bool,
+        /// Message of the merge or squash commit
+        #[arg(short, long)]
+        message: Option<String>,
+        /// Remove the session and its shadow branch instead of archiving it
+        #[arg(long)]
+        remove: bool,
+    },
+
+    /// Find orphaned session state and, with --apply, remove it
+    Gc {
+        /// Remove what was found instead of only reporting it
+        #[arg(long)]
+        apply: bool,
+        /// Also collect sessions whose user branch is merged into BRANCH
+        #[arg(long, value_name = "BRANCH")]
+        merged_into: Option<String>,
+        /// Also collect sessions with no activity for this long (e.g. 14d)
+        #[arg(long, value_name = "DURATION")]
+        older_than: Option<String>,
+    },
+
+    /// Compare what several sessions produced from the same starting commit
+    Compare {
+        #[arg(required = true, num_args = 2.., value_name = "SESSION_ID")]
+        session_ids: Vec<String>,
+        /// Also print the full diff between each pair of sandbox trees
+        #[arg(short, long)]
+        patch: bool,
+    },
+
+    /// Find the first shadow commit whose sandbox fails a command
+    Bisect {
+        /// Session to bisect (default: from `AGT_SESSION_ID` or the
+        /// current directory)
         #[arg(long)]
-        session_id: String,
-        /// Shadow commit SHA to restore to
+        session_id: Option<String>,
+        /// A commit on the shadow branch whose sandbox passes
+        #[arg(long)]
+        good: String,
+        /// A later commit whose sandbox fails (default: the shadow branch tip)
+        #[arg(long)]
+        bad: Option<String>,
+        /// Command to run, with sh -c, in each checked out sandbox; exit
+        /// status 0 means good
+        #[arg(long)]
+        cmd: String,
+    },
+
+    /// Run a command in the sandbox of each session and compare the results
+    Check {
+        /// Command to run, with sh -c, in each sandbox
+        #[arg(long)]
+        cmd: String,
+        /// Check every session
+        #[arg(long, conflicts_with = "session_ids")]
+        all: bool,
+        /// Run the command in all sandboxes at once
+        #[arg(long)]
+        parallel: bool,
+        #[arg(required_unless_present = "all", value_name = "SESSION_ID")]
+        session_ids: Vec<String>,
+    },
+
+    /// Rename a session: its shadow branch, folder, worktree and metadata
+    #[command(override_usage = "agt session rename [OPTIONS] [OLD_ID] <NEW_ID>")]
+    Rename {
+        /// Session to rename (default: from `AGT_SESSION_ID` or the
+        /// current directory)
+        #[arg(long)]
+        session_id: Option<String>,
+        /// `NEW_ID`, or `OLD_ID NEW_ID` without --session-id
+        #[arg(value_name = "ID", num_args = 1..=2, required = true)]
+        ids: Vec<String>,
+    },
+
+    /// Move a session folder, and the sandbox in it, to another path
+    Move {
+        /// Session to move (default: from `AGT_SESSION_ID` or the current
+        /// directory)
+        #[arg(long)]
+        session_id: Option<String>,
+        /// Session to move, as an alternative to --session-id
+        #[arg(value_name = "ID", conflicts_with = "session_id")]
+        id: Option<String>,
+        /// New path of the session folder; must not exist
+        #[arg(long)]
+        to: PathBuf,
+    },
+
+    /// Read or change a session's ticket, title, labels and free-form fields
+    Meta {
+        #[command(subcommand)]
+        command: MetaCommands,
+    },
+
+    /// Print shell exports describing a session, for eval "$(agt session env)"
+    Env {
+        /// Session to describe (default: from `AGT_SESSION_ID` or the
+        /// current directory)
+        #[arg(long)]
+        session_id: Option<String>,
+        /// Session to describe, as an alternative to --session-id
+        #[arg(value_name = "ID", conflicts_with = "session_id")]
+        id: Option<String>,
+    },
+
+    /// Show one session's pending changes, sandbox and shadow history state
+    Status {
+        /// Session to show (default: from `AGT_SESSION_ID` or the
+        /// current directory)
         #[arg(long)]
-        commit: String,
+        session_id: Option<String>,
     },
 
-    /// List sessions
-    List,
+    /// List sessions with their state
+    List {
+        /// Show only sessions where KEY (id, profile, user-branch, sandbox,
+        /// ticket, title, label, or `meta.NAME` for a free-form field) equals
+        /// VALUE; a trailing `*` matches a prefix. Repeatable
+        #[arg(long, value_name = "KEY=VALUE")]
+        filter: Vec<String>,
+        /// Order of the listed sessions
+        #[arg(long, value_enum, default_value = "id")]
+        sort: SessionSort,
+        /// Reverse the order
+        #[arg(long)]
+        reverse: bool,
+    },
+}
+
+/// How `agt session land` integrates a session.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LandMode {
+    Merge,
+    Rebase,
+    Squash,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum SessionSort {
+    Id,
+    Created,
+    LastAutocommit,
+    Commits,
+    Pending,
+    Size,
+}
+
+/// Selects a shadow commit by SHA, time, position or label.
+#[derive(Args, Clone)]
+#[group(id = "target", multiple = false)]
+pub struct ShadowSelector {
+    /// Shadow commit SHA
+    #[arg(long)]
+    pub commit: Option<String>,
+    /// Newest shadow commit at or before this time (e.g. "2026-10-17 14:05")
+    #[arg(long, value_name = "TIME")]
+    pub at: Option<String>,
+    /// Newest shadow commit at least this old (e.g. 15m, 2h, 1h30m)
+    #[arg(long, value_name = "DURATION")]
+    pub before: Option<String>,
+    /// Step back N shadow commits from the latest
+    #[arg(long, value_name = "N")]
+    pub steps_back: Option<usize>,
+    /// Newest shadow commit with this label
+    #[arg(long)]
+    pub label: Option<String>,
 }
@@ -1,20 +1,16 @@
+use super::check_sessions::CHECK_LOG_DIR;
 use crate::config::AgtConfig;
 use crate::path_util;
+use crate::session_store::SessionStore;
+use crate::shadow_history::{self, SESSION_INFO_DIR, SESSION_INFO_PATH};
 use anyhow::{Context, Result};
 use gix::object::tree::EntryKind;
 use gix::Repository;
 use gix_object::TreeRefIter;
 use gix_path::from_byte_slice;
-use serde::Deserialize;
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 
-#[derive(Debug, Deserialize)]
-struct SessionMetadata {
-    user_branch: String,
-    sandbox: String,
-}
-
 struct SnapshotDelta {
     changed: HashMap<PathBuf, PathBuf>,
     deleted: HashSet<PathBuf>,
@@ -29,26 +25,47 @@ impl SnapshotDelta {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn run(
+    repo: &Repository,
+    cwd: &Path,
+    session_id: &str,
+    override_timestamp: Option<i64>,
+    dry_run: bool,
+    siblings: Option<Vec<String>>,
+    label: Option<&str>,
+    config: &AgtConfig,
+) -> Result<()> {
+    let _lock = SessionStore::open(repo).lock(session_id)?;
+    run_locked(
+        repo,
+        cwd,
+        session_id,
+        override_timestamp,
+        dry_run,
+        siblings,
+        label,
+        config,
+    )
+}
+
+/// [`run`] for callers that already hold the session's lock.
+#[allow(clippy::too_many_arguments)]
+pub fn run_locked(
     repo: &Repository,
     cwd: &Path,
     session_id: &str,
     override_timestamp: Option<i64>,
     dry_run: bool,
     _siblings: Option<Vec<String>>,
+    label: Option<&str>,
     config: &AgtConfig,
 ) -> Result<()> {
     let branch_name = format!("{}{}", config.branch_prefix, session_id);
 
     // Resolve session metadata
-    let session_meta_path = repo
-        .common_dir()
-        .join("agt/sessions")
-        .join(format!("{session_id}.json"));
-    let session_meta_raw = std::fs::read_to_string(&session_meta_path)
-        .with_context(|| format!("Failed to read {}", session_meta_path.display()))?;
-    let session: SessionMetadata = serde_json::from_str(&session_meta_raw)
-        .with_context(|| format!("Failed to parse {}", session_meta_path.display()))?;
+    let session = SessionStore::open(repo).load(session_id)?;
+    let session_info = serde_json::to_vec_pretty(&session)?;
 
     // Determine sandbox path
     let mut sandbox_path = PathBuf::from(&session.sandbox);
@@ -69,11 +86,7 @@ pub fn run(
 
     // 1. Read last timestamp
     let timestamp_file = repo.common_dir().join("agt/timestamps").join(session_id);
-    let last_timestamp: u64 = match std::fs::read_to_string(&timestamp_file) {
-        Ok(s) => s.trim().parse()?,
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
-        Err(e) => return Err(e.into()),
-    };
+    let last_timestamp = last_autocommit_timestamp(repo, session_id)?;
 
     let scan_timestamp: u64 = match override_timestamp {
         Some(t) if t < 0 => anyhow::bail!("timestamp override must be >= 0"),
@@ -89,17 +102,9 @@ pub fn run(
         .context("Failed to resolve shadow branch")?;
 
     // 3. Compute Delta - scan entire session folder
-    let mut base_paths = HashSet::new();
-    collect_tree_paths(
-        repo,
-        parent1.tree_id()?.detach(),
-        PathBuf::new(),
-        &mut base_paths,
-    )?;
-
-    let delta = scan_changes(&session_folder, &base_paths, scan_timestamp)?;
+    let delta = scan_session_folder(repo, &parent1, &session_folder, scan_timestamp)?;
 
-    if delta.changed.is_empty() && delta.deleted.is_empty() {
+    if delta.changed.is_empty() && delta.deleted.is_empty() && label.is_none() {
         println!("No modified files since last autocommit");
         return Ok(());
     }
@@ -142,7 +147,7 @@ pub fn run(
         anyhow::bail!("Unborn HEAD in sandbox is not supported");
     }
 
-    let tree_id = build_tree_from_delta(repo, &parent1, &delta)?;
+    let tree_id = build_tree_from_delta(repo, &parent1, &delta, &session_info)?;
 
     let signature = gix::actor::SignatureRef {
         name: gix::bstr::BStr::new("agt"),
@@ -150,11 +155,12 @@ pub fn run(
         time: gix::date::Time::now_local_or_utc(),
     };
 
+    let message = shadow_history::commit_message(session_id, &session.user_branch, label);
     let commit_id = repo.commit_as(
         signature,
         signature,
         shadow_branch_ref.as_str(),
-        "agt autocommit",
+        message.as_str(),
         tree_id,
         [parent1.id, parent2_id],
     )?;
@@ -174,6 +180,68 @@ pub fn run(
     Ok(())
 }
 
+/// Files an autocommit of the session would record, relative to the session
+/// folder.
+pub struct PendingChanges {
+    pub changed: Vec<PathBuf>,
+    pub deleted: Vec<PathBuf>,
+}
+
+/// Scans `session_folder` like `agt autocommit` would, without committing.
+pub fn pending_changes(
+    repo: &Repository,
+    session_id: &str,
+    shadow_branch_ref: &str,
+    session_folder: &Path,
+) -> Result<PendingChanges> {
+    let parent1 = repo
+        .find_reference(shadow_branch_ref)?
+        .peel_to_commit()
+        .context("Failed to resolve shadow branch")?;
+    let since = last_autocommit_timestamp(repo, session_id)?;
+    let delta = scan_session_folder(repo, &parent1, session_folder, since)?;
+    let mut changed: Vec<_> = delta.changed.into_keys().collect();
+    changed.sort();
+    let mut deleted: Vec<_> = delta.deleted.into_iter().collect();
+    deleted.sort();
+    Ok(PendingChanges { changed, deleted })
+}
+
+/// Seconds since the Unix epoch of the session's last autocommit scan; 0 if
+/// it has never been scanned.
+pub fn last_autocommit_timestamp(repo: &Repository, session_id: &str) -> Result<u64> {
+    let timestamp_file = repo.common_dir().join("agt/timestamps").join(session_id);
+    match std::fs::read_to_string(&timestamp_file) {
+        Ok(s) => Ok(s.trim().parse()?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn scan_session_folder(
+    repo: &Repository,
+    parent1: &gix::Commit<'_>,
+    session_folder: &Path,
+    since_timestamp: u64,
+) -> Result<SnapshotDelta> {
+    let mut base_paths = HashSet::new();
+    collect_tree_paths(
+        repo,
+        parent1.tree_id()?.detach(),
+        PathBuf::new(),
+        &mut base_paths,
+    )?;
+
+    let mut delta = scan_changes(session_folder, &base_paths, since_timestamp)?;
+    // `_agt/` is written from the metadata, never from disk, and check logs
+    // are output about the session rather than part of it.
+    let skipped =
+        |path: &Path| path.starts_with(SESSION_INFO_DIR) || path.starts_with(CHECK_LOG_DIR);
+    delta.changed.retain(|path, _| !skipped(path));
+    delta.deleted.retain(|path| !skipped(path));
+    Ok(delta)
+}
+
 fn scan_changes(
     scan_root: &Path,
     base_paths: &HashSet<PathBuf>,
@@ -226,6 +294,7 @@ fn build_tree_from_delta(
     repo: &Repository,
     base_commit: &gix::Commit<'_>,
     delta: &SnapshotDelta,
+    session_info: &[u8],
 ) -> Result<gix::ObjectId> {
     let base_tree_id = base_commit.tree_id()?.detach();
     let mut editor = repo.edit_tree(base_tree_id)?;
@@ -261,6 +330,9 @@ fn build_tree_from_delta(
         editor.upsert(path_for_tree(repo_path), entry_kind, blob_id.detach())?;
     }
 
+    let info_id = repo.write_blob(session_info)?;
+    editor.upsert(SESSION_INFO_PATH, EntryKind::Blob, info_id.detach())?;
+
     Ok(editor.write()?.detach())
 }
 
@@ -0,0 +1,179 @@
+use crate::config::AgtConfig;
+use crate::gix_cli::git_command;
+use crate::session_store::SessionStore;
+use crate::shadow_history;
+use anyhow::{bail, Context, Result};
+use gix::{ObjectId, Repository};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+/// Removes the scratch directory however bisect ends.
+struct ScratchDir(PathBuf);
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Finds the first commit on the first-parent chain from `good` (exclusive)
+/// to `bad` whose sandbox fails `cmd`. Each candidate is checked out into a
+/// scratch directory; the live sandbox is not touched.
+pub fn run(
+    repo: &Repository,
+    config: &AgtConfig,
+    session_id: &str,
+    good: &str,
+    bad: Option<&str>,
+    cmd: &str,
+) -> Result<()> {
+    let metadata = SessionStore::open(repo)
+        .load(session_id)
+        .with_context(|| format!("Session not found: {session_id}"))?;
+    let shadow_branch_ref = format!("refs/heads/{}", metadata.branch);
+    let bad = match bad {
+        Some(rev) => resolve(repo, rev)?,
+        None => {
+            repo.find_reference(shadow_branch_ref.as_str())
+                .with_context(|| format!("Shadow branch not found: {}", metadata.branch))?
+                .peel_to_commit()?
+                .id
+        }
+    };
+    let good = resolve(repo, good)?;
+    let candidates = candidates(repo, &metadata.branch, good, bad)?;
+
+    let scratch = ScratchDir(
+        std::env::temp_dir().join(format!("agt-bisect-{session_id}-{}", std::process::id())),
+    );
+    println!(
+        "Bisecting {} commits of session {session_id} in {}",
+        candidates.len(),
+        scratch.0.display()
+    );
+
+    // candidates[good_index] passes and candidates[bad_index] fails; -1 is
+    // `good` itself.
+    let mut good_index: isize = -1;
+    let mut bad_index = isize::try_from(candidates.len())? - 1;
+    while bad_index - good_index > 1 {
+        let mid = good_index + (bad_index - good_index) / 2;
+        let commit = candidates[usize::try_from(mid)?];
+        let passed = test(repo, commit, &scratch.0, cmd)?;
+        if passed {
+            good_index = mid;
+        } else {
+            bad_index = mid;
+        }
+        println!(
+            "{} {}: {} (roughly {} steps left)",
+            short(commit),
+            describe(repo, commit)?,
+            if passed { "good" } else { "bad" },
+            steps_left(bad_index - good_index - 1)
+        );
+    }
+
+    let first_bad = candidates[usize::try_from(bad_index)?];
+    let last_good = usize::try_from(good_index).map_or(good, |index| candidates[index]);
+    println!();
+    println!("First bad commit: {first_bad}");
+    println!("  {}", describe(repo, first_bad)?);
+    println!("  Last good: {last_good}");
+    let before = shadow_history::sandbox_tree(&repo.find_commit(last_good)?)?.id;
+    let after = shadow_history::sandbox_tree(&repo.find_commit(first_bad)?)?.id;
+    let output = git_command(repo, config)
+        .args(["diff", "--stat", "--no-color"])
+        .arg(before.to_string())
+        .arg(after.to_string())
+        .output()
+        .context("Failed to run git diff")?;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        println!("  {line}");
+    }
+    if shadow_history::is_shadow_commit(&repo.find_commit(last_good)?)? {
+        println!("Restore the last good state with: agt session restore --session-id {session_id} --commit {last_good}");
+    }
+    Ok(())
+}
+
+fn resolve(repo: &Repository, rev: &str) -> Result<ObjectId> {
+    Ok(repo
+        .rev_parse_single(rev)
+        .with_context(|| format!("Commit not found: {rev}"))?
+        .object()?
+        .peel_to_commit()?
+        .id)
+}
+
+/// The first-parent commits after `good` up to `bad`, oldest first.
+fn candidates(
+    repo: &Repository,
+    branch: &str,
+    good: ObjectId,
+    bad: ObjectId,
+) -> Result<Vec<ObjectId>> {
+    let mut candidates = Vec::new();
+    let mut id = bad;
+    while id != good {
+        candidates.push(id);
+        let commit = repo.find_commit(id)?;
+        let Some(parent) = commit.parent_ids().next() else {
+            bail!(
+                "{} is not on the first-parent history of {branch} before {}",
+                short(good),
+                short(bad)
+            );
+        };
+        id = parent.detach();
+    }
+    if candidates.is_empty() {
+        bail!("--good and --bad are the same commit");
+    }
+    candidates.reverse();
+    Ok(candidates)
+}
+
+/// Checks the sandbox of `commit` out into `scratch` and runs `cmd` there.
+fn test(repo: &Repository, commit: ObjectId, scratch: &Path, cmd: &str) -> Result<bool> {
+    if scratch.exists() {
+        fs::remove_dir_all(scratch)?;
+    }
+    fs::create_dir_all(scratch)?;
+    let tree = shadow_history::sandbox_tree(&repo.find_commit(commit)?)?;
+    super::session::restore_checkout_tree_to_disk(repo, tree.id, Path::new(""), scratch)?;
+
+    let status = super::check_sessions::shell(cmd)
+        .current_dir(scratch)
+        .stdin(Stdio::null())
+        .status()
+        .with_context(|| format!("Failed to run `{cmd}`"))?;
+    Ok(status.success())
+}
+
+fn describe(repo: &Repository, id: ObjectId) -> Result<String> {
+    let commit = repo.find_commit(id)?;
+    let time = shadow_history::format_time(commit.time()?.seconds);
+    if !shadow_history::is_shadow_commit(&commit)? {
+        let summary = commit.message()?.summary().to_string();
+        return Ok(format!("{time}, \"{summary}\""));
+    }
+    let message = commit.message_raw()?.to_string();
+    Ok(
+        match shadow_history::trailer(&message, shadow_history::LABEL_TRAILER) {
+            Some(label) => format!("{time}, label {label}"),
+            None => time,
+        },
+    )
+}
+
+fn short(id: ObjectId) -> String {
+    id.to_hex_with_len(12).to_string()
+}
+
+/// Tests still needed to narrow `remaining` untested candidates to one.
+fn steps_left(remaining: isize) -> u32 {
+    let remaining = remaining.unsigned_abs();
+    usize::BITS - remaining.leading_zeros()
+}
@@ -0,0 +1,252 @@
+use super::list_sessions::print_table;
+use crate::config::AgtConfig;
+use crate::path_util;
+use crate::session_store::{CheckResult, SessionMetadata, SessionStore};
+use crate::shadow_history;
+use anyhow::{bail, Context, Result};
+use gix::Repository;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::{Command as StdCommand, Stdio};
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Folder, in the session folder, that check logs are written to. It is not
+/// autocommitted.
+pub const CHECK_LOG_DIR: &str = "checks";
+
+/// A session to run the check in.
+struct Target {
+    session_id: String,
+    sandbox: PathBuf,
+    commit: String,
+}
+
+enum Outcome {
+    Checked(CheckResult),
+    /// The session could not be checked or its result not recorded.
+    Error,
+    Skipped(String),
+}
+
+/// Runs `cmd` in the sandbox of each session, after autocommitting it, and
+/// records the result in the session's metadata.
+pub fn run(
+    repo: &Repository,
+    config: &AgtConfig,
+    cmd: &str,
+    all: bool,
+    session_ids: &[String],
+    parallel: bool,
+) -> Result<()> {
+    let store = SessionStore::open(repo);
+    let sessions = if all {
+        store.list()?
+    } else {
+        session_ids
+            .iter()
+            .map(|id| {
+                store
+                    .load(id)
+                    .with_context(|| format!("Session not found: {id}"))
+            })
+            .collect::<Result<_>>()?
+    };
+    if sessions.is_empty() {
+        println!("No agent sessions found");
+        return Ok(());
+    }
+
+    let mut outcomes = Vec::new();
+    let mut targets = Vec::new();
+    for metadata in &sessions {
+        let session_id = metadata.session_id.clone();
+        match prepare(repo, config, metadata) {
+            Ok(Ok(target)) => targets.push(target),
+            Ok(Err(reason)) => outcomes.push((session_id, Outcome::Skipped(reason))),
+            Err(err) => {
+                eprintln!("Error: session {session_id}: {err:#}");
+                outcomes.push((session_id, Outcome::Error));
+            }
+        }
+    }
+
+    println!("Running `{cmd}` in {} sessions", targets.len());
+    let results = if parallel {
+        thread::scope(|scope| {
+            let handles: Vec<_> = targets
+                .iter()
+                .map(|target| scope.spawn(|| check(target, cmd)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("check thread panicked"))
+                .collect::<Vec<_>>()
+        })
+    } else {
+        targets.iter().map(|target| check(target, cmd)).collect()
+    };
+
+    // One session's failure does not keep the others' results from being recorded.
+    for (target, result) in targets.iter().zip(results) {
+        let outcome = result.and_then(|result| {
+            let _lock = store.lock(&target.session_id)?;
+            let mut metadata = store.load(&target.session_id)?;
+            metadata.last_check = Some(result.clone());
+            store.save(&metadata)?;
+            Ok(result)
+        });
+        match outcome {
+            Ok(result) => outcomes.push((target.session_id.clone(), Outcome::Checked(result))),
+            Err(err) => {
+                eprintln!("Error: session {}: {err:#}", target.session_id);
+                outcomes.push((target.session_id.clone(), Outcome::Error));
+            }
+        }
+    }
+
+    // Passing sessions first, fastest first; skipped sessions last.
+    outcomes.sort_by_key(|(_, outcome)| match outcome {
+        Outcome::Checked(result) => (u8::from(!result.passed()), result.duration_ms),
+        Outcome::Error => (2, 0),
+        Outcome::Skipped(_) => (3, 0),
+    });
+    println!();
+    print_scoreboard(&outcomes);
+
+    let failed = outcomes
+        .iter()
+        .filter(|(_, outcome)| match outcome {
+            Outcome::Checked(result) => !result.passed(),
+            Outcome::Error => true,
+            Outcome::Skipped(_) => false,
+        })
+        .count();
+    if failed > 0 {
+        bail!("{failed} of {} sessions failed `{cmd}`", outcomes.len());
+    }
+    Ok(())
+}
+
+/// Autocommits the session so the result can be tied to a shadow commit,
+/// or says why the session cannot be checked.
+fn prepare(
+    repo: &Repository,
+    config: &AgtConfig,
+    metadata: &SessionMetadata,
+) -> Result<std::result::Result<Target, String>> {
+    let session_id = &metadata.session_id;
+    if metadata.archived_at.is_some() {
+        return Ok(Err("archived".to_string()));
+    }
+    let sandbox = Path::new(&metadata.sandbox);
+    if !sandbox.exists() {
+        return Ok(Err("sandbox missing".to_string()));
+    }
+    let sandbox = path_util::canonicalize(sandbox)?;
+
+    super::autocommit::run(repo, &sandbox, session_id, None, false, None, None, config)
+        .with_context(|| format!("Failed to autocommit session {session_id}"))?;
+    let commit = repo
+        .find_reference(format!("refs/heads/{}", metadata.branch).as_str())?
+        .peel_to_commit()?
+        .id
+        .to_string();
+    Ok(Ok(Target {
+        session_id: session_id.clone(),
+        sandbox,
+        commit,
+    }))
+}
+
+/// Runs `cmd` in the target's sandbox, writing its output to
+/// `checks/<time>.log` in the session folder.
+fn check(target: &Target, cmd: &str) -> Result<CheckResult> {
+    let checked_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let session_folder = target.sandbox.parent().context("Sandbox has no parent")?;
+    let log = PathBuf::from(CHECK_LOG_DIR).join(format!("{checked_at}.log"));
+    let log_path = session_folder.join(&log);
+    fs::create_dir_all(log_path.parent().context("Log path has no parent")?)?;
+    let stdout = File::create(&log_path)
+        .with_context(|| format!("Failed to create {}", log_path.display()))?;
+    let stderr = stdout.try_clone()?;
+
+    let started = Instant::now();
+    let status = shell(cmd)
+        .current_dir(&target.sandbox)
+        .stdin(Stdio::null())
+        .stdout(stdout)
+        .stderr(stderr)
+        .status()
+        .with_context(|| format!("Failed to run `{cmd}` in {}", target.sandbox.display()))?;
+
+    Ok(CheckResult {
+        command: cmd.to_string(),
+        exit_code: status.code(),
+        duration_ms: u64::try_from(started.elapsed().as_millis())?,
+        commit: target.commit.clone(),
+        checked_at,
+        log: log.display().to_string(),
+    })
+}
+
+/// `cmd` run by the platform shell.
+#[cfg(unix)]
+pub fn shell(cmd: &str) -> StdCommand {
+    let mut command = StdCommand::new("sh");
+    command.arg("-c").arg(cmd);
+    command
+}
+
+#[cfg(not(unix))]
+pub fn shell(cmd: &str) -> StdCommand {
+    let mut command = StdCommand::new("cmd");
+    command.arg("/C").arg(cmd);
+    command
+}
+
+fn print_scoreboard(outcomes: &[(String, Outcome)]) {
+    let mut rows =
+        vec![["SESSION", "RESULT", "EXIT", "DURATION", "COMMIT", "LOG"].map(str::to_string)];
+    for (session_id, outcome) in outcomes {
+        rows.push(match outcome {
+            Outcome::Checked(result) => [
+                session_id.clone(),
+                if result.passed() { "pass" } else { "fail" }.to_string(),
+                result
+                    .exit_code
+                    .map_or_else(|| "signal".to_string(), |code| code.to_string()),
+                format_duration_ms(result.duration_ms),
+                result.commit.chars().take(12).collect(),
+                result.log.clone(),
+            ],
+            Outcome::Error => [
+                session_id.clone(),
+                "error".to_string(),
+                "-".to_string(),
+                "-".to_string(),
+                "-".to_string(),
+                "-".to_string(),
+            ],
+            Outcome::Skipped(reason) => [
+                session_id.clone(),
+                format!("skipped ({reason})"),
+                "-".to_string(),
+                "-".to_string(),
+                "-".to_string(),
+                "-".to_string(),
+            ],
+        });
+    }
+    print_table(&rows);
+}
+
+/// Formats a check duration, with tenths of a second under a minute.
+#[allow(clippy::cast_precision_loss)]
+pub fn format_duration_ms(duration_ms: u64) -> String {
+    if duration_ms < 60_000 {
+        format!("{:.1}s", duration_ms as f64 / 1000.0)
+    } else {
+        shadow_history::format_duration(i64::try_from(duration_ms / 1000).unwrap_or(i64::MAX))
+    }
+}
@@ -0,0 +1,133 @@
+//! Named shadow commits. A checkpoint is an autocommit labelled like
+//! `agt autocommit --label`, plus a ref
+//! `refs/agt/sessions/<id>/checkpoints/<label>` that keeps pointing at it
+//! after later autocommits or restores move the shadow branch.
+
+use crate::config::AgtConfig;
+use crate::session_store::SessionStore;
+use crate::shadow_history::{self, Selector};
+use anyhow::{bail, Context, Result};
+use gix::{ObjectId, Repository};
+use gix_ref::transaction::PreviousValue;
+use std::path::Path;
+
+/// Namespace of the checkpoint refs of `session_id`.
+pub fn ref_prefix(session_id: &str) -> String {
+    format!("refs/agt/sessions/{session_id}/checkpoints/")
+}
+
+pub fn create(
+    repo: &Repository,
+    config: &AgtConfig,
+    session_id: Option<&str>,
+    label: &str,
+    force: bool,
+) -> Result<()> {
+    let metadata = super::session::resolve_session(repo, session_id)?;
+    let session_id = metadata.session_id.as_str();
+    let checkpoint_ref = format!("{}{label}", ref_prefix(session_id));
+    if gix_ref::FullName::try_from(checkpoint_ref.as_str()).is_err() {
+        bail!("Invalid checkpoint label {label}; it must be usable in a Git ref name");
+    }
+
+    // Checked under the session lock, so two creates cannot both pass it
+    // and autocommit before one of them fails.
+    let store = SessionStore::open(repo);
+    let _lock = store.lock(session_id)?;
+    let previous = if force {
+        PreviousValue::Any
+    } else {
+        if repo.find_reference(checkpoint_ref.as_str()).is_ok() {
+            bail!("Checkpoint {label} already exists in session {session_id}; pass --force to move it");
+        }
+        PreviousValue::MustNotExist
+    };
+    let sandbox = Path::new(&metadata.sandbox);
+    super::autocommit::run_locked(
+        repo,
+        sandbox,
+        session_id,
+        None,
+        false,
+        None,
+        Some(label),
+        config,
+    )?;
+    let commit = repo
+        .find_reference(format!("refs/heads/{}", metadata.branch).as_str())?
+        .peel_to_commit()?
+        .id;
+    repo.reference(
+        checkpoint_ref.as_str(),
+        commit,
+        previous,
+        format!("agt checkpoint {label}"),
+    )?;
+    println!("Checkpoint {label} of session {session_id} at {commit}");
+    Ok(())
+}
+
+pub fn list(repo: &Repository, session_id: Option<&str>) -> Result<()> {
+    let metadata = super::session::resolve_session(repo, session_id)?;
+    let checkpoints = checkpoints(repo, &metadata.session_id)?;
+    if checkpoints.is_empty() {
+        println!("No checkpoints in session {}", metadata.session_id);
+        return Ok(());
+    }
+    for (label, id, time) in checkpoints {
+        println!(
+            "{}  {}  {label}",
+            id.to_hex_with_len(12),
+            shadow_history::format_short_time(time)
+        );
+    }
+    Ok(())
+}
+
+/// Restores the session to checkpoint `label`, or to the newest shadow commit
+/// labelled `label` by `agt autocommit --label`.
+pub fn rewind(
+    repo: &Repository,
+    config: &AgtConfig,
+    session_id: Option<&str>,
+    label: &str,
+) -> Result<()> {
+    let metadata = super::session::resolve_session(repo, session_id)?;
+    let session_id = metadata.session_id.as_str();
+    let checkpoint_ref = format!("{}{label}", ref_prefix(session_id));
+    let commit = match repo.find_reference(checkpoint_ref.as_str()) {
+        Ok(mut reference) => reference.peel_to_id_in_place()?.detach(),
+        Err(_) => {
+            let chain = shadow_history::first_parent_chain(
+                repo,
+                config,
+                &format!("refs/heads/{}", metadata.branch),
+                &metadata.from_commit,
+            )?;
+            let index = shadow_history::select(&chain, &Selector::Label(label.to_string()))
+                .with_context(|| format!("No checkpoint {label} in session {session_id}"))?;
+            chain[index].id
+        }
+    };
+    super::session::restore_session(repo, config, session_id, &commit.to_string())?;
+    println!("Rewound session {session_id} to checkpoint {label}; undo with: agt session restore --session-id {session_id} --undo");
+    Ok(())
+}
+
+/// Label, commit and commit time of each checkpoint, oldest first.
+fn checkpoints(repo: &Repository, session_id: &str) -> Result<Vec<(String, ObjectId, i64)>> {
+    let prefix = ref_prefix(session_id);
+    let mut checkpoints = Vec::new();
+    for reference in repo.references()?.prefixed(prefix.as_str())? {
+        let mut reference = reference.map_err(|err| anyhow::anyhow!(err))?;
+        let name = reference.name().as_bstr().to_string();
+        let commit = reference.peel_to_commit()?;
+        checkpoints.push((
+            name[prefix.len()..].to_string(),
+            commit.id,
+            commit.time()?.seconds,
+        ));
+    }
+    checkpoints.sort_by_key(|(_, _, time)| *time);
+    Ok(checkpoints)
+}
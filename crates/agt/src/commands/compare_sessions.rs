@@ -0,0 +1,250 @@
+use super::list_sessions::print_table;
+use crate::config::AgtConfig;
+use crate::gix_cli::run_git;
+use crate::session_store::{SessionMetadata, SessionStore};
+use crate::shadow_history;
+use anyhow::{bail, Context, Result};
+use gix::{ObjectId, Repository};
+
+/// Lines added and removed in one file; `None` for binary files.
+struct FileStat {
+    path: String,
+    added: Option<usize>,
+    removed: Option<usize>,
+}
+
+/// What a session produced, compared to the common starting commit.
+struct Outcome {
+    metadata: SessionMetadata,
+    /// The sandbox tree at the newest commit of the shadow branch.
+    tree: ObjectId,
+    /// The user branch diff against the common starting commit.
+    files: Vec<FileStat>,
+    shadow_commits: usize,
+    /// Seconds from creation to the newest commit, if any.
+    elapsed: Option<i64>,
+}
+
+/// Compares what `session_ids` produced: each user branch against the commit
+/// they started from, then each pair of sandbox trees against each other.
+pub fn run(
+    repo: &Repository,
+    config: &AgtConfig,
+    session_ids: &[String],
+    patch: bool,
+) -> Result<()> {
+    let store = SessionStore::open(repo);
+    let sessions = session_ids
+        .iter()
+        .map(|id| {
+            store
+                .load(id)
+                .with_context(|| format!("Session not found: {id}"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let base = common_base(repo, config, &sessions)?;
+    let mut outcomes = Vec::new();
+    for metadata in sessions {
+        outcomes.push(outcome(repo, config, &base, metadata)?);
+    }
+
+    println!(
+        "Comparing {} sessions from {}",
+        outcomes.len(),
+        &base[..base.len().min(12)]
+    );
+    println!();
+    let mut rows = vec![[
+        "SESSION",
+        "USER BRANCH",
+        "FILES",
+        "ADDED",
+        "REMOVED",
+        "SHADOW COMMITS",
+        "ELAPSED",
+    ]
+    .map(str::to_string)];
+    for outcome in &outcomes {
+        let (added, removed) = totals(&outcome.files);
+        rows.push([
+            outcome.metadata.session_id.clone(),
+            user_branch(&outcome.metadata).to_string(),
+            outcome.files.len().to_string(),
+            format!("+{added}"),
+            format!("-{removed}"),
+            outcome.shadow_commits.to_string(),
+            outcome
+                .elapsed
+                .map_or_else(|| "-".to_string(), shadow_history::format_duration),
+        ]);
+    }
+    print_table(&rows);
+
+    for outcome in &outcomes {
+        let (session_id, branch) = (&outcome.metadata.session_id, user_branch(&outcome.metadata));
+        println!();
+        if outcome.files.is_empty() {
+            println!("{session_id}: {branch} changed no files");
+            continue;
+        }
+        println!("Files changed on {branch} ({session_id}):");
+        print_files(&outcome.files);
+    }
+
+    for (index, left) in outcomes.iter().enumerate() {
+        for right in &outcomes[index + 1..] {
+            let (left_id, right_id) = (&left.metadata.session_id, &right.metadata.session_id);
+            let (left_tree, right_tree) = (left.tree.to_string(), right.tree.to_string());
+            let files = numstat(repo, config, &left_tree, &right_tree)?;
+            println!();
+            if files.is_empty() {
+                println!("{left_id} vs {right_id}: identical sandbox trees");
+                continue;
+            }
+            let (added, removed) = totals(&files);
+            println!(
+                "{left_id} vs {right_id}: {} files differ, +{added} -{removed}",
+                files.len()
+            );
+            print_files(&files);
+            if patch {
+                println!();
+                print!(
+                    "{}",
+                    run_git(
+                        repo,
+                        config,
+                        &[
+                            "diff",
+                            "--no-color",
+                            "--no-renames",
+                            &left_tree,
+                            &right_tree
+                        ]
+                    )?
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The starting commit shared by `sessions`, or the merge base of their
+/// starting commits if they differ.
+fn common_base(
+    repo: &Repository,
+    config: &AgtConfig,
+    sessions: &[SessionMetadata],
+) -> Result<String> {
+    let Some(first) = sessions.first() else {
+        bail!("No sessions to compare");
+    };
+    if sessions
+        .iter()
+        .all(|session| session.from_commit == first.from_commit)
+    {
+        return Ok(first.from_commit.clone());
+    }
+
+    let mut args = vec!["merge-base", "--octopus"];
+    args.extend(sessions.iter().map(|session| session.from_commit.as_str()));
+    let base = run_git(repo, config, &args)
+        .context("The sessions share no history to compare against")?
+        .trim()
+        .to_string();
+    eprintln!(
+        "Warning: the sessions started from different commits; comparing against their merge base {base}"
+    );
+    Ok(base)
+}
+
+fn outcome(
+    repo: &Repository,
+    config: &AgtConfig,
+    base: &str,
+    metadata: SessionMetadata,
+) -> Result<Outcome> {
+    let shadow_branch_ref = format!("refs/heads/{}", metadata.branch);
+    let commits =
+        shadow_history::commits_since(repo, config, &shadow_branch_ref, &metadata.from_commit)?;
+    let mut shadow_commits = 0;
+    for commit in &commits {
+        if shadow_history::is_shadow_commit(commit)? {
+            shadow_commits += 1;
+        }
+    }
+
+    let tip = repo
+        .find_reference(shadow_branch_ref.as_str())
+        .with_context(|| format!("Shadow branch not found: {}", metadata.branch))?
+        .peel_to_commit()?;
+    let tree = shadow_history::sandbox_tree(&tip)?;
+
+    let elapsed = match commits.last() {
+        Some(newest) => Some(newest.time()?.seconds - i64::try_from(metadata.created_at)?),
+        None => None,
+    };
+    let files = numstat(repo, config, base, &metadata.user_branch).with_context(|| {
+        format!(
+            "Failed to diff user branch {} of session {}",
+            user_branch(&metadata),
+            metadata.session_id
+        )
+    })?;
+    Ok(Outcome {
+        files,
+        tree: tree.id,
+        shadow_commits,
+        elapsed,
+        metadata,
+    })
+}
+
+fn numstat(repo: &Repository, config: &AgtConfig, from: &str, to: &str) -> Result<Vec<FileStat>> {
+    let output = run_git(
+        repo,
+        config,
+        &["diff", "--numstat", "--no-renames", from, to],
+    )?;
+    Ok(output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let added = fields.next()?.parse().ok();
+            let removed = fields.next()?.parse().ok();
+            Some(FileStat {
+                path: fields.next()?.to_string(),
+                added,
+                removed,
+            })
+        })
+        .collect())
+}
+
+fn user_branch(metadata: &SessionMetadata) -> &str {
+    metadata
+        .user_branch
+        .strip_prefix("refs/heads/")
+        .unwrap_or(&metadata.user_branch)
+}
+
+fn totals(files: &[FileStat]) -> (usize, usize) {
+    files.iter().fold((0, 0), |(added, removed), file| {
+        (
+            added + file.added.unwrap_or_default(),
+            removed + file.removed.unwrap_or_default(),
+        )
+    })
+}
+
+fn print_files(files: &[FileStat]) {
+    for file in files {
+        match (file.added, file.removed) {
+            (Some(added), Some(removed)) => {
+                println!("  {:<12}{}", format!("+{added} -{removed}"), file.path);
+            }
+            _ => println!("  {:<12}{}", "binary", file.path),
+        }
+    }
+}
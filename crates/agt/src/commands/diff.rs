@@ -0,0 +1,229 @@
+use crate::cli::CrossDiffArgs;
+use crate::config::AgtConfig;
+use crate::shadow_history;
+use crate::snapshot::{self, FileEntries, SnapshotView};
+use anyhow::{Context, Result};
+use clap::Parser;
+use gix::Repository;
+use imara_diff::intern::InternedInput;
+use imara_diff::{Algorithm, UnifiedDiffBuilder};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// One side of an `agt diff`.
+enum DiffSpec {
+    Snapshot(String),
+    Git(String),
+    Session(String),
+    /// A directory, or `None` for the default: the work dir of the
+    /// repository when the other side is a Git tree, else the current
+    /// directory.
+    Filesystem(Option<PathBuf>),
+}
+
+impl DiffSpec {
+    fn is_tree(&self) -> bool {
+        matches!(self, Self::Git(_) | Self::Session(_))
+    }
+
+    fn parse(spec: &str) -> Option<Self> {
+        if spec == "fs" {
+            return Some(Self::Filesystem(None));
+        }
+        let (kind, value) = spec.split_once(':')?;
+        match kind {
+            "snapshot" => Some(Self::Snapshot(value.to_string())),
+            "git" => Some(Self::Git(value.to_string())),
+            "session" => Some(Self::Session(value.to_string())),
+            "fs" if value.is_empty() => Some(Self::Filesystem(None)),
+            "fs" => Some(Self::Filesystem(Some(PathBuf::from(value)))),
+            _ => None,
+        }
+    }
+}
+
+enum Source {
+    Snapshot(SnapshotView),
+    Tree(Repository),
+    Filesystem(PathBuf),
+}
+
+struct Side {
+    source: Source,
+    entries: FileEntries,
+}
+
+/// Returns the cross-namespace diff arguments, or `None` when `args` are
+/// meant for `git diff`, including when they only look like specs, as in
+/// `agt diff --cached -- fs`.
+pub fn parse(args: &[String]) -> Option<CrossDiffArgs> {
+    let specs = args
+        .iter()
+        .filter(|arg| DiffSpec::parse(arg).is_some())
+        .count();
+    if specs < 2 {
+        return None;
+    }
+    CrossDiffArgs::try_parse_from(
+        std::iter::once("agt diff".to_string()).chain(args.iter().cloned()),
+    )
+    .ok()
+    .filter(|args| {
+        DiffSpec::parse(&args.spec_a).is_some() && DiffSpec::parse(&args.spec_b).is_some()
+    })
+}
+
+pub fn run(args: &CrossDiffArgs, config: &AgtConfig) -> Result<()> {
+    // Git trees are rooted at the work dir, so the filesystem side is too.
+    let tree_sides = [&args.spec_a, &args.spec_b]
+        .into_iter()
+        .any(|spec| DiffSpec::parse(spec).is_some_and(|spec| spec.is_tree()));
+    let before = load_side(&args.spec_a, args, config, tree_sides)?;
+    let after = load_side(&args.spec_b, args, config, tree_sides)?;
+    let diff = snapshot::diff_entries(&before.entries, &after.entries);
+    snapshot::emit_diff(&diff);
+
+    if args.patch {
+        for path in diff.added.iter().chain(&diff.deleted).chain(&diff.modified) {
+            let old = before.read(path)?;
+            let new = after.read(path)?;
+            print_patch(path, old.as_deref(), new.as_deref());
+        }
+    }
+    Ok(())
+}
+
+fn load_side(
+    spec: &str,
+    args: &CrossDiffArgs,
+    config: &AgtConfig,
+    tree_sides: bool,
+) -> Result<Side> {
+    let parsed = DiffSpec::parse(spec).with_context(|| {
+        format!("Invalid diff spec {spec}; expected snapshot:<tag>, git:<rev>, session:<id> or fs[:<path>]")
+    })?;
+    match parsed {
+        DiffSpec::Snapshot(tag) => {
+            let view =
+                SnapshotView::open(&tag, Path::new("."), args.store.as_deref(), args.shared)?;
+            let entries = view.entries()?;
+            Ok(Side {
+                source: Source::Snapshot(view),
+                entries,
+            })
+        }
+        DiffSpec::Git(rev) => {
+            let repo = gix::discover(".").context("Failed to discover Git repository")?;
+            tree_side(repo, &rev, false)
+                .with_context(|| format!("Failed to read git revision {rev}"))
+        }
+        DiffSpec::Session(session) => {
+            let repo = gix::discover(".").context("Failed to discover Git repository")?;
+            let split = session.find(['~', '^']).unwrap_or(session.len());
+            let (session_id, suffix) = session.split_at(split);
+            let rev = format!("refs/heads/{}{session_id}{suffix}", config.branch_prefix);
+            tree_side(repo, &rev, true)
+                .with_context(|| format!("Failed to read shadow commit of session {session}"))
+        }
+        DiffSpec::Filesystem(path) => {
+            let path = match path {
+                Some(path) => path,
+                None if tree_sides => gix::discover(".")
+                    .context("Failed to discover Git repository")?
+                    .work_dir()
+                    .context("The repository has no work dir to compare with")?
+                    .to_path_buf(),
+                None => PathBuf::from("."),
+            };
+            let root = path
+                .canonicalize()
+                .with_context(|| format!("Directory not found: {}", path.display()))?;
+            let entries = snapshot::filesystem_entries(&root)?;
+            Ok(Side {
+                source: Source::Filesystem(root),
+                entries,
+            })
+        }
+    }
+}
+
+/// Lists the files of `rev`, or of the sandbox tree of shadow branch commit
+/// `rev` if `sandbox` is set.
+fn tree_side(repo: Repository, rev: &str, sandbox: bool) -> Result<Side> {
+    let entries = {
+        let object = repo.rev_parse_single(rev)?.object()?;
+        let tree = if sandbox {
+            shadow_history::sandbox_tree(&object.peel_to_commit()?)?
+        } else {
+            object.peel_to_tree()?
+        };
+        tree_entries(&tree)?
+    };
+    Ok(Side {
+        source: Source::Tree(repo),
+        entries,
+    })
+}
+
+fn tree_entries(tree: &gix::Tree<'_>) -> Result<FileEntries> {
+    let mut recorder = gix::traverse::tree::Recorder::default();
+    tree.traverse().breadthfirst(&mut recorder)?;
+    Ok(recorder
+        .records
+        .into_iter()
+        .filter(|entry| !entry.mode.is_tree() && !entry.mode.is_commit())
+        .map(|entry| (entry.filepath.to_string(), (entry.mode.kind(), entry.oid)))
+        .collect())
+}
+
+impl Side {
+    fn read(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        let Some((_, id)) = self.entries.get(path) else {
+            return Ok(None);
+        };
+        let data = match &self.source {
+            Source::Snapshot(view) => view.read(path)?,
+            Source::Tree(repo) => repo.find_object(*id)?.data.clone(),
+            Source::Filesystem(root) => {
+                let disk_path = root.join(path);
+                if disk_path.is_symlink() {
+                    fs::read_link(&disk_path)?
+                        .as_os_str()
+                        .to_string_lossy()
+                        .into_owned()
+                        .into_bytes()
+                } else {
+                    fs::read(&disk_path)?
+                }
+            }
+        };
+        Ok(Some(data))
+    }
+}
+
+fn print_patch(path: &str, before: Option<&[u8]>, after: Option<&[u8]>) {
+    let old_name = before.map_or_else(|| "/dev/null".to_string(), |_| format!("a/{path}"));
+    let new_name = after.map_or_else(|| "/dev/null".to_string(), |_| format!("b/{path}"));
+    println!("diff --agt a/{path} b/{path}");
+
+    let old = before.unwrap_or_default();
+    let new = after.unwrap_or_default();
+    if old.contains(&0) || new.contains(&0) {
+        println!("Binary files {old_name} and {new_name} differ");
+        return;
+    }
+
+    println!("--- {old_name}");
+    println!("+++ {new_name}");
+    let old = String::from_utf8_lossy(old);
+    let new = String::from_utf8_lossy(new);
+    let input = InternedInput::new(old.as_ref(), new.as_ref());
+    print!(
+        "{}",
+        imara_diff::diff(
+            Algorithm::Histogram,
+            &input,
+            UnifiedDiffBuilder::new(&input)
+        )
+    );
+}
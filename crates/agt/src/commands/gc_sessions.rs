@@ -0,0 +1,322 @@
+use super::list_sessions::{self, SandboxState};
+use crate::config::AgtConfig;
+use crate::gix_cli::git_command;
+use crate::session_store::{SessionMetadata, SessionStore};
+use crate::shadow_history;
+use anyhow::{bail, Context, Result};
+use gix::Repository;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+enum Garbage {
+    /// Removed like `agt session remove --delete-branch`.
+    Session { id: String, reason: String },
+    /// A shadow branch without session metadata.
+    Branch { name: String },
+    /// A worktree admin dir whose worktree is gone.
+    Worktree { admin_dir: PathBuf, gitdir: String },
+    /// A timestamp file without session metadata, or an abandoned
+    /// metadata write.
+    File { path: PathBuf },
+}
+
+impl Garbage {
+    fn describe(&self) -> String {
+        match self {
+            Garbage::Session { id, reason } => format!("session {id}: {reason}"),
+            Garbage::Branch { name } => format!(
+                "shadow branch {} has no session metadata",
+                name.strip_prefix("refs/heads/").unwrap_or(name)
+            ),
+            Garbage::Worktree { admin_dir, gitdir } => format!(
+                "worktree admin dir {} points at missing {gitdir}",
+                admin_dir.display()
+            ),
+            Garbage::File { path } => format!("stray file {}", path.display()),
+        }
+    }
+}
+
+pub fn run(
+    repo: &Repository,
+    config: &AgtConfig,
+    apply: bool,
+    merged_into: Option<&str>,
+    older_than: Option<&str>,
+) -> Result<()> {
+    let older_than = older_than.map(shadow_history::parse_duration).transpose()?;
+    if let Some(target) = merged_into {
+        repo.rev_parse_single(target)
+            .with_context(|| format!("Branch not found: {target}"))?;
+    }
+
+    let store = SessionStore::open(repo);
+    let ids = store.ids()?;
+    let mut garbage = Vec::new();
+    for id in &ids {
+        let metadata = match store.load(id) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                eprintln!("Warning: {err:#}; left alone");
+                continue;
+            }
+        };
+        if let Some(reason) = session_reason(repo, config, &metadata, merged_into, older_than)? {
+            garbage.push(Garbage::Session {
+                id: id.clone(),
+                reason,
+            });
+        }
+    }
+    let known: HashSet<_> = ids.iter().map(String::as_str).collect();
+    find_orphan_branches(repo, config, &known, &mut garbage)?;
+    find_stale_worktrees(repo, &mut garbage)?;
+    find_stray_files(repo, &store, &known, &mut garbage)?;
+
+    if garbage.is_empty() {
+        println!("Nothing to collect");
+        return Ok(());
+    }
+    println!("Found {} items to collect:", garbage.len());
+    for item in &garbage {
+        println!("  {}", item.describe());
+    }
+    if !apply {
+        println!("Run agt session gc --apply to remove them");
+        return Ok(());
+    }
+
+    for item in &garbage {
+        match item {
+            Garbage::Session { id, .. } => super::prune_session::run(repo, id, true, config)?,
+            Garbage::Branch { name } => {
+                repo.find_reference(name.as_str())?.delete()?;
+                println!("Deleted shadow branch: {name}");
+            }
+            Garbage::Worktree { admin_dir, .. } => {
+                fs::remove_dir_all(admin_dir)?;
+                println!("Removed worktree admin dir: {}", admin_dir.display());
+            }
+            Garbage::File { path } => {
+                fs::remove_file(path)?;
+                println!("Removed {}", path.display());
+            }
+        }
+    }
+    println!("Removed {} items", garbage.len());
+    Ok(())
+}
+
+/// Why the session should be removed, if it should.
+fn session_reason(
+    repo: &Repository,
+    config: &AgtConfig,
+    metadata: &SessionMetadata,
+    merged_into: Option<&str>,
+    older_than: Option<i64>,
+) -> Result<Option<String>> {
+    if metadata.archived_at.is_none() && !Path::new(&metadata.sandbox).exists() {
+        return Ok(Some(format!("sandbox {} is missing", metadata.sandbox)));
+    }
+
+    let user_branch = metadata
+        .user_branch
+        .strip_prefix("refs/heads/")
+        .unwrap_or(&metadata.user_branch);
+    let mut reason = None;
+    if let Some(target) = merged_into {
+        let target_branch = target.strip_prefix("refs/heads/").unwrap_or(target);
+        // A user branch still at the session's start commit has nothing merged.
+        if user_branch != target_branch
+            && has_commits_since(repo, config, &metadata.user_branch, &metadata.from_commit)?
+            && is_ancestor(repo, config, &metadata.user_branch, target)?
+        {
+            reason = Some(format!(
+                "user branch {user_branch} is merged into {target_branch}"
+            ));
+        }
+    }
+
+    if let (None, Some(older_than)) = (&reason, older_than) {
+        let shadow_branch_ref = format!("refs/heads/{}", metadata.branch);
+        let last_autocommit = shadow_history::first_parent_chain(
+            repo,
+            config,
+            &shadow_branch_ref,
+            &metadata.from_commit,
+        )
+        .ok()
+        .and_then(|chain| chain.first().map(|commit| commit.time))
+        .unwrap_or_default();
+        let last_active = last_autocommit.max(i64::try_from(metadata.created_at)?);
+        let now = gix::date::Time::now_utc().seconds;
+        if now - last_active >= older_than {
+            reason = Some(format!(
+                "no activity since {}",
+                shadow_history::format_short_time(last_active)
+            ));
+        }
+    }
+
+    if let Some(reason) = &reason {
+        if let Some(unsaved) = unsaved_work(repo, config, metadata) {
+            eprintln!(
+                "Warning: session {}: {reason}, but {unsaved}; left alone",
+                metadata.session_id
+            );
+            return Ok(None);
+        }
+    }
+    Ok(reason)
+}
+
+/// Describes changes in the session folder that removing it would lose.
+fn unsaved_work(
+    repo: &Repository,
+    config: &AgtConfig,
+    metadata: &SessionMetadata,
+) -> Option<String> {
+    match list_sessions::sandbox_state(config, metadata) {
+        SandboxState::Dirty => return Some("its sandbox has uncommitted changes".to_string()),
+        SandboxState::Clean => {}
+        SandboxState::Missing | SandboxState::Archived => return None,
+    }
+    let sandbox = Path::new(&metadata.sandbox);
+    let shadow_branch_ref = format!("refs/heads/{}", metadata.branch);
+    let pending = super::autocommit::pending_changes(
+        repo,
+        &metadata.session_id,
+        &shadow_branch_ref,
+        sandbox.parent().unwrap_or(sandbox),
+    )
+    .and_then(|mut pending| {
+        // Before the first autocommit the shadow branch holds a plain commit
+        // of the sandbox, which `git status` has already compared.
+        let tip = repo
+            .find_reference(shadow_branch_ref.as_str())?
+            .peel_to_commit()?;
+        if !shadow_history::is_shadow_commit(&tip)? {
+            pending.changed.retain(|path| !path.starts_with("sandbox"));
+            pending.deleted.clear();
+        }
+        Ok(pending)
+    });
+    match pending {
+        Ok(pending) if pending.changed.is_empty() && pending.deleted.is_empty() => None,
+        Ok(pending) => Some(format!(
+            "{} changes are not autocommitted",
+            pending.changed.len() + pending.deleted.len()
+        )),
+        Err(err) => Some(format!("its pending changes could not be checked: {err:#}")),
+    }
+}
+
+/// Whether `branch` has commits that `from_commit` does not.
+fn has_commits_since(
+    repo: &Repository,
+    config: &AgtConfig,
+    branch: &str,
+    from_commit: &str,
+) -> Result<bool> {
+    let output = git_command(repo, config)
+        .args(["rev-list", "--count", branch])
+        .arg(format!("^{from_commit}"))
+        .output()
+        .context("Failed to run git rev-list")?;
+    // The user branch no longer exists.
+    if !output.status.success() {
+        return Ok(false);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim() != "0")
+}
+
+fn is_ancestor(repo: &Repository, config: &AgtConfig, commit: &str, target: &str) -> Result<bool> {
+    let status = git_command(repo, config)
+        .args(["merge-base", "--is-ancestor", commit, target])
+        .status()
+        .context("Failed to run git merge-base")?;
+    match status.code() {
+        Some(0) => Ok(true),
+        Some(1) => Ok(false),
+        // The user branch no longer exists.
+        Some(128) => Ok(false),
+        _ => bail!("git merge-base failed for {commit} and {target}"),
+    }
+}
+
+fn find_orphan_branches(
+    repo: &Repository,
+    config: &AgtConfig,
+    known: &HashSet<&str>,
+    garbage: &mut Vec<Garbage>,
+) -> Result<()> {
+    let prefix = format!("refs/heads/{}", config.branch_prefix);
+    for reference in repo.references()?.prefixed(prefix.as_str())? {
+        let reference = reference.map_err(|err| anyhow::anyhow!(err))?;
+        let name = reference.name().as_bstr().to_string();
+        if let Some(session_id) = name.strip_prefix(&prefix) {
+            if !known.contains(session_id) {
+                garbage.push(Garbage::Branch { name });
+            }
+        }
+    }
+    Ok(())
+}
+
+fn find_stale_worktrees(repo: &Repository, garbage: &mut Vec<Garbage>) -> Result<()> {
+    let Ok(entries) = fs::read_dir(repo.common_dir().join("worktrees")) else {
+        return Ok(());
+    };
+    for entry in entries {
+        let admin_dir = entry?.path();
+        // Admin dirs without a gitdir file were not made by agt-worktree.
+        let Ok(gitdir) = fs::read_to_string(admin_dir.join("gitdir")) else {
+            continue;
+        };
+        // Like `git worktree prune`, leave locked worktrees alone.
+        if admin_dir.join("locked").exists() {
+            continue;
+        }
+        let gitdir = gitdir.trim().to_string();
+        // Worktrees made with `worktree.useRelativePaths` record a path
+        // relative to the admin dir.
+        if !admin_dir.join(&gitdir).exists() {
+            garbage.push(Garbage::Worktree { admin_dir, gitdir });
+        }
+    }
+    Ok(())
+}
+
+fn find_stray_files(
+    repo: &Repository,
+    store: &SessionStore,
+    known: &HashSet<&str>,
+    garbage: &mut Vec<Garbage>,
+) -> Result<()> {
+    if let Ok(entries) = fs::read_dir(repo.common_dir().join("agt/timestamps")) {
+        for entry in entries {
+            let path = entry?.path();
+            let session_id = path.file_name().and_then(OsStr::to_str).unwrap_or_default();
+            if !known.contains(session_id) {
+                garbage.push(Garbage::File { path });
+            }
+        }
+    }
+
+    if let Ok(entries) = fs::read_dir(store.dir()) {
+        for entry in entries {
+            let path = entry?.path();
+            let name = path.file_name().and_then(OsStr::to_str).unwrap_or_default();
+            // Lock files are never removed; see `SessionStore::remove`.
+            let stray = name
+                .strip_suffix(".json.tmp")
+                .is_some_and(|session_id| !store.is_locked(session_id));
+            if stray {
+                garbage.push(Garbage::File { path });
+            }
+        }
+    }
+    Ok(())
+}
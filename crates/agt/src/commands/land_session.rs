@@ -0,0 +1,316 @@
+use crate::cli::LandMode;
+use crate::config::AgtConfig;
+use crate::filter;
+use crate::path_util;
+use crate::session_store::SessionStore;
+use crate::shadow_history;
+use anyhow::{bail, Context, Result};
+use gix::{ObjectId, Repository};
+use gix_ref::transaction::PreviousValue;
+use std::path::Path;
+use std::process::{Command as StdCommand, Output};
+
+/// A commit made in the sandbox, as opposed to a shadow commit.
+struct SessionCommit {
+    commit: gix_object::Commit,
+    agent: bool,
+}
+
+/// Lands `session_id` into a branch checked out in the main worktree. The
+/// session's sandbox commits are rebuilt on its starting commit without the
+/// shadow commits between them, then merged, squashed or replayed. On
+/// conflicts the main worktree is left as it was.
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+    repo: &Repository,
+    config: &AgtConfig,
+    session_id: &str,
+    mode: LandMode,
+    into: Option<&str>,
+    strip_agent_commits: bool,
+    message: Option<&str>,
+    remove: bool,
+) -> Result<()> {
+    let main_worktree = main_worktree(repo)?;
+    {
+        let store = SessionStore::open(repo);
+        let _lock = store.lock(session_id)?;
+        let metadata = store
+            .load(session_id)
+            .with_context(|| format!("Session not found: {session_id}"))?;
+        if metadata.archived_at.is_some() {
+            bail!("Session {session_id} is archived; rehydrate it before landing");
+        }
+        let sandbox = path_util::canonicalize_or_original(Path::new(&metadata.sandbox));
+        if !sandbox.exists() {
+            bail!("Sandbox not found: {}", sandbox.display());
+        }
+
+        let target = into
+            .unwrap_or(&metadata.user_branch)
+            .trim_start_matches("refs/heads/");
+        let head = repo.head()?;
+        let current = head
+            .referent_name()
+            .map(|name| name.shorten().to_string())
+            .context("The main worktree has a detached HEAD; check out a branch to land into")?;
+        if current != target {
+            bail!(
+                "The main worktree is on {current}; check out {target} there or pass --into {current}"
+            );
+        }
+        let status = git(
+            config,
+            &main_worktree,
+            &["status", "--porcelain", "--untracked-files=no"],
+        )?;
+        if !status.stdout.is_empty() {
+            bail!("The main worktree has uncommitted changes; commit or stash them before landing");
+        }
+
+        let shadow_branch_ref = format!("refs/heads/{}", metadata.branch);
+        let commits = session_commits(repo, config, &shadow_branch_ref, &metadata.from_commit)?;
+        let Some(last) = commits.last() else {
+            bail!(
+                "Session {session_id} has no commits to land; commit its work in the sandbox first"
+            );
+        };
+        ensure_sandbox_committed(config, &sandbox, last.commit.tree)?;
+
+        let from_commit = ObjectId::from_hex(metadata.from_commit.as_bytes())?;
+        let tip = rebuild_history(
+            repo,
+            config,
+            &main_worktree,
+            session_id,
+            from_commit,
+            &commits,
+            strip_agent_commits,
+        )?;
+        let land_ref = format!("refs/agt/land/{session_id}");
+        repo.reference(
+            land_ref.as_str(),
+            tip,
+            PreviousValue::Any,
+            "agt session land",
+        )?;
+        println!(
+            "Landing {} sandbox commits of session {session_id} into {target}",
+            commits.len()
+        );
+        let landed = integrate(
+            config,
+            &main_worktree,
+            mode,
+            &land_ref,
+            &from_commit.to_string(),
+            message,
+            session_id,
+            target,
+        );
+        repo.find_reference(land_ref.as_str())?.delete()?;
+        landed?;
+
+        let head = git(config, &main_worktree, &["rev-parse", "HEAD"])?;
+        println!(
+            "Landed session {session_id} into {target} at {}",
+            String::from_utf8_lossy(&head.stdout).trim()
+        );
+    }
+
+    if remove {
+        super::prune_session::run(repo, session_id, true, config)
+    } else {
+        super::session::archive_session(repo, config, session_id)
+    }
+}
+
+fn main_worktree(repo: &Repository) -> Result<std::path::PathBuf> {
+    let work_dir = path_util::canonicalize(
+        repo.work_dir()
+            .context("Run agt session land from the main worktree")?,
+    )?;
+    for session in SessionStore::open(repo).list()? {
+        if path_util::canonicalize_or_original(Path::new(&session.sandbox)) == work_dir {
+            bail!("Run agt session land from the main worktree, not a sandbox");
+        }
+    }
+    Ok(work_dir)
+}
+
+/// The sandbox commits on the shadow branch since `from_commit`, oldest first.
+fn session_commits(
+    repo: &Repository,
+    config: &AgtConfig,
+    shadow_branch_ref: &str,
+    from_commit: &str,
+) -> Result<Vec<SessionCommit>> {
+    let mut commits = Vec::new();
+    for commit in shadow_history::commits_since(repo, config, shadow_branch_ref, from_commit)? {
+        if shadow_history::is_shadow_commit(&commit)? {
+            continue;
+        }
+        commits.push(SessionCommit {
+            agent: filter::should_hide_commit(&commit, config),
+            commit: commit.decode()?.into(),
+        });
+    }
+    Ok(commits)
+}
+
+fn ensure_sandbox_committed(config: &AgtConfig, sandbox: &Path, tree: ObjectId) -> Result<()> {
+    let diff = StdCommand::new(&config.git_path)
+        .current_dir(sandbox)
+        .args(["diff", "--quiet"])
+        .arg(tree.to_string())
+        .status()
+        .context("Failed to run git diff")?;
+    let untracked = git(
+        config,
+        sandbox,
+        &["ls-files", "--others", "--exclude-standard"],
+    )?;
+    if !diff.success() || !untracked.stdout.is_empty() {
+        bail!("Sandbox has uncommitted changes; commit them in the sandbox before landing");
+    }
+    Ok(())
+}
+
+/// Writes `commits` as a linear history on `from_commit` and returns its tip.
+/// Agent commits are dropped when stripping; their changes reach the next
+/// commit kept, or a final commit by the user if none follows.
+fn rebuild_history(
+    repo: &Repository,
+    config: &AgtConfig,
+    main_worktree: &Path,
+    session_id: &str,
+    from_commit: ObjectId,
+    commits: &[SessionCommit],
+    strip_agent_commits: bool,
+) -> Result<ObjectId> {
+    let mut parent = from_commit;
+    let mut folded = None;
+    for SessionCommit { commit, agent } in commits {
+        if strip_agent_commits && *agent {
+            folded = Some(commit.tree);
+            continue;
+        }
+        folded = None;
+        let mut commit = commit.clone();
+        commit.parents = [parent].into_iter().collect();
+        parent = repo.write_object(&commit)?.detach();
+    }
+
+    if let Some(tree) = folded {
+        let output = git(
+            config,
+            main_worktree,
+            &[
+                "commit-tree",
+                &tree.to_string(),
+                "-p",
+                &parent.to_string(),
+                "-m",
+                &format!("Agent changes from agt session {session_id}"),
+            ],
+        )?;
+        parent = ObjectId::from_hex(String::from_utf8_lossy(&output.stdout).trim().as_bytes())?;
+    }
+    Ok(parent)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn integrate(
+    config: &AgtConfig,
+    main_worktree: &Path,
+    mode: LandMode,
+    land_ref: &str,
+    from_commit: &str,
+    message: Option<&str>,
+    session_id: &str,
+    target: &str,
+) -> Result<()> {
+    let (args, abort): (Vec<String>, &[&str]) = match mode {
+        LandMode::Merge => (
+            vec![
+                "merge".into(),
+                "--no-ff".into(),
+                "-m".into(),
+                message.map_or_else(|| format!("Merge agt session {session_id}"), str::to_string),
+                land_ref.into(),
+            ],
+            &["merge", "--abort"],
+        ),
+        LandMode::Squash => (
+            vec!["merge".into(), "--squash".into(), land_ref.into()],
+            &["reset", "--merge"],
+        ),
+        LandMode::Rebase => (
+            vec!["cherry-pick".into(), format!("{from_commit}..{land_ref}")],
+            &["cherry-pick", "--abort"],
+        ),
+    };
+
+    let output = StdCommand::new(&config.git_path)
+        .current_dir(main_worktree)
+        .args(&args)
+        .output()
+        .with_context(|| format!("Failed to run git {}", args[0]))?;
+    if !output.status.success() {
+        let conflicts = git(
+            config,
+            main_worktree,
+            &["diff", "--name-only", "--diff-filter=U"],
+        )?;
+        let conflicts = String::from_utf8_lossy(&conflicts.stdout)
+            .lines()
+            .collect::<Vec<_>>()
+            .join(", ");
+        let _ = git(config, main_worktree, abort);
+        if conflicts.is_empty() {
+            bail!(
+                "git {} failed: {}; {target} was left unchanged",
+                args[0],
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        bail!("Landing session {session_id} into {target} conflicts in: {conflicts}; {target} was left unchanged");
+    }
+
+    if mode == LandMode::Squash {
+        let staged = StdCommand::new(&config.git_path)
+            .current_dir(main_worktree)
+            .args(["diff", "--cached", "--quiet"])
+            .status()
+            .context("Failed to run git diff")?;
+        if staged.success() {
+            bail!("{target} already contains the work of session {session_id}");
+        }
+        git(
+            config,
+            main_worktree,
+            &[
+                "commit",
+                "-m",
+                &message.map_or_else(|| format!("Land agt session {session_id}"), str::to_string),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+fn git(config: &AgtConfig, dir: &Path, args: &[&str]) -> Result<Output> {
+    let output = StdCommand::new(&config.git_path)
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .with_context(|| format!("Failed to run git {}", args[0]))?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output)
+}
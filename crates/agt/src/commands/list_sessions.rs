@@ -1,52 +1,373 @@
+use crate::cli::SessionSort;
 use crate::config::AgtConfig;
-use anyhow::Result;
+use crate::gix_cli::run_git;
+use crate::scanner;
+use crate::session_store::{SessionMetadata, SessionStore};
+use crate::shadow_history;
+use anyhow::{bail, Context, Result};
 use gix::Repository;
-use serde::Deserialize;
-use std::fs;
+use std::collections::HashSet;
+use std::path::Path;
+use std::process::Command as StdCommand;
 
-#[derive(Debug, Deserialize)]
-struct SessionMetadata {
-    session_id: String,
-    branch: String,
-    sandbox: String,
-}
+const FILTER_KEYS: [&str; 7] = [
+    "id",
+    "profile",
+    "user-branch",
+    "sandbox",
+    "ticket",
+    "title",
+    "label",
+];
+/// Prefix of filter keys naming a free-form field.
+const META_FILTER_PREFIX: &str = "meta.";
 
-pub fn run(repo: &Repository, _config: &AgtConfig) -> Result<()> {
-    let sessions_dir = repo.common_dir().join("agt/sessions");
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SandboxState {
+    Clean,
+    Dirty,
+    Missing,
+    Archived,
+}
 
-    if !sessions_dir.exists() {
-        println!("No agent sessions found");
-        return Ok(());
+impl SandboxState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SandboxState::Clean => "clean",
+            SandboxState::Dirty => "dirty",
+            SandboxState::Missing => "missing",
+            SandboxState::Archived => "archived",
+        }
     }
+}
 
-    let mut sessions = Vec::new();
+/// What `agt session list` shows about a session. Parts that could not be
+/// determined are `None`.
+pub struct SessionSummary {
+    pub metadata: SessionMetadata,
+    pub shadow_commits: Option<usize>,
+    /// Time of the newest shadow commit.
+    pub last_autocommit: Option<i64>,
+    pub pending: Option<usize>,
+    pub sandbox: SandboxState,
+    /// Commits in `from_commit` and not in the user branch, and the reverse.
+    pub ahead_behind: Option<(usize, usize)>,
+    pub disk_usage: u64,
+}
 
-    for entry in fs::read_dir(&sessions_dir)? {
-        let entry = entry?;
-        if entry.path().extension().and_then(|e| e.to_str()) != Some("json") {
-            continue;
+impl SessionSummary {
+    pub fn collect(repo: &Repository, config: &AgtConfig, metadata: SessionMetadata) -> Self {
+        let shadow_branch_ref = format!("refs/heads/{}", metadata.branch);
+        let chain = shadow_history::first_parent_chain(
+            repo,
+            config,
+            &shadow_branch_ref,
+            &metadata.from_commit,
+        )
+        .ok();
+        let sandbox_path = Path::new(&metadata.sandbox);
+        let session_folder = sandbox_path.parent().unwrap_or(sandbox_path);
+        let sandbox = sandbox_state(config, &metadata);
+        let pending = matches!(sandbox, SandboxState::Clean | SandboxState::Dirty)
+            .then(|| {
+                super::autocommit::pending_changes(
+                    repo,
+                    &metadata.session_id,
+                    &shadow_branch_ref,
+                    session_folder,
+                )
+                .ok()
+            })
+            .flatten()
+            .map(|pending| pending.changed.len() + pending.deleted.len());
+        Self {
+            shadow_commits: chain.as_ref().map(Vec::len),
+            last_autocommit: chain
+                .as_ref()
+                .and_then(|chain| chain.first())
+                .map(|commit| commit.time),
+            pending,
+            sandbox,
+            ahead_behind: ahead_behind(repo, config, &metadata.from_commit, &metadata.user_branch)
+                .ok(),
+            disk_usage: if sandbox_path.exists() {
+                scanner::disk_usage(session_folder).unwrap_or_default()
+            } else {
+                0
+            },
+            metadata,
         }
+    }
 
-        let raw = fs::read_to_string(entry.path())?;
-        let meta: SessionMetadata = match serde_json::from_str(&raw) {
-            Ok(meta) => meta,
-            Err(_) => continue,
+    fn matches(&self, key: &str, wanted: &str) -> bool {
+        let metadata = &self.metadata;
+        let actual = match key {
+            "id" => metadata.session_id.clone(),
+            "profile" => metadata.profile.clone().unwrap_or_default(),
+            "user-branch" => short_branch(&metadata.user_branch).to_string(),
+            "sandbox" => self.sandbox.as_str().to_string(),
+            "label" => {
+                return metadata
+                    .labels
+                    .iter()
+                    .any(|label| matches_filter(label, wanted));
+            }
+            _ => {
+                let key = key.strip_prefix(META_FILTER_PREFIX).unwrap_or(key);
+                metadata.field(key).unwrap_or_default()
+            }
         };
-
-        sessions.push(meta);
+        matches_filter(&actual, wanted)
     }
+}
+
+pub fn run(
+    repo: &Repository,
+    config: &AgtConfig,
+    filters: &[String],
+    sort: SessionSort,
+    reverse: bool,
+) -> Result<()> {
+    let filters = parse_filters(filters)?;
+    let sessions = SessionStore::open(repo).list()?;
+    warn_about_orphans(repo, config, &sessions)?;
 
-    if sessions.is_empty() {
+    let mut summaries: Vec<_> = sessions
+        .into_iter()
+        .map(|metadata| SessionSummary::collect(repo, config, metadata))
+        .filter(|summary| {
+            filters
+                .iter()
+                .all(|(key, value)| summary.matches(key, value))
+        })
+        .collect();
+
+    if summaries.is_empty() {
         println!("No agent sessions found");
         return Ok(());
     }
 
-    println!("Agent Sessions:");
-    for meta in sessions {
-        println!("  {}:", meta.session_id);
-        println!("    Branch: {}", meta.branch);
-        println!("    Sandbox: {}", meta.sandbox);
+    match sort {
+        SessionSort::Id => {}
+        SessionSort::Created => summaries.sort_by_key(|s| s.metadata.created_at),
+        SessionSort::LastAutocommit => summaries.sort_by_key(|s| s.last_autocommit),
+        SessionSort::Commits => summaries.sort_by_key(|s| s.shadow_commits),
+        SessionSort::Pending => summaries.sort_by_key(|s| s.pending),
+        SessionSort::Size => summaries.sort_by_key(|s| s.disk_usage),
+    }
+    if reverse {
+        summaries.reverse();
     }
 
+    let mut rows = vec![[
+        "ID",
+        "TICKET",
+        "CREATED",
+        "PROFILE",
+        "USER BRANCH",
+        "LAST AUTOCOMMIT",
+        "COMMITS",
+        "PENDING",
+        "SANDBOX",
+        "AHEAD/BEHIND",
+        "SIZE",
+    ]
+    .map(str::to_string)];
+    for summary in &summaries {
+        let metadata = &summary.metadata;
+        rows.push([
+            metadata.session_id.clone(),
+            metadata.ticket.clone().unwrap_or_else(|| "-".to_string()),
+            i64::try_from(metadata.created_at)
+                .map_or_else(|_| "-".to_string(), shadow_history::format_short_time),
+            metadata.profile.clone().unwrap_or_else(|| "-".to_string()),
+            short_branch(&metadata.user_branch).to_string(),
+            summary
+                .last_autocommit
+                .map_or_else(|| "never".to_string(), shadow_history::format_short_time),
+            optional(summary.shadow_commits),
+            optional(summary.pending),
+            summary.sandbox.as_str().to_string(),
+            summary.ahead_behind.map_or_else(
+                || "-".to_string(),
+                |(ahead, behind)| format!("+{ahead}/-{behind}"),
+            ),
+            human_size(summary.disk_usage),
+        ]);
+    }
+    print_table(&rows);
+
     Ok(())
 }
+
+/// Reports shadow branches without metadata and sessions whose shadow branch
+/// or sandbox is gone. Unreadable metadata is reported by the store.
+fn warn_about_orphans(
+    repo: &Repository,
+    config: &AgtConfig,
+    sessions: &[SessionMetadata],
+) -> Result<()> {
+    let prefix = format!("refs/heads/{}", config.branch_prefix);
+    let known: HashSet<_> = sessions.iter().map(|s| s.session_id.as_str()).collect();
+    for reference in repo.references()?.prefixed(prefix.as_str())? {
+        let reference = reference.map_err(|err| anyhow::anyhow!(err))?;
+        let name = reference.name().as_bstr().to_string();
+        if let Some(session_id) = name.strip_prefix(&prefix) {
+            if !known.contains(session_id) {
+                eprintln!(
+                    "Warning: shadow branch {} has no session metadata; run agt session recover",
+                    short_branch(&name)
+                );
+            }
+        }
+    }
+
+    for metadata in sessions {
+        let session_id = &metadata.session_id;
+        let shadow_branch_ref = format!("refs/heads/{}", metadata.branch);
+        if repo.find_reference(shadow_branch_ref.as_str()).is_err() {
+            eprintln!(
+                "Warning: session {session_id} has no shadow branch {}",
+                metadata.branch
+            );
+        }
+        if metadata.archived_at.is_none() && !Path::new(&metadata.sandbox).exists() {
+            eprintln!(
+                "Warning: sandbox of session {session_id} is missing; run agt session rehydrate --session-id {session_id} or agt session remove --session-id {session_id}"
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn sandbox_state(config: &AgtConfig, metadata: &SessionMetadata) -> SandboxState {
+    if metadata.archived_at.is_some() {
+        return SandboxState::Archived;
+    }
+    let sandbox = Path::new(&metadata.sandbox);
+    if !sandbox.exists() {
+        return SandboxState::Missing;
+    }
+    match StdCommand::new(&config.git_path)
+        .current_dir(sandbox)
+        .args(["status", "--porcelain"])
+        .output()
+    {
+        Ok(output) if output.status.success() && output.stdout.is_empty() => SandboxState::Clean,
+        _ => SandboxState::Dirty,
+    }
+}
+
+/// Counts commits only in `from` and only in `user_branch`.
+pub fn ahead_behind(
+    repo: &Repository,
+    config: &AgtConfig,
+    from: &str,
+    user_branch: &str,
+) -> Result<(usize, usize)> {
+    let stdout = run_git(
+        repo,
+        config,
+        &[
+            "rev-list",
+            "--left-right",
+            "--count",
+            &format!("{from}...{user_branch}"),
+        ],
+    )?;
+    let counts: Vec<usize> = stdout
+        .split_whitespace()
+        .filter_map(|count| count.parse().ok())
+        .collect();
+    match counts[..] {
+        [ahead, behind] => Ok((ahead, behind)),
+        _ => bail!("Unexpected git rev-list output: {}", stdout.trim()),
+    }
+}
+
+fn parse_filters(filters: &[String]) -> Result<Vec<(String, String)>> {
+    filters
+        .iter()
+        .map(|filter| {
+            let (key, value) = filter
+                .split_once('=')
+                .with_context(|| format!("Invalid filter {filter}; expected KEY=VALUE"))?;
+            let is_meta = key
+                .strip_prefix(META_FILTER_PREFIX)
+                .is_some_and(|name| !name.is_empty());
+            if !FILTER_KEYS.contains(&key) && !is_meta {
+                bail!(
+                    "Unknown filter key {key}; expected one of {}, or {META_FILTER_PREFIX}NAME",
+                    FILTER_KEYS.join(", ")
+                );
+            }
+            Ok((key.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+fn matches_filter(actual: &str, wanted: &str) -> bool {
+    match wanted.strip_suffix('*') {
+        Some(prefix) => actual.starts_with(prefix),
+        None => actual == wanted,
+    }
+}
+
+fn short_branch(name: &str) -> &str {
+    name.strip_prefix("refs/heads/").unwrap_or(name)
+}
+
+fn optional(value: Option<usize>) -> String {
+    value.map_or_else(|| "-".to_string(), |value| value.to_string())
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    if bytes < 1024 {
+        return format!("{bytes}B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1}{}", UNITS[unit])
+}
+
+pub fn print_table<const N: usize>(rows: &[[String; N]]) {
+    let mut widths = [0; N];
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for row in rows {
+        let line: Vec<_> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{human_size, matches_filter, parse_filters};
+
+    #[test]
+    fn formats_sizes_and_matches_filters() {
+        assert_eq!(human_size(512), "512B");
+        assert_eq!(human_size(1536), "1.5K");
+        assert_eq!(human_size(3 * 1024 * 1024), "3.0M");
+        assert!(matches_filter("agent-001", "agent-*"));
+        assert!(!matches_filter("agent-001", "agent"));
+        assert!(parse_filters(&["sandbox=dirty".to_string()]).is_ok());
+        assert!(parse_filters(&["color=red".to_string()]).is_err());
+        assert!(parse_filters(&["meta.color=red".to_string()]).is_ok());
+        assert!(parse_filters(&["meta.=red".to_string()]).is_err());
+        assert!(parse_filters(&["sandbox".to_string()]).is_err());
+    }
+}
@@ -1,9 +1,19 @@
 pub mod autocommit;
+pub mod bisect_session;
+pub mod check_sessions;
+pub mod checkpoint;
 pub mod clone;
+pub mod compare_sessions;
+pub mod diff;
+pub mod gc_sessions;
 pub mod git_porcelain;
+pub mod land_session;
 pub mod list_sessions;
 pub mod passthrough;
 pub mod prune_session;
+pub mod rename_session;
 pub mod session;
+pub mod session_meta;
+pub mod session_status;
 pub mod snapshot;
 pub mod status;
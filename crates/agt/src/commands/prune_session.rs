@@ -1,37 +1,25 @@
 use crate::config::AgtConfig;
 use crate::gix_cli::{find_worktree_binary, repo_base_path};
+use crate::session_store::SessionStore;
 use anyhow::{Context, Result};
 use gix::Repository;
-use serde::Deserialize;
 use std::fs;
 use std::path::PathBuf;
 use std::process::Command as StdCommand;
 
-#[derive(Debug, Deserialize)]
-#[allow(dead_code)]
-struct SessionMetadata {
-    session_id: String,
-    branch: String,
-    sandbox: String,
-}
-
 pub fn run(
     repo: &Repository,
     session_id: &str,
     delete_branch: bool,
     config: &AgtConfig,
 ) -> Result<()> {
-    // Try to read session metadata to get the exact sandbox path
-    let session_file = repo
-        .common_dir()
-        .join("agt/sessions")
-        .join(format!("{session_id}.json"));
+    let store = SessionStore::open(repo);
+    let _lock = store.lock(session_id)?;
 
-    let (metadata, sandbox_path) = if session_file.exists() {
-        let raw = fs::read_to_string(&session_file)?;
-        let meta: SessionMetadata = serde_json::from_str(&raw)?;
-        let sandbox = PathBuf::from(&meta.sandbox);
-        (meta, sandbox)
+    // Try to read session metadata to get the exact sandbox path
+    let (branch_name, sandbox_path) = if store.exists(session_id) {
+        let meta = store.load(session_id)?;
+        (meta.branch, PathBuf::from(&meta.sandbox))
     } else {
         // Fallback: assume new layout
         let sandbox = repo
@@ -40,17 +28,8 @@ pub fn run(
             .join("sessions")
             .join(session_id)
             .join("sandbox");
-        let branch = format!("{}{}", config.branch_prefix, session_id);
-        (
-            SessionMetadata {
-                session_id: session_id.to_string(),
-                branch,
-                sandbox: sandbox.display().to_string(),
-            },
-            sandbox,
-        )
+        (format!("{}{}", config.branch_prefix, session_id), sandbox)
     };
-    let branch_name = metadata.branch;
 
     if sandbox_path.exists() {
         let status = StdCommand::new(find_worktree_binary(&repo_base_path(repo))?)
@@ -99,6 +78,10 @@ pub fn run(
             branch_ref.delete()?;
             println!("Deleted shadow branch: {branch_name}");
         }
+        let prefix = super::checkpoint::ref_prefix(session_id);
+        for reference in repo.references()?.prefixed(prefix.as_str())? {
+            reference.map_err(|err| anyhow::anyhow!(err))?.delete()?;
+        }
     }
 
     // 3. Remove timestamp file
@@ -109,8 +92,7 @@ pub fn run(
     }
 
     // 4. Remove session metadata
-    if session_file.exists() {
-        fs::remove_file(&session_file)?;
+    if store.remove(session_id)? {
         println!("Removed session metadata");
     }
 
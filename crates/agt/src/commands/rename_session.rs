@@ -0,0 +1,360 @@
+use crate::config::AgtConfig;
+use crate::gix_cli::run_git;
+use crate::path_util;
+use crate::session_store::{SessionMetadata, SessionStore};
+use anyhow::{bail, Context, Result};
+use gix::{ObjectId, Repository};
+use gix_ref::transaction::PreviousValue;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A change made while renaming or moving a session, kept so it can be
+/// undone if a later step fails.
+enum Step {
+    /// A branch renamed with `git branch -m`, which moves its reflog and
+    /// updates worktrees that have it checked out.
+    Branch {
+        from: String,
+        to: String,
+    },
+    Ref {
+        from: String,
+        to: String,
+        target: ObjectId,
+    },
+    Path {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    File {
+        path: PathBuf,
+        previous: Vec<u8>,
+    },
+    Metadata {
+        previous: Box<SessionMetadata>,
+        id: String,
+    },
+}
+
+struct Journal<'a> {
+    repo: &'a Repository,
+    config: &'a AgtConfig,
+    steps: Vec<Step>,
+}
+
+impl<'a> Journal<'a> {
+    fn new(repo: &'a Repository, config: &'a AgtConfig) -> Self {
+        Self {
+            repo,
+            config,
+            steps: Vec::new(),
+        }
+    }
+
+    fn rename_branch(&mut self, from: &str, to: &str) -> Result<()> {
+        run_git(self.repo, self.config, &["branch", "-m", from, to])?;
+        self.steps.push(Step::Branch {
+            from: from.to_string(),
+            to: to.to_string(),
+        });
+        Ok(())
+    }
+
+    fn move_ref(&mut self, from: &str, to: &str) -> Result<()> {
+        let target = self
+            .repo
+            .find_reference(from)?
+            .peel_to_id_in_place()?
+            .detach();
+        self.repo.reference(
+            to,
+            target,
+            PreviousValue::MustNotExist,
+            "agt session rename",
+        )?;
+        self.repo.find_reference(from)?.delete()?;
+        self.steps.push(Step::Ref {
+            from: from.to_string(),
+            to: to.to_string(),
+            target,
+        });
+        Ok(())
+    }
+
+    fn move_path(&mut self, from: &Path, to: &Path) -> Result<()> {
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(from, to).map_err(|err| {
+            let context = format!("Failed to move {} to {}", from.display(), to.display());
+            if err.kind() == std::io::ErrorKind::CrossesDevices {
+                anyhow::anyhow!(
+                    "{context}: they are on different filesystems; pick a destination on the same filesystem"
+                )
+            } else {
+                anyhow::Error::from(err).context(context)
+            }
+        })?;
+        self.steps.push(Step::Path {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        });
+        Ok(())
+    }
+
+    fn write_file(&mut self, path: &Path, contents: &str) -> Result<()> {
+        let previous =
+            fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))?;
+        self.steps.push(Step::File {
+            path: path.to_path_buf(),
+            previous,
+        });
+        Ok(())
+    }
+
+    /// Saves `metadata`, removing `previous` if the ID changed.
+    fn replace_metadata(
+        &mut self,
+        store: &SessionStore,
+        previous: &SessionMetadata,
+        metadata: &SessionMetadata,
+    ) -> Result<()> {
+        store.save(metadata)?;
+        self.steps.push(Step::Metadata {
+            previous: Box::new(previous.clone()),
+            id: metadata.session_id.clone(),
+        });
+        if previous.session_id != metadata.session_id {
+            store.remove(&previous.session_id)?;
+        }
+        Ok(())
+    }
+
+    /// Undoes every step, newest first. Steps that cannot be undone are
+    /// reported and skipped.
+    fn rollback(self, store: &SessionStore) {
+        for step in self.steps.into_iter().rev() {
+            let undone = match &step {
+                Step::Branch { from, to } => {
+                    run_git(self.repo, self.config, &["branch", "-m", to, from]).map(drop)
+                }
+                Step::Ref { from, to, target } => self
+                    .repo
+                    .reference(
+                        from.as_str(),
+                        *target,
+                        PreviousValue::Any,
+                        "agt session rename: roll back",
+                    )
+                    .map_err(anyhow::Error::from)
+                    .and_then(|_| Ok(self.repo.find_reference(to.as_str())?.delete()?)),
+                Step::Path { from, to } => fs::rename(to, from).map_err(anyhow::Error::from),
+                Step::File { path, previous } => {
+                    fs::write(path, previous).map_err(anyhow::Error::from)
+                }
+                Step::Metadata { previous, id } => store.save(previous).and_then(|()| {
+                    if *id != previous.session_id {
+                        store.remove(id)?;
+                    }
+                    Ok(())
+                }),
+            };
+            if let Err(err) = undone {
+                eprintln!("Warning: failed to roll back: {err:#}");
+            }
+        }
+    }
+}
+
+/// Renames session `old` to `new`: its shadow branch, checkpoint refs,
+/// session folder, sandbox worktree admin dir, timestamp and metadata.
+pub fn rename(repo: &Repository, config: &AgtConfig, old: &str, new: &str) -> Result<()> {
+    if old == new {
+        bail!("The old and new IDs are the same: {old}");
+    }
+    let store = SessionStore::open(repo);
+    if !store.exists(old) {
+        bail!("Session not found: {old}");
+    }
+    let _locks = store.lock_pair(old, new)?;
+    let metadata = store
+        .load(old)
+        .with_context(|| format!("Session not found: {old}"))?;
+    if store.exists(new) {
+        bail!("Session {new} already exists");
+    }
+    let new_branch = format!("{}{new}", config.branch_prefix);
+    if repo
+        .find_reference(format!("refs/heads/{new_branch}").as_str())
+        .is_ok()
+    {
+        bail!("Branch {new_branch} already exists");
+    }
+    let worktrees = repo.common_dir().join("worktrees");
+    let (old_admin, new_admin) = (worktrees.join(old), worktrees.join(new));
+    if new_admin.exists() {
+        bail!("Worktree admin dir {} already exists", new_admin.display());
+    }
+
+    // The sandbox keeps its name; only the session folder is renamed, and
+    // only when it is named after the session.
+    let old_sandbox = path_util::canonicalize_or_original(Path::new(&metadata.sandbox));
+    let old_folder = old_sandbox.parent().map(Path::to_path_buf);
+    let new_folder = old_folder
+        .as_ref()
+        .filter(|folder| folder.file_name() == Some(old.as_ref()))
+        .and_then(|folder| folder.parent())
+        .map(|parent| parent.join(new));
+    if let Some(new_folder) = &new_folder {
+        if new_folder.exists() {
+            bail!("{} already exists", new_folder.display());
+        }
+    }
+
+    let mut journal = Journal::new(repo, config);
+    let renamed = (|| -> Result<SessionMetadata> {
+        journal.rename_branch(&metadata.branch, &new_branch)?;
+
+        let old_prefix = super::checkpoint::ref_prefix(old);
+        let new_prefix = super::checkpoint::ref_prefix(new);
+        let checkpoints: Vec<_> = repo
+            .references()?
+            .prefixed(old_prefix.as_str())?
+            .map(|reference| {
+                reference
+                    .map(|reference| reference.name().as_bstr().to_string())
+                    .map_err(|err| anyhow::anyhow!(err))
+            })
+            .collect::<Result<_>>()?;
+        for name in checkpoints {
+            let to = format!("{new_prefix}{}", &name[old_prefix.len()..]);
+            journal.move_ref(&name, &to)?;
+        }
+
+        let mut sandbox = old_sandbox.clone();
+        if let (Some(old_folder), Some(new_folder)) = (&old_folder, &new_folder) {
+            if old_folder.exists() {
+                journal.move_path(old_folder, new_folder)?;
+                sandbox = new_folder.join(old_sandbox.file_name().unwrap_or_default());
+            }
+        }
+        if old_admin.exists() {
+            journal.move_path(&old_admin, &new_admin)?;
+            relink_worktree(&mut journal, &new_admin, &sandbox)?;
+        }
+
+        let timestamps = repo.common_dir().join("agt/timestamps");
+        if timestamps.join(old).exists() {
+            journal.move_path(&timestamps.join(old), &timestamps.join(new))?;
+        }
+
+        let renamed = SessionMetadata {
+            session_id: new.to_string(),
+            branch: new_branch.clone(),
+            sandbox: sandbox.display().to_string(),
+            ..metadata.clone()
+        };
+        journal.replace_metadata(&store, &metadata, &renamed)?;
+        Ok(renamed)
+    })();
+
+    match renamed {
+        Ok(renamed) => {
+            println!("Renamed session {old} to {new}");
+            println!("  Shadow branch: {}", renamed.branch);
+            println!("  Sandbox: {}", renamed.sandbox);
+            Ok(())
+        }
+        Err(err) => {
+            journal.rollback(&store);
+            Err(err.context(format!(
+                "Failed to rename session {old} to {new}; changes were rolled back"
+            )))
+        }
+    }
+}
+
+/// Moves the session folder of `session_id` to `to`, keeping its ID.
+pub fn relocate(repo: &Repository, config: &AgtConfig, session_id: &str, to: &Path) -> Result<()> {
+    let store = SessionStore::open(repo);
+    let _lock = store.lock(session_id)?;
+    let metadata = store
+        .load(session_id)
+        .with_context(|| format!("Session not found: {session_id}"))?;
+    if metadata.archived_at.is_some() {
+        bail!("Session {session_id} is archived; rehydrate it before moving it");
+    }
+    let old_sandbox = path_util::canonicalize(Path::new(&metadata.sandbox))
+        .with_context(|| format!("Sandbox not found: {}", metadata.sandbox))?;
+    let old_folder = old_sandbox
+        .parent()
+        .context("Sandbox has no parent directory")?
+        .to_path_buf();
+    if to.exists() {
+        bail!("{} already exists", to.display());
+    }
+    let to = resolve_new_path(to)?;
+    if to.starts_with(&old_folder) {
+        bail!("Cannot move session {session_id} into its own folder");
+    }
+    let admin_dir = repo.common_dir().join("worktrees").join(session_id);
+
+    let mut journal = Journal::new(repo, config);
+    let moved = (|| -> Result<PathBuf> {
+        journal.move_path(&old_folder, &to)?;
+        let sandbox = to.join(old_sandbox.file_name().unwrap_or_default());
+        if admin_dir.exists() {
+            relink_worktree(&mut journal, &admin_dir, &sandbox)?;
+        }
+        journal.replace_metadata(
+            &store,
+            &metadata,
+            &SessionMetadata {
+                sandbox: sandbox.display().to_string(),
+                ..metadata.clone()
+            },
+        )?;
+        Ok(sandbox)
+    })();
+
+    match moved {
+        Ok(sandbox) => {
+            println!("Moved session {session_id} to {}", to.display());
+            println!("  Sandbox: {}", sandbox.display());
+            Ok(())
+        }
+        Err(err) => {
+            journal.rollback(&store);
+            Err(err.context(format!(
+                "Failed to move session {session_id}; changes were rolled back"
+            )))
+        }
+    }
+}
+
+/// `to`, which does not exist yet, with symlinks in its existing ancestors
+/// resolved, so it can be compared with canonical paths.
+fn resolve_new_path(to: &Path) -> Result<PathBuf> {
+    let to = std::path::absolute(to)?;
+    let existing = to
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .context("No parent directory of the destination exists")?;
+    let rest = to.strip_prefix(existing)?;
+    Ok(path_util::canonicalize(existing)?.join(rest))
+}
+
+/// Points the sandbox's `.git` file and the admin dir's `gitdir` file at each
+/// other. `commondir` is relative to the admin dir, which stays in the same
+/// bare repository, so it needs no change.
+fn relink_worktree(journal: &mut Journal<'_>, admin_dir: &Path, sandbox: &Path) -> Result<()> {
+    journal.write_file(
+        &sandbox.join(".git"),
+        &format!("gitdir: {}\n", admin_dir.display()),
+    )?;
+    journal.write_file(
+        &admin_dir.join("gitdir"),
+        &format!("{}\n", sandbox.join(".git").display()),
+    )
+}
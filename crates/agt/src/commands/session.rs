@@ -1,51 +1,229 @@
-use crate::cli::SessionCommands;
+use crate::cli::{LandMode, MetaCommands, SessionCommands, ShadowSelector};
 use crate::config::AgtConfig;
 use crate::gix_cli::{find_worktree_binary, repo_base_path};
 use crate::isolation::SessionPaths;
 use crate::path_util;
+use crate::session_store::{self, SessionMetadata, SessionStore};
+use crate::shadow_history::{
+    self, Selector, SESSION_INFO_DIR, SESSION_INFO_PATH, SESSION_TRAILER, USER_BRANCH_TRAILER,
+};
 use anyhow::{bail, Context, Result};
 use gix::Repository;
 use gix_ref::transaction::PreviousValue;
-use serde::{Deserialize, Serialize};
 use std::ffi::OsStr;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::Command as StdCommand;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct SessionMetadata {
-    session_id: String,
-    branch: String,
-    sandbox: String,
-    from: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    from_spec: Option<String>,
-    from_commit: String,
-    user_branch: String,
-    created_at: u64,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    profile: Option<String>,
-    #[serde(default, skip_serializing_if = "Option::is_none")]
-    isolation: Option<String>,
-}
-
 pub fn run(repo: &Repository, command: SessionCommands, config: &AgtConfig) -> Result<()> {
     match command {
-        SessionCommands::New { id, from, profile } => {
-            let session_id = id.unwrap_or_else(generate_session_id);
-            create_session(repo, config, &session_id, from.as_deref(), &profile)
+        SessionCommands::New {
+            id,
+            from,
+            profile,
+            ticket,
+            title,
+            labels,
+            meta,
+            ticket_branch,
+        } => {
+            let session_id = match (id, &ticket) {
+                (Some(id), _) => id,
+                (None, Some(ticket)) => session_id_for_ticket(repo, config, ticket)?,
+                (None, None) => generate_session_id(),
+            };
+            let mut fields = Vec::new();
+            fields.extend(ticket.clone().map(|ticket| ("ticket".to_string(), ticket)));
+            fields.extend(title.map(|title| ("title".to_string(), title)));
+            if !labels.is_empty() {
+                fields.push(("labels".to_string(), labels.join(",")));
+            }
+            for pair in meta {
+                let (key, value) = pair
+                    .split_once('=')
+                    .with_context(|| format!("Invalid --meta {pair}; expected KEY=VALUE"))?;
+                fields.push((key.to_string(), value.to_string()));
+            }
+            let ticket_branch = match ticket.filter(|_| ticket_branch) {
+                Some(ticket) => Some(ticket_slug(&ticket)?),
+                None => None,
+            };
+            create_session(
+                repo,
+                config,
+                &session_id,
+                from.as_deref(),
+                &profile,
+                &fields,
+                ticket_branch.as_deref(),
+            )
         }
-        SessionCommands::Fork { from, id } => {
+        SessionCommands::Fork { from, id, commit } => {
             let session_id = id.unwrap_or_else(generate_session_id);
-            create_session(repo, config, &session_id, Some(&from), "default")
+            let shadow_commit = clone_session(
+                repo,
+                config,
+                &from,
+                &session_id,
+                commit.as_deref(),
+                "agt session fork",
+            )?;
+            println!("  Forked from: {from} at {shadow_commit}");
+            Ok(())
         }
         SessionCommands::Export { session_id } => export_session(repo, config, session_id),
-        SessionCommands::Remove { id, delete_branch } => {
-            super::prune_session::run(repo, &id, delete_branch, config)
+        SessionCommands::Remove {
+            session_id,
+            delete_branch,
+        } => {
+            let session_id = resolve_session_id(repo, session_id)?;
+            super::prune_session::run(repo, &session_id, delete_branch, config)
+        }
+        SessionCommands::Restore {
+            session_id,
+            select,
+            as_new: Some(new_id),
+            yes,
+            ..
+        } => {
+            let session_id = resolve_session_id(repo, session_id)?;
+            let commit = select_shadow_commit(repo, config, &session_id, &select, true, yes)?;
+            let shadow_commit = clone_session(
+                repo,
+                config,
+                &session_id,
+                &new_id,
+                Some(&commit),
+                "agt session restore --as-new",
+            )?;
+            println!("  Restored from: {session_id} at {shadow_commit}");
+            Ok(())
+        }
+        SessionCommands::Restore {
+            session_id,
+            select,
+            undo,
+            yes,
+            path,
+            as_new: None,
+        } => {
+            let session_id = resolve_session_id(repo, session_id)?;
+            let commit = if undo {
+                SessionStore::open(repo)
+                    .load(&session_id)?
+                    .pre_restore_commit
+                    .with_context(|| format!("Session {session_id} has no restore to undo"))?
+            } else {
+                select_shadow_commit(repo, config, &session_id, &select, false, yes)?
+            };
+            if path.is_empty() {
+                restore_session(repo, config, &session_id, &commit)
+            } else {
+                restore_session_paths(repo, config, &session_id, &commit, &path)
+            }
         }
-        SessionCommands::Restore { session_id, commit } => {
-            restore_session(repo, config, &session_id, &commit)
+        SessionCommands::Archive { session_id } => {
+            let session_id = resolve_session_id(repo, session_id)?;
+            archive_session(repo, config, &session_id)
         }
-        SessionCommands::List => super::list_sessions::run(repo, config),
+        SessionCommands::Rehydrate { session_id, commit } => {
+            let session_id = resolve_session_id(repo, session_id)?;
+            rehydrate_session(repo, &session_id, commit.as_deref())
+        }
+        SessionCommands::Recover => recover_sessions(repo, config),
+        SessionCommands::Land {
+            session_id,
+            id,
+            rebase,
+            squash,
+            merge: _,
+            into,
+            strip_agent_commits,
+            message,
+            remove,
+        } => {
+            let session_id = resolve_session_id(repo, session_id.or(id))?;
+            let mode = if rebase {
+                LandMode::Rebase
+            } else if squash {
+                LandMode::Squash
+            } else {
+                LandMode::Merge
+            };
+            super::land_session::run(
+                repo,
+                config,
+                &session_id,
+                mode,
+                into.as_deref(),
+                strip_agent_commits,
+                message.as_deref(),
+                remove,
+            )
+        }
+        SessionCommands::Gc {
+            apply,
+            merged_into,
+            older_than,
+        } => super::gc_sessions::run(
+            repo,
+            config,
+            apply,
+            merged_into.as_deref(),
+            older_than.as_deref(),
+        ),
+        SessionCommands::Compare { session_ids, patch } => {
+            super::compare_sessions::run(repo, config, &session_ids, patch)
+        }
+        SessionCommands::Bisect {
+            session_id,
+            good,
+            bad,
+            cmd,
+        } => {
+            let session_id = resolve_session_id(repo, session_id)?;
+            super::bisect_session::run(repo, config, &session_id, &good, bad.as_deref(), &cmd)
+        }
+        SessionCommands::Check {
+            cmd,
+            all,
+            parallel,
+            session_ids,
+        } => super::check_sessions::run(repo, config, &cmd, all, &session_ids, parallel),
+        SessionCommands::Rename { session_id, ids } => {
+            let (old, new) = match (ids.as_slice(), session_id) {
+                ([new], session_id) => (resolve_session_id(repo, session_id)?, new.clone()),
+                ([old, new], None) => (old.clone(), new.clone()),
+                _ => bail!("Pass the session to rename as --session-id or as OLD_ID, not both"),
+            };
+            super::rename_session::rename(repo, config, &old, &new)
+        }
+        SessionCommands::Move { session_id, id, to } => {
+            let session_id = resolve_session_id(repo, session_id.or(id))?;
+            super::rename_session::relocate(repo, config, &session_id, &to)
+        }
+        SessionCommands::Meta {
+            command: MetaCommands::Get { key, session_id },
+        } => super::session_meta::get(repo, session_id.as_deref(), key.as_deref()),
+        SessionCommands::Meta {
+            command:
+                MetaCommands::Set {
+                    key,
+                    value,
+                    session_id,
+                },
+        } => super::session_meta::set(repo, session_id.as_deref(), &key, &value),
+        SessionCommands::Env { session_id, id } => {
+            print_session_env(repo, session_id.or(id).as_deref())
+        }
+        SessionCommands::Status { session_id } => {
+            super::session_status::run(repo, config, session_id.as_deref())
+        }
+        SessionCommands::List {
+            filter,
+            sort,
+            reverse,
+        } => super::list_sessions::run(repo, config, &filter, sort, reverse),
     }
 }
 
@@ -55,10 +233,22 @@ fn create_session(
     session_id: &str,
     from: Option<&str>,
     profile: &str,
+    fields: &[(String, String)],
+    ticket_branch: Option<&str>,
 ) -> Result<()> {
     let branch_name = format!("{}{}", config.branch_prefix, session_id);
-
-    let user_branch = resolve_user_branch(repo, from)?;
+    let shadow_branch_ref = format!("refs/heads/{branch_name}");
+    let store = SessionStore::open(repo);
+    let _lock = store.lock(session_id)?;
+    if store.exists(session_id) {
+        bail!("Session {session_id} already exists");
+    }
+    let paths = SessionPaths::new(repo_root(repo)?.join("sessions").join(session_id));
+    ensure_session_free(repo, session_id, &shadow_branch_ref, &paths)?;
+    let mut described = SessionMetadata::new(session_id, &branch_name, "", "");
+    for (key, value) in fields {
+        described.set_field(key, value)?;
+    }
 
     // 1. Resolve starting point
     let start_commit = match from {
@@ -66,24 +256,282 @@ fn create_session(
         None => repo.head()?.peel_to_commit_in_place()?,
     };
 
-    // 2. Create shadow branch
-    repo.reference(
-        format!("refs/heads/{branch_name}"),
-        start_commit.id,
-        PreviousValue::MustNotExist,
-        "agt session new",
-    )?;
+    let mut created_user_branch = false;
+    let user_branch = match ticket_branch {
+        Some(name) => {
+            let user_branch = format!("refs/heads/{name}");
+            if repo.find_reference(user_branch.as_str()).is_err() {
+                repo.reference(
+                    user_branch.as_str(),
+                    start_commit.id,
+                    PreviousValue::MustNotExist,
+                    "agt session new --ticket-branch",
+                )?;
+                created_user_branch = true;
+            }
+            user_branch
+        }
+        None => resolve_user_branch(repo, from)?,
+    };
 
-    // 3. Create session folder structure
-    let repo_root = repo_root(repo)?;
-    let sessions_dir = repo_root.join("sessions");
-    std::fs::create_dir_all(&sessions_dir)?;
-    let session_root = sessions_dir.join(session_id);
+    let created = (|| -> Result<()> {
+        // 2. Create shadow branch
+        repo.reference(
+            shadow_branch_ref.as_str(),
+            start_commit.id,
+            PreviousValue::MustNotExist,
+            "agt session new",
+        )?;
 
-    let paths = SessionPaths::new(session_root.clone());
-    paths.ensure_dirs()?;
+        // 3. Create session folder structure
+        paths.ensure_dirs()?;
+
+        // 4. Create git worktree in sandbox
+        let status = StdCommand::new(find_worktree_binary(&repo_base_path(repo))?)
+            .args([
+                "add",
+                "--git-dir",
+                repo.common_dir().to_str().unwrap(),
+                "--worktree",
+                paths.sandbox.to_str().unwrap(),
+                "--name",
+                session_id,
+                "--branch",
+                &shadow_branch_ref,
+            ])
+            .status()
+            .context("Failed to create sandbox")?;
+        if !status.success() {
+            bail!("Failed to create sandbox for {session_id}");
+        }
+
+        // 5. Initialize timestamp and metadata
+        let agt_dir = repo.common_dir().join("agt");
+        let timestamp_dir = agt_dir.join("timestamps");
+        std::fs::create_dir_all(&timestamp_dir)?;
+        let timestamp_file = timestamp_dir.join(session_id);
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        std::fs::write(&timestamp_file, now.to_string())?;
+
+        let sandbox = path_util::canonicalize_or_original(&paths.sandbox)
+            .display()
+            .to_string();
+        store.save(&SessionMetadata {
+            from: start_commit.id.to_string(),
+            from_spec: from.map(str::to_string),
+            from_commit: start_commit.id.to_string(),
+            created_at: now,
+            profile: Some(profile.to_string()),
+            sandbox,
+            user_branch: user_branch.clone(),
+            ..described
+        })
+    })();
+    if let Err(err) = created {
+        // Leave nothing of the new session behind, including its user branch.
+        remove_partial_session(repo, session_id, &shadow_branch_ref, &paths);
+        if created_user_branch {
+            if let Ok(reference) = repo.find_reference(user_branch.as_str()) {
+                let _ = reference.delete();
+            }
+        }
+        return Err(err);
+    }
+
+    println!("Created session: {session_id}");
+    println!("  Shadow branch: {branch_name}");
+    println!("  Session folder: {}", paths.root.display());
+    println!("  Sandbox: {}", paths.sandbox.display());
+    println!("  Profile: {profile}");
+    if let Some(ticket) = fields.iter().find(|(key, _)| key == "ticket") {
+        println!("  Ticket: {}", ticket.1);
+    }
+    if created_user_branch {
+        println!(
+            "  User branch: {} (created)",
+            ticket_branch.unwrap_or_default()
+        );
+    }
+
+    Ok(())
+}
+
+/// Creates `session_id` as a copy of `source_id`'s session folder at a shadow
+/// commit, leaving the source's files untouched. Without `commit_spec`, a
+/// source that has not been autocommitted since its last sandbox change is
+/// autocommitted first. Returns the shadow commit used.
+fn clone_session(
+    repo: &Repository,
+    config: &AgtConfig,
+    source_id: &str,
+    session_id: &str,
+    commit_spec: Option<&str>,
+    reflog_message: &str,
+) -> Result<gix::ObjectId> {
+    let store = SessionStore::open(repo);
+    if !store.exists(source_id) {
+        bail!("Session not found: {source_id}");
+    }
+    if source_id == session_id {
+        bail!("Session {session_id} already exists");
+    }
+    // The source stays locked so it cannot change while it is copied.
+    let _locks = store.lock_pair(source_id, session_id)?;
+    let source = store
+        .load(source_id)
+        .with_context(|| format!("Session not found: {source_id}"))?;
+    if store.exists(session_id) {
+        bail!("Session {session_id} already exists");
+    }
+    let branch_name = format!("{}{}", config.branch_prefix, session_id);
+    let shadow_branch_ref = format!("refs/heads/{branch_name}");
+    let paths = SessionPaths::new(repo_root(repo)?.join("sessions").join(session_id));
+    ensure_session_free(repo, session_id, &shadow_branch_ref, &paths)?;
+
+    // 1. Resolve the source shadow commit: the tip of its shadow branch by default
+    let source_ref = format!("refs/heads/{}", source.branch);
+    let spec = commit_spec.unwrap_or(&source_ref);
+    let mut shadow_commit = repo
+        .rev_parse_single(spec)
+        .with_context(|| format!("Failed to resolve {spec}"))?
+        .object()?
+        .peel_to_commit()?;
+    let source_sandbox = path_util::canonicalize_or_original(Path::new(&source.sandbox));
+    if commit_spec.is_none()
+        && !shadow_history::is_shadow_commit(&shadow_commit)?
+        && source_sandbox.exists()
+    {
+        super::autocommit::run_locked(
+            repo,
+            &source_sandbox,
+            source_id,
+            None,
+            false,
+            None,
+            None,
+            config,
+        )
+        .with_context(|| format!("Failed to autocommit session {source_id}"))?;
+        shadow_commit = repo.find_reference(source_ref.as_str())?.peel_to_commit()?;
+    }
+    // A commit that is not a shadow commit, such as the start commit of a
+    // session that was never autocommitted, is checked out as a plain commit.
+    let user_commit = match shadow_commit.parent_ids().nth(1) {
+        Some(parent) if shadow_history::is_shadow_commit(&shadow_commit)? => parent.detach(),
+        _ => shadow_commit.id,
+    };
+
+    // 2. Check out the shadow tree into a new session folder and sandbox
+    let created = (|| -> Result<()> {
+        materialize_session(
+            repo,
+            session_id,
+            &shadow_branch_ref,
+            &shadow_commit,
+            &paths,
+            PreviousValue::MustNotExist,
+            reflog_message,
+        )?;
+
+        // 3. Initialize timestamp and metadata
+        let agt_dir = repo.common_dir().join("agt");
+        let timestamp_dir = agt_dir.join("timestamps");
+        std::fs::create_dir_all(&timestamp_dir)?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        std::fs::write(timestamp_dir.join(session_id), now.to_string())?;
+
+        let sandbox = path_util::canonicalize_or_original(&paths.sandbox)
+            .display()
+            .to_string();
+        store.save(&SessionMetadata {
+            from: shadow_commit.id.to_string(),
+            from_spec: Some(source_id.to_string()),
+            from_commit: user_commit.to_string(),
+            created_at: now,
+            profile: source.profile.clone(),
+            isolation: source.isolation.clone(),
+            forked_from: Some(source_id.to_string()),
+            forked_from_commit: Some(shadow_commit.id.to_string()),
+            ..SessionMetadata::new(session_id, &branch_name, &sandbox, &source.user_branch)
+        })
+    })();
+    if let Err(err) = created {
+        // Leave nothing of the new session behind.
+        remove_partial_session(repo, session_id, &shadow_branch_ref, &paths);
+        return Err(err);
+    }
+
+    println!("Created session: {session_id}");
+    println!("  Shadow branch: {branch_name}");
+    println!("  Session folder: {}", paths.root.display());
+    println!("  Sandbox: {}", paths.sandbox.display());
+
+    Ok(shadow_commit.id)
+}
+
+/// Fails if anything a new session `session_id` would create already exists,
+/// so that [`remove_partial_session`] only removes what the session created.
+fn ensure_session_free(
+    repo: &Repository,
+    session_id: &str,
+    shadow_branch_ref: &str,
+    paths: &SessionPaths,
+) -> Result<()> {
+    if repo.find_reference(shadow_branch_ref).is_ok() {
+        bail!("Branch {shadow_branch_ref} already exists");
+    }
+    let admin_dir = repo.common_dir().join("worktrees").join(session_id);
+    for path in [&paths.root, &admin_dir] {
+        if path.exists() {
+            bail!("{} already exists", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Removes what a failed session creation left behind: the shadow branch,
+/// the session folder, the worktree admin dir and the timestamp file.
+fn remove_partial_session(
+    repo: &Repository,
+    session_id: &str,
+    shadow_branch_ref: &str,
+    paths: &SessionPaths,
+) {
+    if let Ok(reference) = repo.find_reference(shadow_branch_ref) {
+        let _ = reference.delete();
+    }
+    let _ = std::fs::remove_dir_all(repo.common_dir().join("worktrees").join(session_id));
+    let _ = std::fs::remove_dir_all(&paths.root);
+    let _ = std::fs::remove_file(repo.common_dir().join("agt/timestamps").join(session_id));
+}
+
+/// Builds the session folder at `paths` from a shadow commit and points
+/// `shadow_branch_ref` at it. The branch is first set to the user commit
+/// (parent2), with `previous` as its expected prior value, so the new sandbox
+/// worktree checks out plain files; the whole shadow tree, including tool
+/// state, config and ignored files, is then checked out over it. Any other
+/// commit is only checked out in the sandbox.
+fn materialize_session(
+    repo: &Repository,
+    session_id: &str,
+    shadow_branch_ref: &str,
+    shadow_commit: &gix::Commit<'_>,
+    paths: &SessionPaths,
+    previous: PreviousValue,
+    reflog_message: &str,
+) -> Result<()> {
+    let user_commit = match shadow_commit.parent_ids().nth(1) {
+        Some(parent) if shadow_history::is_shadow_commit(shadow_commit)? => parent.detach(),
+        _ => shadow_commit.id,
+    };
+
+    repo.reference(shadow_branch_ref, user_commit, previous, reflog_message)?;
 
-    // 4. Create git worktree in sandbox
+    paths.ensure_dirs()?;
     let status = StdCommand::new(find_worktree_binary(&repo_base_path(repo))?)
         .args([
             "add",
@@ -94,7 +542,7 @@ fn create_session(
             "--name",
             session_id,
             "--branch",
-            &format!("refs/heads/{branch_name}"),
+            shadow_branch_ref,
         ])
         .status()
         .context("Failed to create sandbox")?;
@@ -102,57 +550,261 @@ fn create_session(
         bail!("Failed to create sandbox for {session_id}");
     }
 
-    // 5. Initialize timestamp and metadata
-    let agt_dir = repo.common_dir().join("agt");
-    let timestamp_dir = agt_dir.join("timestamps");
-    std::fs::create_dir_all(&timestamp_dir)?;
-    let timestamp_file = timestamp_dir.join(session_id);
+    if user_commit == shadow_commit.id {
+        return Ok(());
+    }
+    restore_shadow_tree(repo, &shadow_commit.tree()?, &paths.root, &paths.sandbox)?;
+
+    repo.reference(
+        shadow_branch_ref,
+        shadow_commit.id,
+        PreviousValue::MustExistAndMatch(gix_ref::Target::Object(user_commit)),
+        reflog_message,
+    )?;
+    Ok(())
+}
+
+/// Removes the session folder and sandbox worktree after saving everything in
+/// a shadow commit. The shadow branch and metadata stay for `rehydrate`.
+pub fn archive_session(repo: &Repository, config: &AgtConfig, session_id: &str) -> Result<()> {
+    let store = SessionStore::open(repo);
+    let _lock = store.lock(session_id)?;
+    let mut metadata = store.load(session_id)?;
+    if metadata.archived_at.is_some() {
+        bail!("Session {session_id} is already archived");
+    }
+    let sandbox_path = path_util::canonicalize_or_original(Path::new(&metadata.sandbox));
+    let session_folder = sandbox_path
+        .parent()
+        .context("Sandbox has no parent directory")?
+        .to_path_buf();
+
+    // A full scan, so files with old modification times are not left behind.
+    if sandbox_path.exists() {
+        super::autocommit::run_locked(
+            repo,
+            &sandbox_path,
+            session_id,
+            Some(0),
+            false,
+            None,
+            None,
+            config,
+        )
+        .context("Failed to save the session state before archiving; nothing was removed")?;
+    }
+    let shadow_branch_ref = format!("refs/heads/{}", metadata.branch);
+    let tip = repo
+        .find_reference(shadow_branch_ref.as_str())?
+        .peel_to_commit()?;
+    if tip.parent_ids().count() < 2 {
+        bail!("Session {session_id} has no shadow commit to rehydrate from; nothing was removed");
+    }
+
+    let status = StdCommand::new(find_worktree_binary(&repo_base_path(repo))?)
+        .args([
+            "remove",
+            "--git-dir",
+            repo.common_dir().to_str().unwrap(),
+            "--worktree",
+            sandbox_path.to_str().unwrap(),
+            "--name",
+            session_id,
+        ])
+        .status()
+        .context("Failed to remove sandbox")?;
+    if !status.success() {
+        bail!("Failed to remove sandbox for {session_id}");
+    }
+    if session_folder.exists() && session_folder.file_name() == Some(OsStr::new(session_id)) {
+        std::fs::remove_dir_all(&session_folder)?;
+    }
+
+    metadata.archived_at = Some(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs(),
+    );
+    store.save(&metadata)?;
+
+    println!("Archived session {session_id} at shadow commit {}", tip.id);
+    println!("  Removed session folder: {}", session_folder.display());
+    println!("  Rehydrate with: agt session rehydrate --session-id {session_id}");
+    Ok(())
+}
+
+/// Recreates a session folder removed by `archive`, or deleted by hand, from
+/// the latest or a chosen shadow commit.
+fn rehydrate_session(repo: &Repository, session_id: &str, commit_spec: Option<&str>) -> Result<()> {
+    let store = SessionStore::open(repo);
+    let _lock = store.lock(session_id)?;
+    let mut metadata = store.load(session_id)?;
+    let sandbox_path = PathBuf::from(&metadata.sandbox);
+    if sandbox_path.join(".git").exists() {
+        bail!(
+            "Session {session_id} already has a sandbox at {}",
+            sandbox_path.display()
+        );
+    }
+    let paths = SessionPaths::new(
+        sandbox_path
+            .parent()
+            .context("Sandbox has no parent directory")?
+            .to_path_buf(),
+    );
+
+    let shadow_branch_ref = format!("refs/heads/{}", metadata.branch);
+    let tip = repo
+        .find_reference(shadow_branch_ref.as_str())?
+        .peel_to_commit()?
+        .id;
+    let spec = commit_spec.unwrap_or(&shadow_branch_ref);
+    let shadow_commit = repo
+        .rev_parse_single(spec)
+        .with_context(|| format!("Failed to resolve {spec}"))?
+        .object()?
+        .peel_to_commit()?;
+    if !shadow_history::is_shadow_commit(&shadow_commit)? {
+        bail!("{spec} is not a shadow commit");
+    }
+
+    if let Err(err) = materialize_session(
+        repo,
+        session_id,
+        &shadow_branch_ref,
+        &shadow_commit,
+        &paths,
+        PreviousValue::MustExistAndMatch(gix_ref::Target::Object(tip)),
+        "agt session rehydrate",
+    ) {
+        // Leave the shadow branch where it was.
+        repo.reference(
+            shadow_branch_ref.as_str(),
+            tip,
+            PreviousValue::Any,
+            "agt session rehydrate: roll back",
+        )?;
+        return Err(err);
+    }
+
     let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)?
         .as_secs();
-    std::fs::write(&timestamp_file, now.to_string())?;
-
-    let sessions_meta_dir = agt_dir.join("sessions");
-    std::fs::create_dir_all(&sessions_meta_dir)?;
-    let session_file = sessions_meta_dir.join(format!("{session_id}.json"));
+    let timestamp_dir = repo.common_dir().join("agt/timestamps");
+    std::fs::create_dir_all(&timestamp_dir)?;
+    std::fs::write(timestamp_dir.join(session_id), now.to_string())?;
 
-    let metadata = SessionMetadata {
-        session_id: session_id.to_string(),
-        branch: branch_name.clone(),
-        sandbox: path_util::canonicalize_or_original(&paths.sandbox)
-            .display()
-            .to_string(),
-        from: start_commit.id.to_string(),
-        from_spec: from.map(str::to_string),
-        from_commit: start_commit.id.to_string(),
-        user_branch,
-        created_at: now,
-        profile: Some(profile.to_string()),
-        isolation: None,
-    };
-    std::fs::write(&session_file, serde_json::to_string_pretty(&metadata)?)?;
+    if shadow_commit.id != tip {
+        metadata.pre_restore_commit = Some(tip.to_string());
+    }
+    metadata.archived_at = None;
+    store.save(&metadata)?;
 
-    println!("Created session: {session_id}");
-    println!("  Shadow branch: {branch_name}");
+    println!(
+        "Rehydrated session {session_id} from shadow commit {}",
+        shadow_commit.id
+    );
     println!("  Session folder: {}", paths.root.display());
     println!("  Sandbox: {}", paths.sandbox.display());
-    println!("  Profile: {profile}");
+    Ok(())
+}
+
+/// Rebuilds missing `agt/sessions/<id>.json` files from the session
+/// information recorded in shadow commits.
+fn recover_sessions(repo: &Repository, config: &AgtConfig) -> Result<()> {
+    let prefix = format!("refs/heads/{}", config.branch_prefix);
+    let agt_dir = repo.common_dir().join("agt");
+    std::fs::create_dir_all(agt_dir.join("timestamps"))?;
+    let sessions_dir = repo_root(repo)?.join("sessions");
+    let store = SessionStore::open(repo);
 
+    let mut recovered = 0;
+    for reference in repo.references()?.prefixed(prefix.as_str())? {
+        let mut reference = reference.map_err(|err| anyhow::anyhow!(err))?;
+        let name = reference.name().as_bstr().to_string();
+        let Some(session_id) = name.strip_prefix(&prefix).map(str::to_string) else {
+            continue;
+        };
+        let _lock = store.lock(&session_id)?;
+        if store.exists(&session_id) {
+            continue;
+        }
+
+        let tip = reference.peel_to_commit()?;
+        let Some((mut metadata, source)) = recover_metadata(repo, tip)? else {
+            eprintln!("Warning: no session information in the shadow commits of {session_id}");
+            continue;
+        };
+        let sandbox = sessions_dir.join(&session_id).join("sandbox");
+        metadata.session_id.clone_from(&session_id);
+        metadata.branch = format!("{}{session_id}", config.branch_prefix);
+        metadata.sandbox = sandbox.display().to_string();
+        if !sandbox.exists() && metadata.archived_at.is_none() {
+            metadata.archived_at = Some(
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)?
+                    .as_secs(),
+            );
+        }
+        store.save(&metadata)?;
+
+        let timestamp_file = agt_dir.join("timestamps").join(&session_id);
+        if !timestamp_file.exists() {
+            std::fs::write(&timestamp_file, "0")?;
+        }
+        println!("Recovered session {session_id} from shadow commit {source}");
+        recovered += 1;
+    }
+
+    println!("Recovered {recovered} sessions");
     Ok(())
 }
 
+/// Finds the newest shadow commit reachable by first parents that describes
+/// its session, preferring `_agt/session.json` over commit trailers.
+fn recover_metadata<'repo>(
+    repo: &'repo Repository,
+    mut commit: gix::Commit<'repo>,
+) -> Result<Option<(SessionMetadata, gix::ObjectId)>> {
+    loop {
+        let parents: Vec<_> = commit.parent_ids().map(gix::Id::detach).collect();
+        if parents.len() < 2 {
+            return Ok(None);
+        }
+
+        let tree = commit.tree()?;
+        if let Some(entry) = tree.lookup_entry_by_path(SESSION_INFO_PATH)? {
+            let blob = repo.find_object(entry.object_id())?;
+            let metadata = session_store::parse(&blob.data)
+                .with_context(|| format!("Failed to parse {SESSION_INFO_PATH} in {}", commit.id))?;
+            return Ok(Some((metadata, commit.id)));
+        }
+
+        let message = commit.message_raw()?.to_string();
+        if let (Some(session_id), Some(user_branch)) = (
+            shadow_history::trailer(&message, SESSION_TRAILER),
+            shadow_history::trailer(&message, USER_BRANCH_TRAILER),
+        ) {
+            let metadata = SessionMetadata {
+                from: parents[1].to_string(),
+                from_commit: parents[1].to_string(),
+                created_at: u64::try_from(commit.time()?.seconds).unwrap_or_default(),
+                ..SessionMetadata::new(&session_id, "", "", &user_branch)
+            };
+            return Ok(Some((metadata, commit.id)));
+        }
+
+        commit = repo.find_object(parents[0])?.try_into_commit()?;
+    }
+}
+
 fn export_session(
     repo: &Repository,
     config: &AgtConfig,
     explicit_session_id: Option<String>,
 ) -> Result<()> {
-    let (session_id, metadata) = match explicit_session_id {
-        Some(id) => {
-            let meta = load_metadata(repo, &id)?;
-            (id, meta)
-        }
-        None => infer_session_from_cwd(repo)?,
-    };
+    let metadata = resolve_session(repo, explicit_session_id.as_deref())?;
+    let session_id = metadata.session_id.clone();
 
     let sandbox_path = path_util::canonicalize_or_original(Path::new(&metadata.sandbox));
     if !sandbox_path.exists() {
@@ -187,13 +839,173 @@ fn export_session(
     Ok(())
 }
 
-fn restore_session(
+/// Resolves `select` against the session's shadow history. Unless `yes` or
+/// `non_destructive`, asks before a commit picked by time, position or label
+/// is restored over the live session.
+fn select_shadow_commit(
+    repo: &Repository,
+    config: &AgtConfig,
+    session_id: &str,
+    select: &ShadowSelector,
+    non_destructive: bool,
+    yes: bool,
+) -> Result<String> {
+    if let Some(commit) = &select.commit {
+        return Ok(commit.clone());
+    }
+    let selector = if let Some(at) = &select.at {
+        Selector::At(shadow_history::parse_time(at)?)
+    } else if let Some(before) = &select.before {
+        Selector::Before(shadow_history::parse_duration(before)?)
+    } else if let Some(steps) = select.steps_back {
+        Selector::StepsBack(steps)
+    } else if let Some(label) = &select.label {
+        Selector::Label(label.clone())
+    } else {
+        bail!("Specify the shadow commit with --commit, --at, --before, --steps-back or --label");
+    };
+
+    let metadata = SessionStore::open(repo).load(session_id)?;
+    let branch_ref = format!("refs/heads/{}", metadata.branch);
+    let chain =
+        shadow_history::first_parent_chain(repo, config, &branch_ref, &metadata.from_commit)?;
+    let index = shadow_history::select(&chain, &selector)
+        .with_context(|| format!("Failed to select a shadow commit of session {session_id}"))?;
+    let picked = &chain[index];
+    let label = picked
+        .label
+        .as_ref()
+        .map(|label| format!(", label {label}"))
+        .unwrap_or_default();
+    println!(
+        "Selected shadow commit {} from {} ({index} steps back{label})",
+        picked.id,
+        shadow_history::format_time(picked.time)
+    );
+
+    if !yes && !non_destructive && !confirm(&format!("Restore session {session_id} to it?"))? {
+        bail!("Restore cancelled");
+    }
+    Ok(picked.id.to_string())
+}
+
+fn confirm(question: &str) -> Result<bool> {
+    eprint!("{question} [N/y] ");
+    std::io::stderr().flush()?;
+
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+    Ok(input.trim().eq_ignore_ascii_case("y"))
+}
+
+/// Autocommits everything since the last autocommit and records the result
+/// as `pre_restore_commit`, so the restore can be undone.
+fn save_pre_restore_state(
+    repo: &Repository,
+    config: &AgtConfig,
+    metadata: &mut SessionMetadata,
+    sandbox_path: &Path,
+) -> Result<gix::ObjectId> {
+    if !sandbox_path.exists() {
+        bail!(
+            "Session {0} has no sandbox at {1}; run agt session rehydrate --session-id {0}",
+            metadata.session_id,
+            sandbox_path.display()
+        );
+    }
+    super::autocommit::run_locked(
+        repo,
+        sandbox_path,
+        &metadata.session_id,
+        None,
+        false,
+        None,
+        None,
+        config,
+    )
+    .context("Failed to save the session state before restoring; nothing was changed")?;
+    let shadow_branch_ref = format!("refs/heads/{}", metadata.branch);
+    let pre_restore_commit = repo
+        .find_reference(shadow_branch_ref.as_str())?
+        .peel_to_commit()?
+        .id;
+    metadata.pre_restore_commit = Some(pre_restore_commit.to_string());
+    SessionStore::open(repo).save(metadata)?;
+    Ok(pre_restore_commit)
+}
+
+/// Extracts `paths`, files or directories relative to the session folder,
+/// from a shadow commit. Nothing else is reset or deleted.
+fn restore_session_paths(
+    repo: &Repository,
+    config: &AgtConfig,
+    session_id: &str,
+    commit_spec: &str,
+    paths: &[PathBuf],
+) -> Result<()> {
+    let store = SessionStore::open(repo);
+    let _lock = store.lock(session_id)?;
+    let mut metadata = store.load(session_id)?;
+    let sandbox_path = path_util::canonicalize_or_original(Path::new(&metadata.sandbox));
+    let session_folder = sandbox_path
+        .parent()
+        .context("Sandbox has no parent directory")?;
+
+    let shadow_commit = repo
+        .rev_parse_single(commit_spec)?
+        .object()?
+        .peel_to_commit()?;
+    let shadow_tree = shadow_commit.tree()?;
+
+    let mut entries = Vec::new();
+    for path in paths {
+        if !path
+            .components()
+            .all(|component| matches!(component, std::path::Component::Normal(_)))
+        {
+            bail!(
+                "Restore paths must be relative to the session folder: {}",
+                path.display()
+            );
+        }
+        let entry = shadow_tree.lookup_entry_by_path(path)?.with_context(|| {
+            format!("Path not found in {}: {}", shadow_commit.id, path.display())
+        })?;
+        entries.push((path, entry.mode().kind(), entry.object_id()));
+    }
+
+    let pre_restore_commit = save_pre_restore_state(repo, config, &mut metadata, &sandbox_path)?;
+
+    for (path, kind, object_id) in entries {
+        if kind == gix::object::tree::EntryKind::Tree {
+            std::fs::create_dir_all(session_folder.join(path))?;
+            restore_checkout_tree_to_disk(repo, object_id, path, session_folder)?;
+        } else {
+            restore_blob_to_disk(repo, kind, object_id, &session_folder.join(path))?;
+        }
+        println!("Restored {}", path.display());
+    }
+
+    println!(
+        "Restored {} paths of session {session_id} from commit {}",
+        paths.len(),
+        shadow_commit.id
+    );
+    println!(
+        "  Previous state saved as {pre_restore_commit}; undo with: agt session restore --session-id {session_id} --undo"
+    );
+    Ok(())
+}
+
+pub fn restore_session(
     repo: &Repository,
     config: &AgtConfig,
     session_id: &str,
     commit_spec: &str,
 ) -> Result<()> {
-    let metadata = load_metadata(repo, session_id)?;
+    let store = SessionStore::open(repo);
+    let _lock = store.lock(session_id)?;
+    let mut metadata = store.load(session_id)?;
     let sandbox_path = path_util::canonicalize_or_original(Path::new(&metadata.sandbox));
 
     let session_folder = sandbox_path
@@ -211,6 +1023,9 @@ fn restore_session(
     }
     let user_branch_commit = parents[1].detach();
 
+    let pre_restore_commit = save_pre_restore_state(repo, config, &mut metadata, &sandbox_path)?;
+    let shadow_branch_ref = format!("refs/heads/{}{session_id}", config.branch_prefix);
+
     let shadow_tree = shadow_commit.tree()?;
 
     let status = StdCommand::new(&config.git_path)
@@ -223,6 +1038,43 @@ fn restore_session(
         bail!("git reset failed in sandbox");
     }
 
+    restore_shadow_tree(repo, &shadow_tree, session_folder, &sandbox_path)?;
+
+    repo.reference(
+        shadow_branch_ref,
+        shadow_commit.id,
+        gix_ref::transaction::PreviousValue::Any,
+        "agt session restore",
+    )?;
+
+    let timestamp_file = repo.common_dir().join("agt/timestamps").join(session_id);
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+    std::fs::write(&timestamp_file, now.to_string())?;
+
+    println!(
+        "Restored session {session_id} to commit {}",
+        shadow_commit.id
+    );
+    println!("  Shadow tree checked out to: {}", session_folder.display());
+    println!("  Sandbox reset to user commit: {user_branch_commit}");
+    println!(
+        "  Previous state saved as {pre_restore_commit}; undo with: agt session restore --session-id {session_id} --undo"
+    );
+
+    Ok(())
+}
+
+/// Makes the session folder match `shadow_tree`: files the tree does not
+/// know are removed, the rest are written, and the sandbox index is restored
+/// from `_/index`. The sandbox must already be checked out at the user commit.
+fn restore_shadow_tree(
+    repo: &Repository,
+    shadow_tree: &gix::Tree<'_>,
+    session_folder: &Path,
+    sandbox_path: &Path,
+) -> Result<()> {
     let mut tree_paths = std::collections::HashSet::new();
     restore_collect_tree_paths(repo, shadow_tree.id, PathBuf::new(), &mut tree_paths)?;
 
@@ -254,37 +1106,15 @@ fn restore_session(
     let index_blob_path = Path::new("_/index");
     if let Some(index_entry) = shadow_tree.lookup_entry_by_path(index_blob_path)? {
         let index_blob = repo.find_object(index_entry.object_id())?.try_into_blob()?;
-        let sandbox_repo = gix::open(&sandbox_path)?;
+        let sandbox_repo = gix::open(sandbox_path)?;
         let index_path = sandbox_repo.path().join("index");
         std::fs::write(&index_path, &index_blob.data)?;
     }
-
-    let branch_name = format!("{}{}", config.branch_prefix, session_id);
-    let shadow_branch_ref = format!("refs/heads/{branch_name}");
-    repo.reference(
-        shadow_branch_ref,
-        shadow_commit.id,
-        gix_ref::transaction::PreviousValue::Any,
-        "agt session restore",
-    )?;
-
-    let timestamp_file = repo.common_dir().join("agt/timestamps").join(session_id);
-    let now = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)?
-        .as_secs();
-    std::fs::write(&timestamp_file, now.to_string())?;
-
-    println!(
-        "Restored session {session_id} to commit {}",
-        shadow_commit.id
-    );
-    println!("  Shadow tree checked out to: {}", session_folder.display());
-    println!("  Sandbox reset to user commit: {user_branch_commit}");
-
     Ok(())
 }
 
-fn restore_checkout_tree_to_disk(
+/// Writes the files of `tree_id` under `disk_root`, skipping `_agt`.
+pub fn restore_checkout_tree_to_disk(
     repo: &Repository,
     tree_id: gix::ObjectId,
     prefix: &Path,
@@ -299,39 +1129,59 @@ fn restore_checkout_tree_to_disk(
         let name = from_byte_slice(entry.filename);
         let entry_path = prefix.join(name);
         let disk_path = disk_root.join(&entry_path);
+        if entry_path == Path::new(SESSION_INFO_DIR) {
+            continue;
+        }
 
         if entry.mode.kind() == EntryKind::Tree {
             std::fs::create_dir_all(&disk_path)?;
             restore_checkout_tree_to_disk(repo, entry.oid.to_owned(), &entry_path, disk_root)?;
-        } else if entry.mode.kind() == EntryKind::Link {
-            let blob = repo.find_object(entry.oid)?.try_into_blob()?;
-            let target = String::from_utf8_lossy(&blob.data);
-            if disk_path.exists() || disk_path.is_symlink() {
-                std::fs::remove_file(&disk_path)?;
-            }
-            #[cfg(unix)]
-            std::os::unix::fs::symlink(target.as_ref(), &disk_path)?;
-            #[cfg(not(unix))]
-            std::fs::write(&disk_path, target.as_bytes())?;
         } else {
-            let blob = repo.find_object(entry.oid)?.try_into_blob()?;
-            if let Some(parent) = disk_path.parent() {
-                std::fs::create_dir_all(parent)?;
-            }
-            std::fs::write(&disk_path, &blob.data)?;
-
-            #[cfg(unix)]
-            if entry.mode.kind() == EntryKind::BlobExecutable {
-                use std::os::unix::fs::PermissionsExt;
-                let mut perms = std::fs::metadata(&disk_path)?.permissions();
-                perms.set_mode(perms.mode() | 0o111);
-                std::fs::set_permissions(&disk_path, perms)?;
-            }
+            restore_blob_to_disk(repo, entry.mode.kind(), entry.oid.to_owned(), &disk_path)?;
         }
     }
     Ok(())
 }
 
+fn restore_blob_to_disk(
+    repo: &Repository,
+    kind: gix::object::tree::EntryKind,
+    object_id: gix::ObjectId,
+    disk_path: &Path,
+) -> Result<()> {
+    use gix::object::tree::EntryKind;
+
+    let blob = repo.find_object(object_id)?.try_into_blob()?;
+    if let Some(parent) = disk_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if kind == EntryKind::Link {
+        let target = String::from_utf8_lossy(&blob.data);
+        if disk_path.exists() || disk_path.is_symlink() {
+            std::fs::remove_file(disk_path)?;
+        }
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(target.as_ref(), disk_path)?;
+        #[cfg(not(unix))]
+        std::fs::write(disk_path, target.as_bytes())?;
+        return Ok(());
+    }
+
+    if disk_path.is_symlink() {
+        std::fs::remove_file(disk_path)?;
+    }
+    std::fs::write(disk_path, &blob.data)?;
+
+    #[cfg(unix)]
+    if kind == EntryKind::BlobExecutable {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = std::fs::metadata(disk_path)?.permissions();
+        perms.set_mode(perms.mode() | 0o111);
+        std::fs::set_permissions(disk_path, perms)?;
+    }
+    Ok(())
+}
+
 #[allow(clippy::needless_pass_by_value)]
 fn restore_collect_tree_paths(
     repo: &Repository,
@@ -435,54 +1285,87 @@ fn resolve_user_branch(repo: &Repository, from: Option<&str>) -> Result<String>
 }
 
 fn user_branch_from_session(repo: &Repository, session_id: &str) -> Result<Option<String>> {
-    let meta = load_metadata(repo, session_id).ok();
+    let meta = SessionStore::open(repo).load(session_id).ok();
     Ok(meta.map(|m| m.user_branch))
 }
 
-fn load_metadata(repo: &Repository, session_id: &str) -> Result<SessionMetadata> {
-    let path = repo
-        .common_dir()
-        .join("agt/sessions")
-        .join(format!("{session_id}.json"));
-    let raw = std::fs::read_to_string(&path)
-        .with_context(|| format!("Failed to read {}", path.display()))?;
-    Ok(
-        serde_json::from_str(&raw)
-            .with_context(|| format!("Failed to parse {}", path.display()))?,
-    )
+/// [`resolve_session`] for commands that load the session themselves; an
+/// explicit ID is passed through as is.
+pub fn resolve_session_id(repo: &Repository, explicit: Option<String>) -> Result<String> {
+    match explicit {
+        Some(id) => Ok(id),
+        None => Ok(resolve_session(repo, None)?.session_id),
+    }
+}
+
+/// Prints `export` lines for the session, for `eval "$(agt session env)"`.
+fn print_session_env(repo: &Repository, session_id: Option<&str>) -> Result<()> {
+    let metadata = resolve_session(repo, session_id)?;
+    let sandbox = Path::new(&metadata.sandbox);
+    let session_folder = sandbox.parent().unwrap_or(sandbox);
+    let vars = [
+        (SESSION_ID_ENV, metadata.session_id.clone()),
+        ("AGT_SESSION_DIR", session_folder.display().to_string()),
+        ("AGT_SANDBOX", metadata.sandbox.clone()),
+        ("AGT_SHADOW_BRANCH", metadata.branch.clone()),
+        ("AGT_USER_BRANCH", metadata.user_branch.clone()),
+    ];
+    for (name, value) in vars {
+        println!("export {name}='{}'", value.replace('\'', r"'\''"));
+    }
+    Ok(())
 }
 
-fn infer_session_from_cwd(repo: &Repository) -> Result<(String, SessionMetadata)> {
+/// Names the session of session-scoped commands run without a session flag.
+pub const SESSION_ID_ENV: &str = "AGT_SESSION_ID";
+
+/// Finds the session a command applies to, in order: `explicit` (the
+/// command's session flag), `AGT_SESSION_ID`, the `sessions/<id>/` folder
+/// containing the current directory, then the worktree name of the sandbox
+/// the current directory is in, which is the session ID even when the
+/// sandbox was moved.
+pub fn resolve_session(repo: &Repository, explicit: Option<&str>) -> Result<SessionMetadata> {
+    let store = SessionStore::open(repo);
+    if let Some(id) = explicit {
+        return store
+            .load(id)
+            .with_context(|| format!("Session not found: {id}"));
+    }
+    if let Some(id) = std::env::var(SESSION_ID_ENV)
+        .ok()
+        .filter(|id| !id.is_empty())
+    {
+        return store
+            .load(&id)
+            .with_context(|| format!("Session not found: {id} (from {SESSION_ID_ENV})"));
+    }
+
     let cwd = path_util::canonicalize(
         &std::env::current_dir().context("Failed to determine current directory")?,
     )?;
+    for meta in store.list()? {
+        let sandbox = path_util::canonicalize_or_original(Path::new(&meta.sandbox));
+        if let Some(session_folder) = sandbox.parent() {
+            if session_folder.file_name() == Some(OsStr::new(&meta.session_id))
+                && cwd.starts_with(session_folder)
+            {
+                return Ok(meta);
+            }
+        }
+    }
 
-    let sessions_dir = repo.common_dir().join("agt/sessions");
-    for entry in std::fs::read_dir(&sessions_dir)
-        .with_context(|| format!("Failed to read {}", sessions_dir.display()))?
+    let git_dir = repo.git_dir();
+    if git_dir != repo.common_dir()
+        && git_dir.parent().and_then(Path::file_name) == Some(OsStr::new("worktrees"))
     {
-        let entry = entry?;
-        if entry.path().extension() != Some(OsStr::new("json")) {
-            continue;
-        }
-        let session_id = entry
-            .path()
-            .file_stem()
-            .and_then(OsStr::to_str)
-            .unwrap_or_default()
-            .to_string();
-        if let Ok(meta) = load_metadata(repo, &session_id) {
-            let sandbox = PathBuf::from(&meta.sandbox);
-            if sandbox.exists() {
-                let sandbox = path_util::canonicalize_or_original(&sandbox);
-                if cwd == sandbox || cwd.starts_with(&sandbox) {
-                    return Ok((session_id, meta));
-                }
+        if let Some(id) = git_dir.file_name().and_then(OsStr::to_str) {
+            if store.exists(id) {
+                return store.load(id);
             }
         }
     }
 
-    bail!("Unable to determine session from current directory; specify --session-id")
+    bail!("Unable to determine the session; pass its ID, set {SESSION_ID_ENV}, or run inside sessions/<id>/")
 }
 
 fn repo_root(repo: &Repository) -> Result<PathBuf> {
@@ -501,6 +1384,45 @@ fn repo_root(repo: &Repository) -> Result<PathBuf> {
         .context("Failed to resolve repository root")
 }
 
+/// Lowercases `ticket` and replaces runs of other characters than ASCII
+/// letters and digits with `-`, giving a name usable as a session ID and
+/// branch name.
+fn ticket_slug(ticket: &str) -> Result<String> {
+    let mut slug = String::new();
+    for c in ticket.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-').to_string();
+    if slug.is_empty() {
+        bail!("Cannot derive a session ID from ticket {ticket:?}; pass --id");
+    }
+    Ok(slug)
+}
+
+/// The ticket's slug, or `<slug>-2`, `<slug>-3`, ... if sessions already
+/// work on the ticket.
+fn session_id_for_ticket(repo: &Repository, config: &AgtConfig, ticket: &str) -> Result<String> {
+    let slug = ticket_slug(ticket)?;
+    let store = SessionStore::open(repo);
+    let taken = |id: &str| {
+        store.exists(id)
+            || repo
+                .find_reference(format!("refs/heads/{}{id}", config.branch_prefix).as_str())
+                .is_ok()
+    };
+    let mut session_id = slug.clone();
+    let mut attempt = 1;
+    while taken(&session_id) {
+        attempt += 1;
+        session_id = format!("{slug}-{attempt}");
+    }
+    Ok(session_id)
+}
+
 fn generate_session_id() -> String {
     let ts_millis = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -0,0 +1,39 @@
+use crate::session_store::SessionStore;
+use anyhow::{bail, Result};
+use gix::Repository;
+
+/// Prints field `key` of the session, or every set field as `KEY=VALUE`.
+pub fn get(repo: &Repository, session_id: Option<&str>, key: Option<&str>) -> Result<()> {
+    let metadata = super::session::resolve_session(repo, session_id)?;
+    if let Some(key) = key {
+        match metadata.field(key) {
+            Some(value) => println!("{value}"),
+            None => bail!("Session {} has no field {key}", metadata.session_id),
+        }
+        return Ok(());
+    }
+    let fields = ["ticket", "title", "labels"]
+        .into_iter()
+        .map(str::to_string)
+        .chain(metadata.meta.keys().cloned());
+    for key in fields {
+        if let Some(value) = metadata.field(&key) {
+            println!("{key}={value}");
+        }
+    }
+    Ok(())
+}
+
+pub fn set(repo: &Repository, session_id: Option<&str>, key: &str, value: &str) -> Result<()> {
+    let session_id = super::session::resolve_session(repo, session_id)?.session_id;
+    let store = SessionStore::open(repo);
+    let _lock = store.lock(&session_id)?;
+    let mut metadata = store.load(&session_id)?;
+    metadata.set_field(key, value)?;
+    store.save(&metadata)?;
+    match metadata.field(key) {
+        Some(value) => println!("Set {key} of session {session_id} to {value}"),
+        None => println!("Removed {key} from session {session_id}"),
+    }
+    Ok(())
+}
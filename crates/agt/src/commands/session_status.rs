@@ -0,0 +1,158 @@
+use super::list_sessions::{ahead_behind, sandbox_state, SandboxState};
+use crate::config::AgtConfig;
+use crate::session_store::SessionStore;
+use crate::shadow_history;
+use anyhow::{Context, Result};
+use gix::Repository;
+use std::path::Path;
+use std::process::Command as StdCommand;
+
+pub fn run(repo: &Repository, config: &AgtConfig, session_id: Option<&str>) -> Result<()> {
+    let store = SessionStore::open(repo);
+    let metadata = super::session::resolve_session(repo, session_id)?;
+    let session_id = metadata.session_id.as_str();
+    let shadow_branch_ref = format!("refs/heads/{}", metadata.branch);
+    let user_branch = metadata
+        .user_branch
+        .strip_prefix("refs/heads/")
+        .unwrap_or(&metadata.user_branch);
+    let sandbox = sandbox_state(config, &metadata);
+
+    println!("Session: {session_id}");
+    if let Some(profile) = &metadata.profile {
+        println!("  Profile: {profile}");
+    }
+    if let Some(ticket) = &metadata.ticket {
+        println!("  Ticket: {ticket}");
+    }
+    if let Some(title) = &metadata.title {
+        println!("  Title: {title}");
+    }
+    if !metadata.labels.is_empty() {
+        println!("  Labels: {}", metadata.labels.join(", "));
+    }
+    for (key, value) in &metadata.meta {
+        println!("  {key}: {value}");
+    }
+    println!("  Sandbox: {} ({})", metadata.sandbox, sandbox.as_str());
+    if let Some(archived_at) = metadata.archived_at {
+        println!(
+            "  Archived: {}; rehydrate with: agt session rehydrate --session-id {session_id}",
+            i64::try_from(archived_at)
+                .map_or_else(|_| "-".to_string(), shadow_history::format_time)
+        );
+    }
+
+    println!("  Shadow branch: {}", metadata.branch);
+    let chain = shadow_history::first_parent_chain(
+        repo,
+        config,
+        &shadow_branch_ref,
+        &metadata.from_commit,
+    )?;
+    match chain.first() {
+        Some(head) => {
+            let label = head
+                .label
+                .as_ref()
+                .map(|label| format!(", label {label}"))
+                .unwrap_or_default();
+            println!(
+                "  Shadow head: {} from {}{label}",
+                head.id,
+                shadow_history::format_time(head.time)
+            );
+        }
+        None => println!("  Shadow head: none; no autocommit yet"),
+    }
+    println!("  Shadow commits: {}", chain.len());
+
+    println!("  User branch: {user_branch}");
+    println!("  Started from: {}", metadata.from_commit);
+    match ahead_behind(repo, config, &metadata.from_commit, &metadata.user_branch) {
+        Ok((0, 0)) => println!("  Divergence: none; {user_branch} is at the starting commit"),
+        Ok((ahead, behind)) => {
+            print!("  Divergence: {user_branch} has {behind} commits since the session started");
+            if ahead > 0 {
+                print!(", and {ahead} commits of the start are no longer on it");
+            }
+            println!();
+        }
+        Err(err) => println!("  Divergence: unknown ({err:#})"),
+    }
+
+    match &metadata.pre_restore_commit {
+        Some(commit) => println!(
+            "  Last restore: state before it saved as {commit}; undo with: agt session restore --session-id {session_id} --undo"
+        ),
+        None => println!("  Last restore: none"),
+    }
+    match &metadata.last_check {
+        Some(check) => println!(
+            "  Last check: `{}` {} in {} at {}, {}; output in {}",
+            check.command,
+            check.exit_code.map_or_else(
+                || "was killed by a signal".to_string(),
+                |code| if code == 0 {
+                    "passed".to_string()
+                } else {
+                    format!("failed with exit code {code}")
+                }
+            ),
+            super::check_sessions::format_duration_ms(check.duration_ms),
+            &check.commit[..check.commit.len().min(12)],
+            i64::try_from(check.checked_at)
+                .map_or_else(|_| "-".to_string(), shadow_history::format_time),
+            check.log
+        ),
+        None => println!("  Last check: none"),
+    }
+    if store.is_locked(session_id) {
+        println!("  Lock: held by another agt process");
+    } else {
+        println!("  Lock: free");
+    }
+
+    if !matches!(sandbox, SandboxState::Clean | SandboxState::Dirty) {
+        return Ok(());
+    }
+    let sandbox_path = Path::new(&metadata.sandbox);
+    let session_folder = sandbox_path.parent().unwrap_or(sandbox_path);
+    let pending =
+        super::autocommit::pending_changes(repo, session_id, &shadow_branch_ref, session_folder)?;
+    println!();
+    if pending.changed.is_empty() && pending.deleted.is_empty() {
+        println!("No changes pending autocommit");
+    } else {
+        println!(
+            "Pending autocommit: {} changed, {} deleted",
+            pending.changed.len(),
+            pending.deleted.len()
+        );
+        for path in &pending.changed {
+            println!("  M {}", path.display());
+        }
+        for path in &pending.deleted {
+            println!("  D {}", path.display());
+        }
+    }
+
+    let output = StdCommand::new(&config.git_path)
+        .current_dir(sandbox_path)
+        .args(["status", "--short", "--branch"])
+        .output()
+        .context("Failed to run git status")?;
+    println!();
+    println!("Sandbox git status:");
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        println!("  {line}");
+    }
+    if !output.status.success() {
+        println!(
+            "  git status failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
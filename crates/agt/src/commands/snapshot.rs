@@ -2,29 +2,99 @@ use crate::cli::SnapshotCommands;
 use crate::config::AgtConfig;
 use crate::snapshot;
 use anyhow::Result;
-use gix::Repository;
 
-pub fn run(repo: &Repository, command: SnapshotCommands, config: &AgtConfig) -> Result<()> {
+pub fn run(command: SnapshotCommands, config: &AgtConfig) -> Result<()> {
     match command {
         SnapshotCommands::Save {
             target,
             store,
+            shared,
             message,
-        } => snapshot::save(repo, config, &target, store.as_deref(), message.as_deref()),
+            if_changed,
+        } => snapshot::save(
+            config,
+            &target,
+            store.as_deref(),
+            shared,
+            message.as_deref(),
+            if_changed,
+        ),
         SnapshotCommands::Diff {
             before,
             after,
+            target,
+            store,
+            shared,
+        } => snapshot::check(&before, &after, &target, store.as_deref(), shared),
+        SnapshotCommands::Status {
+            target,
+            store,
+            shared,
+            quiet,
+        } => snapshot::status(target.as_deref(), store.as_deref(), shared, quiet),
+        SnapshotCommands::List {
+            target,
+            store,
+            shared,
+            all_targets,
+            quiet,
+            verbose,
+        } => snapshot::list(&snapshot::ListOptions {
+            target: &target,
+            store: store.as_deref(),
+            shared,
+            all_targets,
+            detail: if quiet {
+                snapshot::ListDetail::Tags
+            } else if verbose {
+                snapshot::ListDetail::Provenance
+            } else {
+                snapshot::ListDetail::Messages
+            },
+        }),
+        SnapshotCommands::Watch {
+            snapshot,
+            target,
+            store,
+            shared,
+            allow,
+            json,
+            exit_on_violation,
+        } => snapshot::watch(
+            snapshot.as_deref(),
+            target.as_deref(),
+            store.as_deref(),
+            shared,
+            &allow,
+            json,
+            exit_on_violation,
+        ),
+        SnapshotCommands::Cat {
+            spec,
+            path,
+            target,
+            store,
+            shared,
+        } => snapshot::cat(&spec, &path, &target, store.as_deref(), shared),
+        SnapshotCommands::Export {
+            spec,
+            to,
+            target,
+            store,
+            shared,
+        } => snapshot::export(&spec, &to, &target, store.as_deref(), shared),
+        SnapshotCommands::Show {
+            spec,
+            target,
             store,
-        } => snapshot::check(repo, &before, &after, store.as_deref()),
-        SnapshotCommands::Status { store, quiet } => {
-            snapshot::status(repo, store.as_deref(), quiet)
-        }
-        SnapshotCommands::List { store, quiet } => snapshot::list(repo, store.as_deref(), quiet),
+            shared,
+        } => snapshot::show(&spec, &target, store.as_deref(), shared),
         SnapshotCommands::Restore {
             snapshot: snapshot_name,
             target,
             path,
             store,
-        } => snapshot::restore(repo, &snapshot_name, &target, &path, store.as_deref()),
+            shared,
+        } => snapshot::restore(&snapshot_name, &target, &path, store.as_deref(), shared),
     }
 }
@@ -1,7 +1,8 @@
 use crate::config::AgtConfig;
+use crate::session_store::SessionStore;
 use anyhow::Result;
 use gix::Repository;
-use std::fs;
+use std::path::Path;
 
 pub fn run(repo: &Repository, config: &AgtConfig) -> Result<()> {
     println!("AGT Status:");
@@ -13,45 +14,31 @@ pub fn run(repo: &Repository, config: &AgtConfig) -> Result<()> {
     }
 
     // Count active sessions
-    let sessions_dir = repo.common_dir().join("agt/sessions");
-    let session_count = if sessions_dir.exists() {
-        fs::read_dir(&sessions_dir)?
-            .filter_map(|entry| entry.ok())
-            .filter(|entry| entry.file_name().to_string_lossy().ends_with(".json"))
-            .count()
-    } else {
-        0
-    };
+    let sessions = SessionStore::open(repo).list()?;
 
-    println!("  Active Sessions: {session_count}");
+    println!("  Active Sessions: {}", sessions.len());
 
     // Check for pending autocommits
-    let timestamps_dir = repo.common_dir().join("agt/timestamps");
-    if timestamps_dir.exists() {
-        let mut pending = 0;
-        for entry in fs::read_dir(&timestamps_dir)? {
-            let entry = entry?;
-            let session_id = entry.file_name().to_string_lossy().to_string();
-            let timestamp_file = entry.path();
-
-            let last_timestamp: u64 = fs::read_to_string(&timestamp_file)?.trim().parse()?;
-
-            // Check if there are files modified since last autocommit
-            let worktree_path = repo.work_dir().map_or_else(std::path::PathBuf::new, |wd| {
-                wd.join("sessions").join(&session_id)
-            });
-
-            if worktree_path.exists() {
-                let modified_files =
-                    crate::scanner::scan_modified_files(&worktree_path, last_timestamp)?;
-                if !modified_files.is_empty() {
-                    pending += 1;
-                }
-            }
+    let mut pending = 0;
+    for session in &sessions {
+        let sandbox = Path::new(&session.sandbox);
+        if session.archived_at.is_some() || !sandbox.exists() {
+            continue;
+        }
+        let session_folder = sandbox.parent().unwrap_or(sandbox);
+        let shadow_branch_ref = format!("refs/heads/{}", session.branch);
+        let changes = super::autocommit::pending_changes(
+            repo,
+            &session.session_id,
+            &shadow_branch_ref,
+            session_folder,
+        )?;
+        if !changes.changed.is_empty() || !changes.deleted.is_empty() {
+            pending += 1;
         }
-
-        println!("  Sessions with pending changes: {pending}");
     }
 
+    println!("  Sessions with pending changes: {pending}");
+
     Ok(())
 }
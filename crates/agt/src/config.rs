@@ -79,7 +79,7 @@ impl AgtConfig {
 // License used here: MIT
 // Modified from original to inline only the tiny home-directory logic AGT uses.
 // See the root LICENSE file for the full third-party notice.
-fn home_dir() -> Option<PathBuf> {
+pub fn home_dir() -> Option<PathBuf> {
     #[cfg(windows)]
     {
         std::env::var_os("USERPROFILE")
@@ -9,7 +9,6 @@ pub fn should_hide_ref(ref_name: &str, config: &AgtConfig) -> bool {
 }
 
 /// Check if a commit should be hidden from user view (agent commits)
-#[allow(dead_code)]
 pub fn should_hide_commit(commit: &Commit, config: &AgtConfig) -> bool {
     commit
         .author()
@@ -0,0 +1,193 @@
+//! Change notifications for a directory tree, used by `agt snapshot watch`.
+//!
+//! Linux uses inotify with one watch per directory. Other platforms have no
+//! event source here and fall back to rescanning the tree periodically, as
+//! Linux does when a directory cannot be watched, e.g. because the inotify
+//! watch limit is reached.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How often the tree is rescanned without change notifications.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub enum Changes {
+    /// Absolute paths, files or directories, that may have changed.
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    Paths(Vec<PathBuf>),
+    /// Events were lost or are unavailable; the whole tree must be rechecked.
+    Everything,
+}
+
+#[cfg(target_os = "linux")]
+pub use inotify::FsEvents;
+
+#[cfg(not(target_os = "linux"))]
+pub use polling::FsEvents;
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use super::{Changes, POLL_INTERVAL};
+    use anyhow::{Context, Result};
+    use rustix::fd::OwnedFd;
+    use rustix::fs::inotify::{self, CreateFlags, ReadFlags, WatchFlags};
+    use rustix::io::Errno;
+    use std::collections::HashMap;
+    use std::ffi::OsStr;
+    use std::fs;
+    use std::mem::MaybeUninit;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    const EVENT_BUFFER: usize = 64 * 1024;
+    /// Writers usually create, write and close in quick succession; waiting
+    /// briefly lets one batch cover all of it instead of reporting a half-written file.
+    const SETTLE: Duration = Duration::from_millis(50);
+
+    pub struct FsEvents {
+        fd: OwnedFd,
+        dirs: HashMap<i32, PathBuf>,
+        skip: Box<dyn Fn(&Path) -> bool>,
+        buffer: Vec<MaybeUninit<u8>>,
+        /// Set once a directory could not be watched; from then on the tree
+        /// is rescanned every `POLL_INTERVAL`.
+        polling: bool,
+    }
+
+    impl FsEvents {
+        /// Watches every directory under `root` except those `skip` rejects.
+        pub fn new(root: &Path, skip: impl Fn(&Path) -> bool + 'static) -> Result<Self> {
+            let fd = inotify::init(CreateFlags::CLOEXEC).context("Failed to initialize inotify")?;
+            let mut events = Self {
+                fd,
+                dirs: HashMap::new(),
+                skip: Box::new(skip),
+                buffer: vec![MaybeUninit::uninit(); EVENT_BUFFER],
+                polling: false,
+            };
+            events.watch_tree(root)?;
+            Ok(events)
+        }
+
+        /// Blocks until at least one event arrives, then collects events until
+        /// the tree has been quiet for `SETTLE`.
+        pub fn next_changes(&mut self) -> Result<Changes> {
+            if self.polling {
+                std::thread::sleep(POLL_INTERVAL);
+                return Ok(Changes::Everything);
+            }
+            let mut paths = Vec::new();
+            loop {
+                if !self.read_batch(&mut paths)? {
+                    return Ok(Changes::Everything);
+                }
+                std::thread::sleep(SETTLE);
+                if rustix::io::ioctl_fionread(&self.fd)? == 0 {
+                    return Ok(Changes::Paths(paths));
+                }
+            }
+        }
+
+        /// Appends the paths of one read to `paths`; false if events were lost.
+        fn read_batch(&mut self, paths: &mut Vec<PathBuf>) -> Result<bool> {
+            let mut raw = Vec::new();
+            let mut reader = inotify::Reader::new(&self.fd, &mut self.buffer);
+            loop {
+                let event = reader.next().context("Failed to read inotify events")?;
+                let name = event
+                    .file_name()
+                    .map(|name| OsStr::from_bytes(name.to_bytes()).to_os_string());
+                raw.push((event.wd(), event.events(), name));
+                if reader.is_buffer_empty() {
+                    break;
+                }
+            }
+
+            for (wd, flags, name) in raw {
+                if flags.contains(ReadFlags::QUEUE_OVERFLOW) {
+                    return Ok(false);
+                }
+                if flags.contains(ReadFlags::IGNORED) {
+                    self.dirs.remove(&wd);
+                    continue;
+                }
+                let (Some(dir), Some(name)) = (self.dirs.get(&wd), name) else {
+                    continue;
+                };
+                let path = dir.join(name);
+                if (self.skip)(&path) {
+                    continue;
+                }
+                if flags.contains(ReadFlags::ISDIR)
+                    && flags.intersects(ReadFlags::CREATE | ReadFlags::MOVED_TO)
+                {
+                    self.watch_tree(&path)?;
+                }
+                paths.push(path);
+            }
+            Ok(true)
+        }
+
+        fn watch_tree(&mut self, dir: &Path) -> Result<()> {
+            let flags = WatchFlags::CREATE
+                | WatchFlags::DELETE
+                | WatchFlags::MODIFY
+                | WatchFlags::CLOSE_WRITE
+                | WatchFlags::MOVED_FROM
+                | WatchFlags::MOVED_TO
+                | WatchFlags::ATTRIB
+                | WatchFlags::ONLYDIR
+                | WatchFlags::DONT_FOLLOW;
+            if self.polling {
+                return Ok(());
+            }
+            let wd = match inotify::add_watch(&self.fd, dir, flags) {
+                Ok(wd) => wd,
+                // The directory may vanish between the event and the watch.
+                Err(Errno::NOENT | Errno::NOTDIR) => return Ok(()),
+                Err(err) => {
+                    eprintln!(
+                        "Warning: cannot watch {}: {err}; rescanning the whole tree every {}s instead",
+                        dir.display(),
+                        POLL_INTERVAL.as_secs()
+                    );
+                    self.polling = true;
+                    return Ok(());
+                }
+            };
+            self.dirs.insert(wd, dir.to_path_buf());
+
+            let Ok(entries) = fs::read_dir(dir) else {
+                return Ok(());
+            };
+            for entry in entries.filter_map(std::result::Result::ok) {
+                let path = entry.path();
+                if entry.file_type().is_ok_and(|kind| kind.is_dir()) && !(self.skip)(&path) {
+                    self.watch_tree(&path)?;
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod polling {
+    use super::{Changes, POLL_INTERVAL};
+    use anyhow::Result;
+    use std::path::Path;
+
+    pub struct FsEvents;
+
+    impl FsEvents {
+        pub fn new(_root: &Path, _skip: impl Fn(&Path) -> bool + 'static) -> Result<Self> {
+            Ok(Self)
+        }
+
+        pub fn next_changes(&mut self) -> Result<Changes> {
+            std::thread::sleep(POLL_INTERVAL);
+            Ok(Changes::Everything)
+        }
+    }
+}
@@ -1,5 +1,6 @@
+use crate::config::AgtConfig;
 use crate::logging::debug_log;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
@@ -115,6 +116,31 @@ fn find_named_binary(name: &str, env_var: &str, base: &Path) -> Result<PathBuf>
     anyhow::bail!("{name} binary not found; set {env_var} or build it")
 }
 
+/// A git command run against the common dir of `repo`, which works the same
+/// from the bare repository and from any of its worktrees.
+pub fn git_command(repo: &gix::Repository, config: &AgtConfig) -> Command {
+    let mut command = Command::new(&config.git_path);
+    command.arg("--git-dir").arg(repo.common_dir());
+    command
+}
+
+/// Runs git with `args` against `repo` and returns its stdout; fails with
+/// git's stderr if it exits non-zero.
+pub fn run_git(repo: &gix::Repository, config: &AgtConfig, args: &[&str]) -> Result<String> {
+    let output = git_command(repo, config)
+        .args(args)
+        .output()
+        .with_context(|| format!("Failed to run git {}", args[0]))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
 pub fn repo_base_path(repo: &gix::Repository) -> PathBuf {
     repo.work_dir()
         .map(Path::to_path_buf)
@@ -3,15 +3,19 @@ use anyhow::{Context, Result};
 use clap::Parser;
 use std::path::Path;
 
+mod chunking;
 mod cli;
 mod commands;
 mod config;
 mod filter;
+mod fs_events;
 mod gix_cli;
 mod isolation;
 mod logging;
 mod path_util;
 mod scanner;
+mod session_store;
+mod shadow_history;
 mod snapshot;
 
 pub use cli::*;
@@ -49,8 +53,8 @@ fn main() -> Result<()> {
     }
 
     // Handle commands that do not require an existing repository.
-    if let Some(Commands::Setup { store }) = cli.command.clone() {
-        return snapshot::setup(store.as_deref());
+    if let Some(Commands::Setup { store, shared }) = cli.command.clone() {
+        return snapshot::setup(store.as_deref(), shared);
     }
 
     // Handle init command before discovering repo (init doesn't need existing repo)
@@ -62,13 +66,21 @@ fn main() -> Result<()> {
     // Load configuration (from ~/.agtconfig and .agt/config)
     let config = config::AgtConfig::load().with_context(|| "Failed to load AGT configuration")?;
 
-    // Discover repo
-    let repo = gix::discover(".").with_context(|| "Failed to discover Git repository")?;
-
+    // Standalone snapshots use their own store and work outside repositories.
     if let Some(Commands::Snapshot(snapshot_cmd)) = cli.command.clone() {
-        return commands::snapshot::run(&repo, snapshot_cmd, &config);
+        return commands::snapshot::run(snapshot_cmd, &config);
     }
 
+    // Cross-namespace diffs may not need a repository; other diff arguments go to git.
+    if let Some(Commands::Diff { args }) = &cli.command {
+        if let Some(diff_args) = commands::diff::parse(args) {
+            return commands::diff::run(&diff_args, &config);
+        }
+    }
+
+    // Discover repo
+    let repo = discover_repo()?;
+
     // Determine if filtering should be disabled
     let disable_filter = cli.disable_agt || std::env::var("AGT_DISABLE_FILTER").is_ok();
 
@@ -82,8 +94,10 @@ fn main() -> Result<()> {
             timestamp,
             dry_run,
             siblings,
+            label,
         }) => {
             let worktree_path = std::env::current_dir()?;
+            let session_id = commands::session::resolve_session_id(&repo, session_id)?;
             commands::autocommit::run(
                 &repo,
                 &worktree_path,
@@ -91,10 +105,31 @@ fn main() -> Result<()> {
                 timestamp,
                 dry_run,
                 siblings,
+                label.as_deref(),
                 &config,
             )
         }
+        Some(Commands::Checkpoint {
+            command: Some(CheckpointCommands::List { session_id }),
+            ..
+        }) => commands::checkpoint::list(&repo, session_id.as_deref()),
+        Some(Commands::Checkpoint {
+            command: None,
+            label,
+            session_id,
+            force,
+        }) => {
+            let label = label.context("Name the checkpoint with -m <label>")?;
+            commands::checkpoint::create(&repo, &config, session_id.as_deref(), &label, force)
+        }
+        Some(Commands::Rewind { label, session_id }) => {
+            commands::checkpoint::rewind(&repo, &config, session_id.as_deref(), &label)
+        }
         Some(Commands::Snapshot(_)) => unreachable!(),
+        Some(Commands::Diff { args }) => {
+            let git_args: Vec<String> = std::iter::once("diff".to_string()).chain(args).collect();
+            commands::passthrough::run(&git_args, is_git_mode, disable_filter, &config, &repo)
+        }
         Some(Commands::Status) => commands::status::run(&repo, &config),
         None => {
             if cli.args.is_empty() {
@@ -109,6 +144,23 @@ fn main() -> Result<()> {
     }
 }
 
+/// Discovers the repository from the current directory, or, inside a session
+/// folder but outside its sandbox (e.g. `sessions/<id>/xdg`), from the sandbox.
+fn discover_repo() -> Result<gix::Repository> {
+    let discovered = gix::discover(".");
+    if discovered.is_err() {
+        let cwd = std::env::current_dir()?;
+        for dir in cwd.ancestors() {
+            if dir.parent().and_then(Path::file_name) == Some("sessions".as_ref()) {
+                if let Ok(repo) = gix::discover(dir.join("sandbox")) {
+                    return Ok(repo);
+                }
+            }
+        }
+    }
+    discovered.with_context(|| "Failed to discover Git repository")
+}
+
 fn should_show_own_version(args: &[String]) -> bool {
     std::env::var("AGT_SHOW_OWN_VERSION").is_ok()
         && args.iter().any(|arg| arg == "--version" || arg == "-V")
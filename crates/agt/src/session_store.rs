@@ -0,0 +1,449 @@
+//! Session metadata, stored as `agt/sessions/<id>.json` in the bare repository.
+//!
+//! Every command reads and writes session metadata through [`SessionStore`].
+//! Files are replaced atomically, and commands that change a session hold its
+//! advisory lock (`agt/sessions/<id>.lock`) for the whole operation, so
+//! concurrent autocommit, restore and remove cannot interleave. Files written
+//! by older versions are migrated to [`SCHEMA_VERSION`] on load; fields this
+//! version does not know about are carried through unchanged.
+
+use anyhow::{bail, Context, Result};
+use gix::Repository;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Version 1 files have no `schema_version` field.
+pub const SCHEMA_VERSION: u64 = 2;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionMetadata {
+    #[serde(default)]
+    pub schema_version: u64,
+    pub session_id: String,
+    pub branch: String,
+    pub sandbox: String,
+    pub from: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from_spec: Option<String>,
+    pub from_commit: String,
+    pub user_branch: String,
+    pub created_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub isolation: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forked_from: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forked_from_commit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_restore_commit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archived_at: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_check: Option<CheckResult>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ticket: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+    /// Free-form fields set with `--meta` or `agt session meta set`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub meta: BTreeMap<String, String>,
+    /// Fields written by newer versions of agt.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// Outcome of the last `agt session check` of a session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckResult {
+    pub command: String,
+    /// `None` if the command was killed by a signal.
+    pub exit_code: Option<i32>,
+    pub duration_ms: u64,
+    /// Tip of the shadow branch the command ran against.
+    pub commit: String,
+    pub checked_at: u64,
+    /// Captured output, relative to the session folder.
+    pub log: String,
+}
+
+impl CheckResult {
+    pub fn passed(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+impl SessionMetadata {
+    pub fn new(session_id: &str, branch: &str, sandbox: &str, user_branch: &str) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            session_id: session_id.to_string(),
+            branch: branch.to_string(),
+            sandbox: sandbox.to_string(),
+            from: String::new(),
+            from_spec: None,
+            from_commit: String::new(),
+            user_branch: user_branch.to_string(),
+            created_at: 0,
+            profile: None,
+            isolation: None,
+            forked_from: None,
+            forked_from_commit: None,
+            pre_restore_commit: None,
+            archived_at: None,
+            last_check: None,
+            ticket: None,
+            title: None,
+            labels: Vec::new(),
+            meta: BTreeMap::new(),
+            extra: Map::new(),
+        }
+    }
+
+    /// Value of a descriptive field: `ticket`, `title`, `labels` (comma
+    /// separated) or a free-form field.
+    pub fn field(&self, key: &str) -> Option<String> {
+        match key {
+            "ticket" => self.ticket.clone(),
+            "title" => self.title.clone(),
+            "labels" => (!self.labels.is_empty()).then(|| self.labels.join(",")),
+            _ => self.meta.get(key).cloned(),
+        }
+    }
+
+    /// Sets a field read by [`Self::field`]; an empty value removes it.
+    pub fn set_field(&mut self, key: &str, value: &str) -> Result<()> {
+        if key.is_empty() || key.contains(['=', ',']) || key.contains(char::is_whitespace) {
+            bail!("Invalid field name {key:?}");
+        }
+        let value = (!value.is_empty()).then(|| value.to_string());
+        match key {
+            "ticket" => self.ticket = value,
+            "title" => self.title = value,
+            "labels" => {
+                self.labels = value
+                    .iter()
+                    .flat_map(|labels| labels.split(','))
+                    .map(str::trim)
+                    .filter(|label| !label.is_empty())
+                    .map(str::to_string)
+                    .collect();
+            }
+            _ => match value {
+                Some(value) => {
+                    self.meta.insert(key.to_string(), value);
+                }
+                None => {
+                    self.meta.remove(key);
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    pub fn open(repo: &Repository) -> Self {
+        Self::in_dir(repo.common_dir().join("agt/sessions"))
+    }
+
+    pub fn in_dir(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn path(&self, session_id: &str) -> PathBuf {
+        self.dir.join(format!("{session_id}.json"))
+    }
+
+    pub fn exists(&self, session_id: &str) -> bool {
+        self.path(session_id).exists()
+    }
+
+    pub fn load(&self, session_id: &str) -> Result<SessionMetadata> {
+        let path = self.path(session_id);
+        let raw = fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        parse(&raw).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Writes `metadata` atomically. Callers changing an existing session
+    /// should hold its lock from before they loaded it.
+    pub fn save(&self, metadata: &SessionMetadata) -> Result<()> {
+        if metadata.schema_version > SCHEMA_VERSION {
+            bail!(
+                "Session {} was written by a newer agt (schema version {}); upgrade agt",
+                metadata.session_id,
+                metadata.schema_version
+            );
+        }
+        fs::create_dir_all(&self.dir)?;
+        let mut metadata = metadata.clone();
+        metadata.schema_version = SCHEMA_VERSION;
+
+        let path = self.path(&metadata.session_id);
+        let temp = self.dir.join(format!("{}.json.tmp", metadata.session_id));
+        fs::write(&temp, serde_json::to_string_pretty(&metadata)?)
+            .with_context(|| format!("Failed to write {}", temp.display()))?;
+        fs::rename(&temp, &path).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Returns every readable session, sorted by ID. Unreadable files are
+    /// reported and skipped.
+    pub fn list(&self) -> Result<Vec<SessionMetadata>> {
+        let mut sessions = Vec::new();
+        for session_id in self.ids()? {
+            match self.load(&session_id) {
+                Ok(metadata) => sessions.push(metadata),
+                Err(err) => eprintln!("Warning: {err:#}"),
+            }
+        }
+        Ok(sessions)
+    }
+
+    /// IDs of all sessions with a metadata file, readable or not, sorted.
+    pub fn ids(&self) -> Result<Vec<String>> {
+        let mut ids = Vec::new();
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return Ok(ids);
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension() != Some(OsStr::new("json")) {
+                continue;
+            }
+            if let Some(session_id) = path.file_stem().and_then(OsStr::to_str) {
+                ids.push(session_id.to_string());
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    /// Removes the session's metadata. Its lock file is kept: another
+    /// process may be waiting on it, and a new file in its place would let
+    /// two processes hold the lock at once.
+    pub fn remove(&self, session_id: &str) -> Result<bool> {
+        let path = self.path(session_id);
+        if !path.exists() {
+            return Ok(false);
+        }
+        fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+        Ok(true)
+    }
+
+    /// Takes the session's advisory lock, waiting for other agt processes.
+    pub fn lock(&self, session_id: &str) -> Result<SessionLock> {
+        fs::create_dir_all(&self.dir)?;
+        SessionLock::acquire(&self.lock_path(session_id), session_id)
+    }
+
+    /// Takes the locks of two different sessions, in ID order so that two
+    /// processes locking the same pair cannot deadlock. Returns them in
+    /// argument order.
+    pub fn lock_pair(&self, first: &str, second: &str) -> Result<(SessionLock, SessionLock)> {
+        // Locks are per open file, so locking one session twice would deadlock.
+        if first == second {
+            bail!("Cannot lock session {first} twice");
+        }
+        if first < second {
+            let first = self.lock(first)?;
+            Ok((first, self.lock(second)?))
+        } else {
+            let second = self.lock(second)?;
+            Ok((self.lock(first)?, second))
+        }
+    }
+
+    /// Whether another process holds the session's lock right now.
+    pub fn is_locked(&self, session_id: &str) -> bool {
+        let path = self.lock_path(session_id);
+        path.exists() && SessionLock::is_held(&path)
+    }
+
+    fn lock_path(&self, session_id: &str) -> PathBuf {
+        self.dir.join(format!("{session_id}.lock"))
+    }
+}
+
+/// Parses session metadata, migrating it to [`SCHEMA_VERSION`].
+pub fn parse(raw: &[u8]) -> Result<SessionMetadata> {
+    let value: Value = serde_json::from_slice(raw)?;
+    Ok(serde_json::from_value(migrate(value)?)?)
+}
+
+fn migrate(mut value: Value) -> Result<Value> {
+    let version = value
+        .get("schema_version")
+        .and_then(Value::as_u64)
+        .unwrap_or(1);
+    if version > SCHEMA_VERSION {
+        bail!("Written by a newer agt (schema version {version}); upgrade agt");
+    }
+    let fields = value
+        .as_object_mut()
+        .context("Session metadata is not a JSON object")?;
+    if version < 2 {
+        // Sessions forked before profiles were recorded ran with the default profile.
+        fields
+            .entry("profile")
+            .or_insert_with(|| Value::from("default"));
+    }
+    fields.insert("schema_version".to_string(), Value::from(SCHEMA_VERSION));
+    Ok(value)
+}
+
+/// Held for as long as the value lives.
+pub struct SessionLock {
+    #[cfg(unix)]
+    _file: fs::File,
+    #[cfg(not(unix))]
+    _marker: gix::lock::Marker,
+}
+
+impl SessionLock {
+    #[cfg(unix)]
+    fn acquire(path: &Path, session_id: &str) -> Result<Self> {
+        use rustix::fs::{flock, FlockOperation};
+
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        if flock(&file, FlockOperation::NonBlockingLockExclusive).is_err() {
+            eprintln!("Waiting for another agt process to release session {session_id}");
+            flock(&file, FlockOperation::LockExclusive)
+                .with_context(|| format!("Failed to lock {}", path.display()))?;
+        }
+        Ok(Self { _file: file })
+    }
+
+    #[cfg(unix)]
+    fn is_held(path: &Path) -> bool {
+        use rustix::fs::{flock, FlockOperation};
+
+        fs::File::open(path)
+            .is_ok_and(|file| flock(&file, FlockOperation::NonBlockingLockShared).is_err())
+    }
+
+    /// The marker file only exists while the lock is held.
+    #[cfg(not(unix))]
+    fn is_held(_path: &Path) -> bool {
+        true
+    }
+
+    #[cfg(not(unix))]
+    fn acquire(path: &Path, session_id: &str) -> Result<Self> {
+        use gix::lock::acquire::Fail;
+
+        let marker = gix::lock::Marker::acquire_to_hold_resource(
+            path.with_extension(""),
+            Fail::AfterDurationWithBackoff(std::time::Duration::from_secs(300)),
+            None,
+        )
+        .with_context(|| format!("Failed to lock session {session_id}"))?;
+        Ok(Self { _marker: marker })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SessionMetadata, SessionStore, SCHEMA_VERSION};
+    use anyhow::Result;
+    use std::fs;
+
+    #[test]
+    fn migrates_old_files_and_keeps_unknown_fields() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store = SessionStore::in_dir(dir.path().to_path_buf());
+        fs::write(
+            store.path("s1"),
+            r#"{"session_id":"s1","branch":"agtsessions/s1","sandbox":"/x","from":"a",
+                "from_commit":"a","user_branch":"refs/heads/main","created_at":1,
+                "ticket":"ENG-7"}"#,
+        )?;
+
+        let mut metadata = store.load("s1")?;
+        metadata.archived_at = Some(5);
+        store.save(&metadata)?;
+        assert_eq!(metadata.profile.as_deref(), Some("default"));
+
+        let saved: serde_json::Value = serde_json::from_slice(&fs::read(store.path("s1"))?)?;
+        assert_eq!(saved["schema_version"], SCHEMA_VERSION);
+        assert_eq!(saved["ticket"], "ENG-7");
+        assert_eq!(saved["archived_at"], 5);
+        assert_eq!(store.list()?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn sets_and_reads_descriptive_fields() -> Result<()> {
+        let mut metadata = SessionMetadata::new("s1", "agtsessions/s1", "/x", "main");
+        metadata.set_field("ticket", "ENG-7")?;
+        metadata.set_field("labels", "api, urgent,")?;
+        metadata.set_field("reviewer", "alice")?;
+        assert_eq!(metadata.field("ticket").as_deref(), Some("ENG-7"));
+        assert_eq!(metadata.labels, ["api", "urgent"]);
+        assert_eq!(metadata.field("labels").as_deref(), Some("api,urgent"));
+        assert_eq!(metadata.field("reviewer").as_deref(), Some("alice"));
+
+        metadata.set_field("reviewer", "")?;
+        assert_eq!(metadata.field("reviewer"), None);
+        assert!(metadata.set_field("a=b", "c").is_err());
+        assert!(metadata.set_field("", "c").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn remove_keeps_the_lock_file() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store = SessionStore::in_dir(dir.path().to_path_buf());
+        let lock = store.lock("s1")?;
+        store.save(&SessionMetadata::new("s1", "agtsessions/s1", "/x", "main"))?;
+        assert!(store.remove("s1")?);
+        assert!(!store.exists("s1"));
+        assert!(dir.path().join("s1.lock").exists());
+        drop(lock);
+        Ok(())
+    }
+
+    #[test]
+    fn locks_pairs_in_id_order() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store = SessionStore::in_dir(dir.path().to_path_buf());
+        let locks = store.lock_pair("b", "a")?;
+        assert!(store.is_locked("a") && store.is_locked("b"));
+        drop(locks);
+        assert!(!store.is_locked("a") && !store.is_locked("b"));
+        assert!(store.lock_pair("a", "a").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_files_from_newer_versions() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store = SessionStore::in_dir(dir.path().to_path_buf());
+        fs::write(
+            store.path("s1"),
+            r#"{"schema_version":99,"session_id":"s1","branch":"b","sandbox":"/x",
+                "from":"a","from_commit":"a","user_branch":"main","created_at":1}"#,
+        )?;
+        assert!(store.load("s1").is_err());
+        Ok(())
+    }
+}
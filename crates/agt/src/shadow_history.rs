@@ -0,0 +1,313 @@
+//! Walking and selecting shadow commits of a session.
+//!
+//! Shadow history is the first-parent chain of `{branch_prefix}<id>` since
+//! the commit the session was created from. Commits made in the sandbox sit
+//! on the same chain between shadow commits and are skipped.
+
+use crate::config::AgtConfig;
+use crate::gix_cli::run_git;
+use anyhow::{bail, Context, Result};
+use gix::{ObjectId, Repository};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Trailer that names a shadow commit, written by `agt autocommit --label`.
+pub const LABEL_TRAILER: &str = "Agt-Label";
+pub const SESSION_TRAILER: &str = "Agt-Session";
+pub const USER_BRANCH_TRAILER: &str = "Agt-User-Branch";
+const SHADOW_COMMIT_SUBJECT: &str = "agt autocommit";
+/// Session metadata as of each shadow commit, so a shadow branch can describe
+/// its session even when `agt/sessions/<id>.json` is lost.
+pub const SESSION_INFO_PATH: &str = "_agt/session.json";
+pub const SESSION_INFO_DIR: &str = "_agt";
+
+#[derive(Debug, Clone)]
+pub struct ShadowCommit {
+    pub id: ObjectId,
+    /// Commit time in seconds since the Unix epoch.
+    pub time: i64,
+    pub label: Option<String>,
+}
+
+/// How to pick a shadow commit other than by SHA.
+pub enum Selector {
+    /// Newest commit at or before this time.
+    At(i64),
+    /// Newest commit at least this many seconds old.
+    Before(i64),
+    /// Commits to step back along the first-parent chain; 0 is the tip.
+    StepsBack(usize),
+    /// Newest commit carrying this label.
+    Label(String),
+}
+
+/// Returns the shadow commits of `branch_ref` since `from_commit`, newest
+/// first.
+pub fn first_parent_chain(
+    repo: &Repository,
+    config: &AgtConfig,
+    branch_ref: &str,
+    from_commit: &str,
+) -> Result<Vec<ShadowCommit>> {
+    repo.find_reference(branch_ref)
+        .with_context(|| format!("Shadow branch not found: {branch_ref}"))?;
+    let mut chain = Vec::new();
+    for commit in commits_since(repo, config, branch_ref, from_commit)?
+        .iter()
+        .rev()
+    {
+        if !is_shadow_commit(commit)? {
+            continue;
+        }
+        chain.push(ShadowCommit {
+            id: commit.id,
+            time: commit.time()?.seconds,
+            label: trailer(commit.message_raw()?.to_string().as_str(), LABEL_TRAILER),
+        });
+    }
+    Ok(chain)
+}
+
+/// The first-parent commits of `branch_ref` since `from_commit`, oldest
+/// first, including commits made in the sandbox.
+pub fn commits_since<'repo>(
+    repo: &'repo Repository,
+    config: &AgtConfig,
+    branch_ref: &str,
+    from_commit: &str,
+) -> Result<Vec<gix::Commit<'repo>>> {
+    run_git(
+        repo,
+        config,
+        &[
+            "rev-list",
+            "--first-parent",
+            "--reverse",
+            branch_ref,
+            &format!("^{from_commit}"),
+        ],
+    )?
+    .lines()
+    .map(|id| {
+        Ok(repo
+            .find_object(ObjectId::from_hex(id.as_bytes())?)?
+            .try_into_commit()?)
+    })
+    .collect()
+}
+
+/// Whether `commit` was made by `agt autocommit` rather than in the sandbox.
+pub fn is_shadow_commit(commit: &gix::Commit<'_>) -> Result<bool> {
+    Ok(commit.parent_ids().count() >= 2
+        && commit.message()?.summary().as_ref() == SHADOW_COMMIT_SUBJECT.as_bytes())
+}
+
+/// The sandbox as of `commit`: the `sandbox` directory of a shadow commit,
+/// whose tree is rooted at the session folder, or the whole tree of a commit
+/// made in the sandbox.
+pub fn sandbox_tree<'repo>(commit: &gix::Commit<'repo>) -> Result<gix::Tree<'repo>> {
+    let tree = commit.tree()?;
+    if !is_shadow_commit(commit)? {
+        return Ok(tree);
+    }
+    Ok(tree
+        .lookup_entry_by_path(std::path::Path::new("sandbox"))?
+        .with_context(|| format!("Shadow commit {} has no sandbox directory", commit.id))?
+        .object()?
+        .peel_to_tree()?)
+}
+
+/// Picks the commit `selector` refers to from `chain`, returning its index.
+pub fn select(chain: &[ShadowCommit], selector: &Selector) -> Result<usize> {
+    if chain.is_empty() {
+        bail!("Session has no shadow commits yet");
+    }
+    let found = match selector {
+        Selector::At(time) => chain.iter().position(|commit| commit.time <= *time),
+        Selector::Before(seconds) => {
+            let cutoff = now_seconds()? - seconds;
+            chain.iter().position(|commit| commit.time <= cutoff)
+        }
+        Selector::StepsBack(steps) => (*steps < chain.len()).then_some(*steps),
+        Selector::Label(label) => chain
+            .iter()
+            .position(|commit| commit.label.as_deref() == Some(label.as_str())),
+    };
+    found.with_context(|| match selector {
+        Selector::At(_) | Selector::Before(_) => {
+            "No shadow commit that old; the oldest is the session's first autocommit".to_string()
+        }
+        Selector::StepsBack(steps) => format!(
+            "Cannot step back {steps}; the session has {} shadow commits",
+            chain.len()
+        ),
+        Selector::Label(label) => format!("No shadow commit labeled {label}"),
+    })
+}
+
+/// The message of a shadow commit: a fixed subject plus trailers naming the
+/// session, its user branch and, optionally, a label.
+pub fn commit_message(session_id: &str, user_branch: &str, label: Option<&str>) -> String {
+    let mut message = format!(
+        "{SHADOW_COMMIT_SUBJECT}\n\n{SESSION_TRAILER}: {session_id}\n{USER_BRANCH_TRAILER}: {user_branch}\n"
+    );
+    if let Some(label) = label {
+        message.push_str(&format!("{LABEL_TRAILER}: {label}\n"));
+    }
+    message
+}
+
+/// Returns the value of the last `key: value` trailer in `message`.
+pub fn trailer(message: &str, key: &str) -> Option<String> {
+    message.lines().rev().find_map(|line| {
+        line.strip_prefix(key)
+            .and_then(|rest| rest.strip_prefix(':'))
+            .map(|value| value.trim().to_string())
+    })
+}
+
+/// Parses an absolute or relative time such as `2026-10-17 14:05`,
+/// `2026-10-17T14:05:00+02:00` or `2 hours ago`. Times without an offset are local.
+pub fn parse_time(input: &str) -> Result<i64> {
+    if let Ok(time) = gix::date::parse(input, Some(SystemTime::now())) {
+        return Ok(time.seconds);
+    }
+    let offset = gix::date::Time::now_local_or_utc().offset;
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.abs();
+    let zone = format!("{sign}{:02}{:02}", offset / 3600, offset % 3600 / 60);
+    for candidate in [format!("{input}:00 {zone}"), format!("{input} {zone}")] {
+        if let Ok(time) = gix::date::parse(&candidate, None) {
+            return Ok(time.seconds);
+        }
+    }
+    bail!("Invalid time {input}; expected e.g. \"2026-10-17 14:05\"")
+}
+
+/// Parses a duration such as `90s`, `15m`, `2h`, `1d` or `1h30m` into seconds.
+pub fn parse_duration(input: &str) -> Result<i64> {
+    let mut total: i64 = 0;
+    let mut digits = String::new();
+    for ch in input.trim().chars() {
+        if ch.is_ascii_digit() {
+            digits.push(ch);
+            continue;
+        }
+        let unit = match ch {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86_400,
+            'w' => 604_800,
+            _ => bail!("Invalid duration {input}; expected e.g. 15m, 2h or 1h30m"),
+        };
+        let amount: i64 = digits
+            .parse()
+            .with_context(|| format!("Invalid duration {input}; expected e.g. 15m, 2h or 1h30m"))?;
+        total += amount * unit;
+        digits.clear();
+    }
+    if !digits.is_empty() || total == 0 {
+        bail!("Invalid duration {input}; expected e.g. 15m, 2h or 1h30m");
+    }
+    Ok(total)
+}
+
+/// Formats seconds in the units `parse_duration` reads, keeping the two
+/// largest, e.g. `1h5m`.
+pub fn format_duration(seconds: i64) -> String {
+    const UNITS: [(char, i64); 4] = [('d', 86_400), ('h', 3600), ('m', 60), ('s', 1)];
+    let mut rest = seconds.max(0);
+    let mut parts = Vec::new();
+    for (unit, size) in UNITS {
+        if rest >= size && parts.len() < 2 {
+            parts.push(format!("{}{unit}", rest / size));
+            rest %= size;
+        } else if !parts.is_empty() {
+            break;
+        }
+    }
+    if parts.is_empty() {
+        "0s".to_string()
+    } else {
+        parts.concat()
+    }
+}
+
+pub fn format_time(seconds: i64) -> String {
+    let offset = gix::date::Time::now_local_or_utc().offset;
+    gix::date::Time::new(seconds, offset).format(gix::date::time::format::ISO8601)
+}
+
+/// Formats `seconds` as local `YYYY-MM-DD HH:MM`, for tables.
+pub fn format_short_time(seconds: i64) -> String {
+    let offset = gix::date::Time::now_local_or_utc().offset;
+    gix::date::Time::new(seconds, offset)
+        .format(gix::date::time::CustomFormat::new("%Y-%m-%d %H:%M"))
+}
+
+fn now_seconds() -> Result<i64> {
+    Ok(i64::try_from(
+        SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        commit_message, format_duration, parse_duration, select, trailer, Selector, ShadowCommit,
+        LABEL_TRAILER, USER_BRANCH_TRAILER,
+    };
+    use anyhow::Result;
+    use gix::ObjectId;
+
+    fn chain(times: &[i64]) -> Vec<ShadowCommit> {
+        times
+            .iter()
+            .enumerate()
+            .map(|(index, time)| ShadowCommit {
+                id: ObjectId::null(gix::hash::Kind::Sha1),
+                time: *time,
+                label: (index == 2).then(|| "before-refactor".to_string()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn selects_by_time_steps_and_label() -> Result<()> {
+        let chain = chain(&[400, 300, 200, 100]);
+        assert_eq!(select(&chain, &Selector::At(350))?, 1);
+        assert_eq!(select(&chain, &Selector::At(400))?, 0);
+        assert!(select(&chain, &Selector::At(50)).is_err());
+        assert_eq!(select(&chain, &Selector::StepsBack(3))?, 3);
+        assert!(select(&chain, &Selector::StepsBack(4)).is_err());
+        assert_eq!(
+            select(&chain, &Selector::Label("before-refactor".to_string()))?,
+            2
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parses_durations_and_trailers() -> Result<()> {
+        assert_eq!(parse_duration("90s")?, 90);
+        assert_eq!(parse_duration("1h30m")?, 5400);
+        assert_eq!(parse_duration("2d")?, 172_800);
+        assert_eq!(format_duration(5400), "1h30m");
+        assert_eq!(format_duration(3605), "1h");
+        assert_eq!(format_duration(42), "42s");
+        assert_eq!(parse_duration(&format_duration(93_600))?, 93_600);
+        assert!(parse_duration("15").is_err());
+        assert!(parse_duration("5x").is_err());
+        let message = commit_message("s1", "refs/heads/main", Some("green tests"));
+        assert_eq!(
+            trailer(&message, LABEL_TRAILER).as_deref(),
+            Some("green tests")
+        );
+        assert_eq!(
+            trailer(&message, USER_BRANCH_TRAILER).as_deref(),
+            Some("refs/heads/main")
+        );
+        assert_eq!(trailer("agt autocommit", LABEL_TRAILER), None);
+        Ok(())
+    }
+}
@@ -1,11 +1,14 @@
+use crate::chunking;
 use crate::config::AgtConfig;
+use crate::fs_events::{Changes, FsEvents};
 use anyhow::{bail, Context, Result};
 use gix::bstr::BStr;
 use gix::bstr::ByteSlice;
 use gix::object::tree::EntryKind;
 use gix::Repository;
 use gix_object::{compute_hash, Kind, Tree};
-use std::collections::{BTreeSet, HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::ffi::OsStr;
 use std::fs::{self, Metadata};
 use std::io::{self, Cursor, Read, Write};
@@ -14,16 +17,26 @@ use std::process::Command;
 
 const DEFAULT_STORE_DIR: &str = ".agt-snapshots";
 const SNAPSHOT_REF: &str = "refs/heads/agt-snapshots";
+const SHARED_SNAPSHOT_REF_PREFIX: &str = "refs/heads/targets/";
+const SHARED_STORE_MARKER: &str = "agt-shared";
 const MANIFEST_PATH: &str = "meta/manifest.bin";
+const PROVENANCE_PATH: &str = "meta/provenance.json";
 const PAYLOAD_PREFIX: &str = "payload";
+const CHUNKS_PREFIX: &str = "chunks";
 const MANIFEST_MAGIC: &[u8; 8] = b"AGTSNP01";
-const MANIFEST_VERSION: u32 = 1;
+/// Version 2 added the chunk threshold and the per-record chunk index;
+/// version 1 manifests still decode.
+const MANIFEST_VERSION: u32 = 2;
+const DEFAULT_CHUNK_THRESHOLD: u64 = 32 * 1024 * 1024;
 const SNAPSHOT_LIST_WIDTH: usize = 80;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 struct SnapshotManifest {
     target_root: String,
     created_at_ns: u128,
+    /// Files larger than this were stored as chunks. Comparisons against the
+    /// filesystem chunk with the same threshold, so object ids match.
+    chunk_threshold: u64,
     records: Vec<SnapshotRecord>,
 }
 
@@ -42,6 +55,83 @@ struct SnapshotRecord {
     uid: Option<u32>,
     gid: Option<u32>,
     flags: Option<u32>,
+    /// Content chunks of a large file, in order; empty for files stored whole.
+    chunks: Vec<SnapshotChunk>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct SnapshotChunk {
+    object_id: String,
+    size: u64,
+}
+
+/// Files keyed by relative path, with their entry kind and the id of the
+/// blob holding their full content.
+pub type FileEntries = BTreeMap<String, (EntryKind, gix::ObjectId)>;
+
+/// A saved snapshot opened read-only, for comparing with other trees.
+pub struct SnapshotView {
+    store: SnapshotStore,
+    manifest: SnapshotManifest,
+}
+
+impl SnapshotView {
+    pub fn open(spec: &str, target: &Path, store: Option<&Path>, shared: bool) -> Result<Self> {
+        ensure_supported_platform()?;
+        let current_dir = std::env::current_dir()?;
+        let target_root = resolve_target(target);
+        let store_path = discover_store_path(store, shared, &target_root, &current_dir)?;
+        let store = SnapshotStore::open(&store_path, shared, &target_root)?;
+        let tag = store.qualify_tag(spec);
+        let manifest = load_manifest_for_tag(&store.repo, &tag)
+            .with_context(|| format!("Snapshot not found: {tag}"))?;
+        Ok(Self { store, manifest })
+    }
+
+    /// Chunked files are listed by the id their content would have as one blob,
+    /// so they compare equal to the same file in Git or on disk.
+    pub fn entries(&self) -> Result<FileEntries> {
+        let mut entries = FileEntries::new();
+        for record in &self.manifest.records {
+            let id = content_id(&self.store.repo, record)?;
+            entries.insert(record.path.clone(), (record.kind.entry_kind(), id));
+        }
+        Ok(entries)
+    }
+
+    pub fn read(&self, path: &str) -> Result<Vec<u8>> {
+        let record = self
+            .manifest
+            .records
+            .iter()
+            .find(|record| record.path == path)
+            .with_context(|| format!("Snapshot path not found: {path}"))?;
+        if record.chunks.is_empty() {
+            let blob = self
+                .store
+                .repo
+                .find_object(gix::ObjectId::from_hex(record.object_id.as_bytes())?)?;
+            Ok(blob.data.clone())
+        } else {
+            let mut data = Vec::new();
+            write_chunks(&self.store.repo, &record.chunks, &mut data)?;
+            Ok(data)
+        }
+    }
+}
+
+/// Where and how a snapshot was taken, stored next to the manifest.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct SnapshotProvenance {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hostname: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    cwd: String,
+    command: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    git_head: Option<String>,
+    agt_version: String,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -69,37 +159,55 @@ enum PlatformFileId {
 struct PlatformFileId;
 
 pub fn save(
-    repo: &Repository,
     config: &AgtConfig,
     target: &Path,
     store: Option<&Path>,
+    shared: bool,
     message: Option<&str>,
+    if_changed: bool,
 ) -> Result<()> {
     ensure_supported_platform()?;
     let current_dir = std::env::current_dir()?;
     let target_root = target
         .canonicalize()
         .with_context(|| format!("Failed to resolve target {}", target.display()))?;
-    let store_path = resolve_store_path(store, &current_dir)?;
-    warn_if_store_not_ignored(repo, config, &store_path)?;
-    let snapshot_repo = open_or_init_snapshot_repo(&store_path)?;
+    let store_path = discover_store_path(store, shared, &target_root, &current_dir)?;
+    warn_if_store_not_ignored(config, &target_root, &store_path)?;
+    let store = SnapshotStore::open_or_init(&store_path, shared, &target_root)?;
+
+    if if_changed {
+        if let Some(latest_tag) = unchanged_latest_tag(&store, &target_root, &store_path)? {
+            println!("Unchanged snapshot {latest_tag}");
+            println!("Store: {}", store_path.display());
+            return Ok(());
+        }
+    }
 
     let created_at_ns = now_ns();
-    let mut records = capture_records(&snapshot_repo, &target_root, &store_path, true)?;
+    let chunk_threshold = chunk_threshold();
+    let mut records = capture_records(
+        &store.repo,
+        &target_root,
+        &store_path,
+        chunk_threshold,
+        true,
+    )?;
     records.sort_by(|left, right| left.path.cmp(&right.path));
     let manifest = SnapshotManifest {
         target_root: normalize_path(&target_root),
         created_at_ns,
+        chunk_threshold,
         records,
     };
 
-    let tag_name = next_tag_name(&snapshot_repo, created_at_ns)?;
+    let tag_name = store.next_tag_name(created_at_ns)?;
     let message = message
         .map(ToOwned::to_owned)
         .unwrap_or_else(|| format!("snapshot save for {}", target_root.display()));
-    let commit_id = write_snapshot_commit(&snapshot_repo, config, &manifest, &message)?;
+    let provenance = capture_provenance(&current_dir, &target_root);
+    let commit_id = write_snapshot_commit(&store, config, &manifest, &provenance, &message)?;
     let signature = signature(config);
-    snapshot_repo.tag(
+    store.repo.tag(
         &tag_name,
         commit_id.as_ref(),
         Kind::Commit,
@@ -114,9 +222,359 @@ pub fn save(
     Ok(())
 }
 
-pub fn setup(store: Option<&Path>) -> Result<()> {
+/// Returns the latest tag when the target still matches it exactly.
+fn unchanged_latest_tag(
+    store: &SnapshotStore,
+    target_root: &Path,
+    store_path: &Path,
+) -> Result<Option<String>> {
+    let Some(latest_tag) = store.latest_tag()? else {
+        return Ok(None);
+    };
+    let manifest = load_manifest_for_tag(&store.repo, &latest_tag)?;
+    if manifest.target_root != normalize_path(target_root)
+        || has_changes_against_manifest(&store.repo, &manifest, target_root, store_path)?
+    {
+        return Ok(None);
+    }
+    Ok(Some(latest_tag))
+}
+
+fn capture_provenance(current_dir: &Path, target_root: &Path) -> SnapshotProvenance {
+    let git_head = gix::discover(target_root)
+        .ok()
+        .and_then(|repo| repo.head_id().ok().map(|id| id.to_string()));
+    SnapshotProvenance {
+        hostname: hostname(),
+        user: std::env::var("USER")
+            .or_else(|_| std::env::var("USERNAME"))
+            .ok()
+            .filter(|user| !user.is_empty()),
+        cwd: normalize_path(current_dir),
+        command: std::env::args().collect(),
+        git_head,
+        agt_version: env!("AGT_BUILD_VERSION").to_string(),
+    }
+}
+
+fn hostname() -> Option<String> {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+pub fn show(spec: &str, target: &Path, store: Option<&Path>, shared: bool) -> Result<()> {
+    ensure_supported_platform()?;
+    let current_dir = std::env::current_dir()?;
+    let target_root = resolve_target(target);
+    let store_path = discover_store_path(store, shared, &target_root, &current_dir)?;
+    let store = SnapshotStore::open(&store_path, shared, &target_root)?;
+    let tag = store.qualify_tag(spec);
+    let manifest = load_manifest_for_tag(&store.repo, &tag)?;
+
+    println!("Snapshot {tag}");
+    println!("Target: {}", manifest.target_root);
+    println!("Created: {}", manifest.created_at_ns / 1_000_000_000);
+    println!("Files: {}", manifest.records.len());
+    let chunked = chunked_files(&manifest).len();
+    if chunked > 0 {
+        println!("Chunked files: {chunked}");
+    }
+    if let Some(message) = tag_message(&store.repo, &tag) {
+        println!("Message: {message}");
+    }
+    if let Some(provenance) = load_provenance_for_tag(&store.repo, &tag)? {
+        print_provenance(&provenance, "");
+    }
+    Ok(())
+}
+
+pub fn cat(
+    spec: &str,
+    path: &Path,
+    target: &Path,
+    store: Option<&Path>,
+    shared: bool,
+) -> Result<()> {
+    ensure_supported_platform()?;
+    let current_dir = std::env::current_dir()?;
+    let target_root = resolve_target(target);
+    let store_path = discover_store_path(store, shared, &target_root, &current_dir)?;
+    let store = SnapshotStore::open(&store_path, shared, &target_root)?;
+    let tag = store.qualify_tag(spec);
+    let manifest = load_manifest_for_tag(&store.repo, &tag)?;
+    let wanted = normalize_rel_path(path);
+    let record = manifest
+        .records
+        .iter()
+        .find(|record| record.path == wanted)
+        .with_context(|| format!("Snapshot path not found: {}", path.display()))?;
+
+    let mut out = io::BufWriter::new(io::stdout().lock());
+    if record.chunks.is_empty() {
+        let blob = store
+            .repo
+            .find_object(gix::ObjectId::from_hex(record.object_id.as_bytes())?)?
+            .try_into_blob()?;
+        out.write_all(&blob.data)?;
+    } else {
+        write_chunks(&store.repo, &record.chunks, &mut out)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Writes every file of a snapshot under `to`, which must not exist or be
+/// empty, leaving the snapshot's target untouched.
+pub fn export(
+    spec: &str,
+    to: &Path,
+    target: &Path,
+    store: Option<&Path>,
+    shared: bool,
+) -> Result<()> {
+    ensure_supported_platform()?;
     let current_dir = std::env::current_dir()?;
-    let store_path = resolve_store_path(store, &current_dir)?;
+    let target_root = resolve_target(target);
+    let store_path = discover_store_path(store, shared, &target_root, &current_dir)?;
+    let store = SnapshotStore::open(&store_path, shared, &target_root)?;
+    let tag = store.qualify_tag(spec);
+    let manifest = load_manifest_for_tag(&store.repo, &tag)?;
+    if fs::read_dir(to).is_ok_and(|mut entries| entries.next().is_some()) {
+        bail!("{} is not empty", to.display());
+    }
+
+    let commit = store
+        .repo
+        .find_reference(format!("refs/tags/{tag}").as_str())?
+        .peel_to_commit()?;
+    let payload_tree = commit
+        .tree()?
+        .lookup_entry_by_path(Path::new(PAYLOAD_PREFIX))?
+        .context("Snapshot payload tree missing")?;
+    fs::create_dir_all(to)?;
+    restore_tree_to_disk(
+        &store.repo,
+        payload_tree.object_id(),
+        Path::new(""),
+        to,
+        &chunked_files(&manifest),
+    )?;
+    println!(
+        "Exported snapshot {tag} ({} files) to {}",
+        manifest.records.len(),
+        to.display()
+    );
+    Ok(())
+}
+
+fn print_provenance(provenance: &SnapshotProvenance, indent: &str) {
+    if let Some(hostname) = &provenance.hostname {
+        println!("{indent}Host: {hostname}");
+    }
+    if let Some(user) = &provenance.user {
+        println!("{indent}User: {user}");
+    }
+    println!("{indent}Cwd: {}", provenance.cwd);
+    println!("{indent}Command: {}", provenance.command.join(" "));
+    if let Some(git_head) = &provenance.git_head {
+        println!("{indent}Git HEAD: {git_head}");
+    }
+    println!("{indent}agt version: {}", provenance.agt_version);
+}
+
+/// Streams changes against a baseline snapshot as they happen.
+pub fn watch(
+    snapshot: Option<&str>,
+    target: Option<&Path>,
+    store: Option<&Path>,
+    shared: bool,
+    allow: &[String],
+    json: bool,
+    exit_on_violation: bool,
+) -> Result<()> {
+    ensure_supported_platform()?;
+    let current_dir = std::env::current_dir()?;
+    let start = target.map_or_else(|| current_dir.clone(), resolve_target);
+    let store_path = discover_store_path(store, shared, &start, &current_dir)?;
+    let store = SnapshotStore::open(&store_path, shared, &start)?;
+    let (tag, manifest) = match snapshot {
+        Some(snapshot) => {
+            let tag = store.qualify_tag(snapshot);
+            let manifest = load_manifest_for_tag(&store.repo, &tag)?;
+            (tag, manifest)
+        }
+        None => store.latest_snapshot(target.map(|_| start.as_path()))?,
+    };
+    let target_root = match target {
+        Some(_) => start,
+        None => PathBuf::from(&manifest.target_root),
+    };
+
+    let mut watcher = SnapshotWatcher {
+        repo: &store.repo,
+        target_root: &target_root,
+        store_path: &store_path,
+        chunk_threshold: manifest.chunk_threshold,
+        known: manifest
+            .records
+            .iter()
+            .map(|record| (record.path.clone(), (record.kind, record.object_id.clone())))
+            .collect(),
+        allow,
+        json,
+        exit_on_violation,
+    };
+    let skip_store = store_path.clone();
+    let mut events = FsEvents::new(&target_root, move |path| is_skipped_path(path, &skip_store))?;
+    eprintln!("Watching {} against snapshot {tag}", target_root.display());
+
+    // Report anything that changed between the snapshot and the first event.
+    let mut changes = Changes::Everything;
+    loop {
+        watcher.check(changes)?;
+        changes = events.next_changes()?;
+    }
+}
+
+struct SnapshotWatcher<'a> {
+    repo: &'a Repository,
+    target_root: &'a Path,
+    store_path: &'a Path,
+    /// Chunk threshold of the baseline, so content ids are comparable.
+    chunk_threshold: u64,
+    /// Last seen kind and content id per path, starting from the baseline.
+    known: HashMap<String, (RecordKind, String)>,
+    allow: &'a [String],
+    json: bool,
+    exit_on_violation: bool,
+}
+
+impl SnapshotWatcher<'_> {
+    fn check(&mut self, changes: Changes) -> Result<()> {
+        let mut candidates = BTreeSet::new();
+        match changes {
+            Changes::Everything => {
+                candidates.extend(self.known.keys().cloned());
+                for path in tree_files(self.target_root, self.store_path) {
+                    candidates.insert(normalize_rel_path(path.strip_prefix(self.target_root)?));
+                }
+            }
+            Changes::Paths(paths) => {
+                for path in paths {
+                    let Ok(rel_path) = path.strip_prefix(self.target_root) else {
+                        continue;
+                    };
+                    let rel = normalize_rel_path(rel_path);
+                    let prefix = format!("{rel}/");
+                    candidates.extend(
+                        self.known
+                            .keys()
+                            .filter(|known| **known == rel || known.starts_with(&prefix))
+                            .cloned(),
+                    );
+                    if path.is_dir() && !path.is_symlink() {
+                        for file in tree_files(&path, self.store_path) {
+                            candidates
+                                .insert(normalize_rel_path(file.strip_prefix(self.target_root)?));
+                        }
+                    } else {
+                        candidates.insert(rel);
+                    }
+                }
+            }
+        }
+
+        for rel in candidates {
+            let abs_path = self.target_root.join(&rel);
+            if is_skipped_path(&abs_path, self.store_path) {
+                continue;
+            }
+            let current = match fs::symlink_metadata(&abs_path) {
+                Ok(metadata) if !metadata.is_dir() => {
+                    let record = build_record(
+                        self.repo,
+                        self.target_root,
+                        Path::new(&rel),
+                        &abs_path,
+                        &metadata,
+                        self.chunk_threshold,
+                        false,
+                    )?;
+                    Some((record.kind, record.object_id))
+                }
+                _ => None,
+            };
+            let change = match (self.known.get(&rel), &current) {
+                (None, Some(_)) => "A",
+                (Some(_), None) => "D",
+                (Some(before), Some(after)) if before != after => "M",
+                _ => continue,
+            };
+            match current {
+                Some(content) => self.known.insert(rel.clone(), content),
+                None => self.known.remove(&rel),
+            };
+            self.report(change, &rel)?;
+        }
+        Ok(())
+    }
+
+    fn report(&self, change: &str, path: &str) -> Result<()> {
+        let allowed = self.allow.iter().any(|pattern| {
+            gix::glob::wildmatch(
+                pattern.as_bytes().as_bstr(),
+                path.as_bytes().as_bstr(),
+                gix::glob::wildmatch::Mode::NO_MATCH_SLASH_LITERAL,
+            )
+        });
+        if self.json {
+            println!(
+                "{}",
+                serde_json::json!({
+                    "change": change,
+                    "path": path,
+                    "allowed": allowed,
+                    "time_ns": now_ns().to_string(),
+                })
+            );
+        } else if allowed || self.allow.is_empty() {
+            println!("{change} {path}");
+        } else {
+            println!("{change} {path} (not allowed)");
+        }
+        if self.exit_on_violation && !allowed {
+            bail!("Change outside the allowed paths: {path}");
+        }
+        Ok(())
+    }
+}
+
+/// Paths `agt snapshot` never looks at: `.git`, snapshot stores and their contents.
+fn is_skipped_path(path: &Path, store_path: &Path) -> bool {
+    path.starts_with(store_path)
+        || path.components().any(|component| {
+            component.as_os_str() == OsStr::new(".git")
+                || component.as_os_str() == OsStr::new(DEFAULT_STORE_DIR)
+        })
+}
+
+pub fn setup(store: Option<&Path>, shared: bool) -> Result<()> {
+    let current_dir = std::env::current_dir()?;
+    let store_path = if shared && store.is_none() {
+        shared_store_path()?
+    } else {
+        resolve_store_path(store, &current_dir)?
+    };
+
+    if shared {
+        SnapshotStore::open_or_init(&store_path, true, &current_dir)?;
+        println!("Shared snapshot store ready at {}", store_path.display());
+        return Ok(());
+    }
 
     ensure_store_directory(&store_path)?;
 
@@ -129,7 +587,8 @@ pub fn setup(store: Option<&Path>) -> Result<()> {
 }
 
 fn is_timestamp_tag(tag: &str) -> Option<u64> {
-    let digits: String = tag.chars().filter(|c| c.is_ascii_digit()).collect();
+    let leaf = tag.rsplit('/').next().unwrap_or(tag);
+    let digits: String = leaf.chars().filter(|c| c.is_ascii_digit()).collect();
     if digits.len() >= 17 {
         digits.parse::<u64>().ok()
     } else {
@@ -137,40 +596,51 @@ fn is_timestamp_tag(tag: &str) -> Option<u64> {
     }
 }
 
-pub fn check(_repo: &Repository, before: &str, after: &str, store: Option<&Path>) -> Result<()> {
+pub fn check(
+    before: &str,
+    after: &str,
+    target: &Path,
+    store: Option<&Path>,
+    shared: bool,
+) -> Result<()> {
     ensure_supported_platform()?;
     let current_dir = std::env::current_dir()?;
-    let store_path = resolve_store_path(store, &current_dir)?;
-    let snapshot_repo = open_snapshot_repo(&store_path)?;
+    let target_root = resolve_target(target);
+    let store_path = discover_store_path(store, shared, &target_root, &current_dir)?;
+    let store = SnapshotStore::open(&store_path, shared, &target_root)?;
+    let before = store.qualify_tag(before);
+    let after = store.qualify_tag(after);
 
-    let (sorted_before, sorted_after) = match (is_timestamp_tag(before), is_timestamp_tag(after)) {
-        (Some(before_ts), Some(after_ts)) if before_ts > after_ts => {
-            (after.to_string(), before.to_string())
-        }
-        _ => (before.to_string(), after.to_string()),
+    let (sorted_before, sorted_after) = match (is_timestamp_tag(&before), is_timestamp_tag(&after))
+    {
+        (Some(before_ts), Some(after_ts)) if before_ts > after_ts => (after, before),
+        _ => (before, after),
     };
 
-    let before_manifest = load_manifest_for_tag(&snapshot_repo, &sorted_before)?;
-    let after_manifest = load_manifest_for_tag(&snapshot_repo, &sorted_after)?;
-    let diff = diff_manifests(&before_manifest, &after_manifest);
+    let before_manifest = load_manifest_for_tag(&store.repo, &sorted_before)?;
+    let after_manifest = load_manifest_for_tag(&store.repo, &sorted_after)?;
+    let diff = diff_manifests(&store.repo, &before_manifest, &after_manifest);
 
     println!("Comparing {} -> {}", sorted_before, sorted_after);
     emit_diff(&diff);
     Ok(())
 }
 
-pub fn status(_repo: &Repository, store: Option<&Path>, quiet: u8) -> Result<()> {
+pub fn status(target: Option<&Path>, store: Option<&Path>, shared: bool, quiet: u8) -> Result<()> {
     ensure_supported_platform()?;
     let current_dir = std::env::current_dir()?;
-    let store_path = resolve_store_path(store, &current_dir)?;
-    let snapshot_repo = open_snapshot_repo(&store_path)?;
-    let latest_tag = latest_snapshot_tag(&snapshot_repo)?.context("No snapshots found in store")?;
-    let manifest = load_manifest_for_tag(&snapshot_repo, &latest_tag)?;
-    let target_root = PathBuf::from(&manifest.target_root);
+    let start = target.map_or_else(|| current_dir.clone(), resolve_target);
+    let store_path = discover_store_path(store, shared, &start, &current_dir)?;
+    let store = SnapshotStore::open(&store_path, shared, &start)?;
+    let (latest_tag, manifest) = store.latest_snapshot(target.map(|_| start.as_path()))?;
+    let target_root = match target {
+        Some(_) => start,
+        None => PathBuf::from(&manifest.target_root),
+    };
 
     if quiet > 0 {
         let changed =
-            has_changes_against_manifest(&snapshot_repo, &manifest, &target_root, &store_path)?;
+            has_changes_against_manifest(&store.repo, &manifest, &target_root, &store_path)?;
         if quiet > 1 {
             if changed {
                 std::process::exit(1);
@@ -185,9 +655,16 @@ pub fn status(_repo: &Repository, store: Option<&Path>, quiet: u8) -> Result<()>
     let current_manifest = SnapshotManifest {
         target_root: manifest.target_root.clone(),
         created_at_ns: now_ns(),
-        records: capture_records(&snapshot_repo, &target_root, &store_path, false)?,
+        chunk_threshold: manifest.chunk_threshold,
+        records: capture_records(
+            &store.repo,
+            &target_root,
+            &store_path,
+            manifest.chunk_threshold,
+            false,
+        )?,
     };
-    let diff = diff_manifests(&manifest, &current_manifest);
+    let diff = diff_manifests(&store.repo, &manifest, &current_manifest);
     println!("Latest snapshot {latest_tag}");
     emit_diff(&diff);
     if diff.is_empty() {
@@ -196,46 +673,114 @@ pub fn status(_repo: &Repository, store: Option<&Path>, quiet: u8) -> Result<()>
     Ok(())
 }
 
-pub fn list(_repo: &Repository, store: Option<&Path>, quiet: bool) -> Result<()> {
+/// How much `agt snapshot list` prints for each snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListDetail {
+    /// Only the tag (`--quiet`).
+    Tags,
+    /// The tag and its message.
+    Messages,
+    /// The tag, its message and its provenance (`--verbose`).
+    Provenance,
+}
+
+/// Parsed `agt snapshot list` arguments.
+pub struct ListOptions<'a> {
+    pub target: &'a Path,
+    pub store: Option<&'a Path>,
+    pub shared: bool,
+    pub all_targets: bool,
+    pub detail: ListDetail,
+}
+
+pub fn list(options: &ListOptions<'_>) -> Result<()> {
     ensure_supported_platform()?;
+    let all_targets = options.all_targets;
     let current_dir = std::env::current_dir()?;
-    let store_path = resolve_store_path(store, &current_dir)?;
-    let snapshot_repo = open_snapshot_repo(&store_path)?;
+    let target_root = resolve_target(options.target);
+    let store_path =
+        discover_store_path(options.store, options.shared, &target_root, &current_dir)?;
+    let store = SnapshotStore::open(&store_path, options.shared, &target_root)?;
 
     let mut tags: Vec<(String, Option<String>)> = Vec::new();
-    for reference in snapshot_repo.references()?.tags()? {
+    for reference in store.repo.references()?.tags()? {
         let reference = reference.map_err(|err| anyhow::anyhow!(err.to_string()))?;
         let full_name = reference.name().as_bstr().to_string();
         let Some(short_name) = full_name.strip_prefix("refs/tags/") else {
             continue;
         };
-        let message = reference.try_id().and_then(|id| {
-            let object = id.object().ok()?;
-            let tag = object.try_to_tag_ref().ok()?;
-            let message = normalize_snapshot_message(tag.message.as_bstr().to_string());
-            if message.is_empty() {
-                None
-            } else {
-                Some(message)
-            }
-        });
+        if !all_targets && !store.owns_tag(short_name) {
+            continue;
+        }
+        let message = reference.try_id().and_then(|id| annotated_tag_message(&id));
         tags.push((short_name.to_string(), message));
     }
 
     tags.sort_by(|left, right| left.0.cmp(&right.0));
+    let grouped = all_targets && store.namespace.is_some();
+    let mut current_group: Option<&str> = None;
     for (tag, message) in &tags {
-        if quiet {
-            println!("{tag}");
-        } else if let Some(message) = message {
-            println!("{}", format_snapshot_list_line(tag, message));
-        } else {
-            println!("{tag}");
+        if grouped {
+            let group = tag.split_once('/').map_or("", |(namespace, _)| namespace);
+            if current_group != Some(group) {
+                if current_group.is_some() {
+                    println!();
+                }
+                println!("{}", format_target_group_header(&store.repo, &tags, group));
+                current_group = Some(group);
+            }
+        }
+        match message {
+            Some(message) if options.detail != ListDetail::Tags => {
+                println!("{}", format_snapshot_list_line(tag, message));
+            }
+            _ => println!("{tag}"),
+        }
+        if options.detail == ListDetail::Provenance {
+            if let Ok(Some(provenance)) = load_provenance_for_tag(&store.repo, tag) {
+                print_provenance(&provenance, "    ");
+            }
         }
     }
     println!("\n{} snapshot(s)", tags.len());
     Ok(())
 }
 
+fn format_target_group_header(
+    repo: &Repository,
+    tags: &[(String, Option<String>)],
+    group: &str,
+) -> String {
+    let target_root = tags
+        .iter()
+        .rev()
+        .filter(|(tag, _)| tag.split_once('/').map(|(namespace, _)| namespace) == Some(group))
+        .find_map(|(tag, _)| load_manifest_for_tag(repo, tag).ok())
+        .map(|manifest| manifest.target_root);
+    match target_root {
+        Some(target_root) => format!("{target_root} ({group})"),
+        None => format!("({group})"),
+    }
+}
+
+fn annotated_tag_message(id: &gix::Id<'_>) -> Option<String> {
+    let object = id.object().ok()?;
+    let tag = object.try_to_tag_ref().ok()?;
+    let message = normalize_snapshot_message(tag.message.as_bstr().to_string());
+    if message.is_empty() {
+        None
+    } else {
+        Some(message)
+    }
+}
+
+fn tag_message(repo: &Repository, tag: &str) -> Option<String> {
+    let reference = repo
+        .find_reference(format!("refs/tags/{tag}").as_str())
+        .ok()?;
+    annotated_tag_message(&reference.try_id()?)
+}
+
 fn normalize_snapshot_message(message: String) -> String {
     message.replace('\n', " ").trim().to_string()
 }
@@ -257,46 +802,49 @@ fn format_snapshot_list_line(tag: &str, message: &str) -> String {
 }
 
 pub fn restore(
-    _repo: &Repository,
     snapshot: &str,
     target: &Path,
     paths: &[PathBuf],
     store: Option<&Path>,
+    shared: bool,
 ) -> Result<()> {
     ensure_supported_platform()?;
     let current_dir = std::env::current_dir()?;
-    let store_path = resolve_store_path(store, &current_dir)?;
-    let snapshot_repo = open_snapshot_repo(&store_path)?;
+    let target_root = resolve_target(target);
+    let store_path = discover_store_path(store, shared, &target_root, &current_dir)?;
+    let store = SnapshotStore::open(&store_path, shared, &target_root)?;
+    let snapshot_repo = &store.repo;
 
     if paths.is_empty() {
-        ensure_latest_snapshot_is_clean_backup(&snapshot_repo, &store_path)?;
+        ensure_latest_snapshot_is_clean_backup(&store, &store_path)?;
     }
 
+    let snapshot = store.qualify_tag(snapshot);
     let ref_name = format!("refs/tags/{snapshot}");
     let mut tag_ref = snapshot_repo.find_reference(ref_name.as_str())?;
     let commit = tag_ref.peel_to_commit()?;
     let tree = commit.tree()?;
-    let target_root = target
-        .canonicalize()
-        .unwrap_or_else(|_| target.to_path_buf());
     let payload_tree = tree
         .lookup_entry_by_path(Path::new(PAYLOAD_PREFIX))?
         .context("Snapshot payload tree missing")?;
+    let manifest = load_manifest_for_tag(snapshot_repo, &snapshot)?;
+    let chunked = chunked_files(&manifest);
 
     if paths.is_empty() {
         let mut expected_paths = HashSet::new();
         collect_tree_paths(
-            &snapshot_repo,
+            snapshot_repo,
             payload_tree.object_id(),
             PathBuf::new(),
             &mut expected_paths,
         )?;
         remove_paths_not_in_snapshot(&target_root, &expected_paths, Path::new(""), &store_path)?;
         restore_tree_to_disk(
-            &snapshot_repo,
+            snapshot_repo,
             payload_tree.object_id(),
             &PathBuf::new(),
             &target_root,
+            &chunked,
         )?;
     } else {
         let mut restore_entries = Vec::new();
@@ -308,7 +856,7 @@ pub fn restore(
                 .with_context(|| format!("Snapshot path not found: {}", path.display()))?;
             let destination = target_root.join(path);
             if restore_would_clobber(
-                &snapshot_repo,
+                snapshot_repo,
                 entry.object_id(),
                 entry.mode().kind(),
                 path,
@@ -321,7 +869,14 @@ pub fn restore(
         }
 
         for (object_id, kind, path) in restore_entries {
-            restore_entry_to_disk(&snapshot_repo, object_id, kind, &path, &target_root)?;
+            restore_entry_to_disk(
+                snapshot_repo,
+                object_id,
+                kind,
+                &path,
+                &target_root,
+                &chunked,
+            )?;
         }
     }
 
@@ -354,6 +909,83 @@ fn resolve_store_path(store: Option<&Path>, current_dir: &Path) -> Result<PathBu
     absolutize(Path::new(DEFAULT_STORE_DIR), current_dir)
 }
 
+/// Resolves the store for commands that operate on a target tree.
+///
+/// An explicit `--store` or `AGT_SNAPSHOT_STORE` wins, then `--shared`.
+/// Otherwise the nearest `.agt-snapshots` directory at or above `start` is
+/// used, falling back to `.agt-snapshots` in the current directory when none
+/// exists yet.
+fn discover_store_path(
+    store: Option<&Path>,
+    shared: bool,
+    start: &Path,
+    current_dir: &Path,
+) -> Result<PathBuf> {
+    if store.is_some() || std::env::var_os("AGT_SNAPSHOT_STORE").is_some() {
+        return resolve_store_path(store, current_dir);
+    }
+    if shared {
+        return shared_store_path();
+    }
+    let mut candidate = Some(start);
+    while let Some(dir) = candidate {
+        let store_path = dir.join(DEFAULT_STORE_DIR);
+        if store_path.is_dir() {
+            return Ok(store_path);
+        }
+        candidate = dir.parent();
+    }
+    resolve_store_path(None, current_dir)
+}
+
+fn shared_store_path() -> Result<PathBuf> {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| crate::config::home_dir().map(|home| home.join(".local/share")))
+        .context("Unable to locate a data directory for the shared snapshot store")?;
+    Ok(data_home.join("agt/snapshots"))
+}
+
+/// Namespace used for a target inside a shared store: the directory name
+/// plus a short hash of the full path, so it is both readable and unique.
+fn target_namespace(target_root: &Path) -> String {
+    let digest = compute_hash(
+        gix::hash::Kind::Sha1,
+        Kind::Blob,
+        normalize_path(target_root).as_bytes(),
+    )
+    .to_hex_with_len(12)
+    .to_string();
+    let slug: String = target_root
+        .file_name()
+        .map(|name| {
+            name.to_string_lossy()
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                        c
+                    } else {
+                        '-'
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let slug = slug.trim_matches('-');
+    if slug.is_empty() {
+        digest
+    } else {
+        format!("{slug}-{digest}")
+    }
+}
+
+fn resolve_target(target: &Path) -> PathBuf {
+    target
+        .canonicalize()
+        .unwrap_or_else(|_| target.to_path_buf())
+}
+
 fn absolutize(path: &Path, base: &Path) -> Result<PathBuf> {
     Ok(if path.is_absolute() {
         path.to_path_buf()
@@ -362,6 +994,129 @@ fn absolutize(path: &Path, base: &Path) -> Result<PathBuf> {
     })
 }
 
+/// An opened snapshot store, scoped to one target when the store is shared.
+///
+/// Per-directory stores keep the historical layout: tags at the top level and a
+/// single `agt-snapshots` branch. Shared stores put each target's tags under
+/// `<namespace>/` and its history on `refs/heads/targets/<namespace>`.
+struct SnapshotStore {
+    repo: Repository,
+    namespace: Option<String>,
+}
+
+impl SnapshotStore {
+    fn open(path: &Path, shared: bool, target_root: &Path) -> Result<Self> {
+        let repo = open_snapshot_repo(path)?;
+        if shared && !path.join(SHARED_STORE_MARKER).exists() {
+            bail!(
+                "Snapshot store {} is not a shared store; run `agt setup --shared` first",
+                path.display()
+            );
+        }
+        Ok(Self::scoped(repo, path, target_root))
+    }
+
+    fn open_or_init(path: &Path, shared: bool, target_root: &Path) -> Result<Self> {
+        let repo = open_or_init_snapshot_repo(path)?;
+        let marker = path.join(SHARED_STORE_MARKER);
+        if shared && !marker.exists() {
+            if repo.references()?.all()?.next().is_some() {
+                bail!(
+                    "Snapshot store {} already holds per-directory snapshots and cannot be shared",
+                    path.display()
+                );
+            }
+            fs::write(&marker, "")?;
+        }
+        Ok(Self::scoped(repo, path, target_root))
+    }
+
+    fn scoped(repo: Repository, path: &Path, target_root: &Path) -> Self {
+        let namespace = path
+            .join(SHARED_STORE_MARKER)
+            .exists()
+            .then(|| target_namespace(target_root));
+        Self { repo, namespace }
+    }
+
+    fn branch_ref(&self) -> String {
+        match &self.namespace {
+            Some(namespace) => format!("{SHARED_SNAPSHOT_REF_PREFIX}{namespace}"),
+            None => SNAPSHOT_REF.to_string(),
+        }
+    }
+
+    /// Accepts a bare tag for the current target as well as a fully
+    /// namespaced `<namespace>/<tag>` name.
+    fn qualify_tag(&self, tag: &str) -> String {
+        match &self.namespace {
+            Some(namespace) if !tag.contains('/') => format!("{namespace}/{tag}"),
+            _ => tag.to_string(),
+        }
+    }
+
+    fn owns_tag(&self, tag: &str) -> bool {
+        match &self.namespace {
+            Some(namespace) => tag
+                .strip_prefix(namespace.as_str())
+                .is_some_and(|rest| rest.starts_with('/')),
+            None => true,
+        }
+    }
+
+    fn next_tag_name(&self, created_at_ns: u128) -> Result<String> {
+        let mut candidate = created_at_ns;
+        loop {
+            let name = self.qualify_tag(&format!("{candidate:020}"));
+            let ref_name = format!("refs/tags/{name}");
+            if self.repo.find_reference(ref_name.as_str()).is_err() {
+                return Ok(name);
+            }
+            candidate += 1;
+        }
+    }
+
+    fn latest_tag(&self) -> Result<Option<String>> {
+        Ok(self.tags()?.into_iter().max())
+    }
+
+    /// The latest snapshot taken of `target_root`, or of any target if `None`.
+    /// A store found by walking up from a directory may hold snapshots of its
+    /// parents and siblings too.
+    fn latest_snapshot(&self, target_root: Option<&Path>) -> Result<(String, SnapshotManifest)> {
+        let mut tags = self.tags()?;
+        if tags.is_empty() {
+            bail!("No snapshots found in store");
+        }
+        tags.sort_unstable_by(|left, right| right.cmp(left));
+        for tag in tags {
+            let manifest = load_manifest_for_tag(&self.repo, &tag)?;
+            if target_root.is_none_or(|root| manifest.target_root == normalize_path(root)) {
+                return Ok((tag, manifest));
+            }
+        }
+        bail!(
+            "No snapshot of {} found in store",
+            target_root.unwrap_or(Path::new("")).display()
+        )
+    }
+
+    /// Short names of the tags belonging to this store's target namespace.
+    fn tags(&self) -> Result<Vec<String>> {
+        let mut tags = Vec::new();
+        for reference in self.repo.references()?.tags()? {
+            let reference = reference.map_err(|err| anyhow::anyhow!(err.to_string()))?;
+            let full_name = reference.name().as_bstr().to_string();
+            if let Some(short_name) = full_name.strip_prefix("refs/tags/") {
+                if self.owns_tag(short_name) {
+                    tags.push(short_name.to_string());
+                }
+            }
+        }
+        Ok(tags)
+    }
+}
+
 fn open_or_init_snapshot_repo(path: &Path) -> Result<Repository> {
     if path.exists() {
         if let Ok(repo) = gix::open(path) {
@@ -405,10 +1160,13 @@ fn open_snapshot_repo(path: &Path) -> Result<Repository> {
 }
 
 fn warn_if_store_not_ignored(
-    repo: &Repository,
     config: &AgtConfig,
+    target_root: &Path,
     store_path: &Path,
 ) -> Result<()> {
+    let Ok(repo) = gix::discover(target_root) else {
+        return Ok(());
+    };
     let Some(work_dir) = repo.work_dir() else {
         return Ok(());
     };
@@ -515,6 +1273,7 @@ fn capture_records(
     repo: &Repository,
     target_root: &Path,
     store_path: &Path,
+    chunk_threshold: u64,
     write_blobs: bool,
 ) -> Result<Vec<SnapshotRecord>> {
     let store_for_walk = store_path.to_path_buf();
@@ -553,6 +1312,7 @@ fn capture_records(
             rel_path,
             &path,
             &metadata,
+            chunk_threshold,
             write_blobs,
         )?);
     }
@@ -566,11 +1326,13 @@ fn build_record(
     rel_path: &Path,
     abs_path: &Path,
     metadata: &Metadata,
+    chunk_threshold: u64,
     write_blobs: bool,
 ) -> Result<SnapshotRecord> {
     let parent = abs_path.parent().unwrap_or(target_root);
     let rel = normalize_rel_path(rel_path);
     let file_type = metadata.file_type();
+    let mut chunks = Vec::new();
     let (kind, object_id, size) = if file_type.is_symlink() {
         let target = fs::read_link(abs_path)?;
         let data = target
@@ -584,6 +1346,18 @@ fn build_record(
             compute_hash(repo.object_hash(), Kind::Blob, &data).to_string()
         };
         (RecordKind::Symlink, object_id, data.len() as u64)
+    } else if metadata.len() > chunk_threshold {
+        let file = fs::File::open(abs_path)
+            .with_context(|| format!("Failed to open {}", abs_path.display()))?;
+        chunks = store_chunks(repo, file, write_blobs)?;
+        let index = chunk_index_bytes(&chunks);
+        let object_id = if write_blobs {
+            repo.write_blob(&index)?.to_string()
+        } else {
+            compute_hash(repo.object_hash(), Kind::Blob, &index).to_string()
+        };
+        let size = chunks.iter().map(|chunk| chunk.size).sum();
+        (file_record_kind(metadata), object_id, size)
     } else {
         let data = fs::read(abs_path)?;
         let object_id = if write_blobs {
@@ -591,12 +1365,7 @@ fn build_record(
         } else {
             compute_hash(repo.object_hash(), Kind::Blob, &data).to_string()
         };
-        let kind = if is_executable(metadata) {
-            RecordKind::Executable
-        } else {
-            RecordKind::File
-        };
-        (kind, object_id, metadata.len())
+        (file_record_kind(metadata), object_id, metadata.len())
     };
 
     Ok(SnapshotRecord {
@@ -613,15 +1382,87 @@ fn build_record(
         uid: metadata_uid(metadata),
         gid: metadata_gid(metadata),
         flags: metadata_flags(metadata),
+        chunks,
     })
 }
 
-fn write_snapshot_commit(
+fn file_record_kind(metadata: &Metadata) -> RecordKind {
+    if is_executable(metadata) {
+        RecordKind::Executable
+    } else {
+        RecordKind::File
+    }
+}
+
+/// Files larger than this are stored as content-defined chunks by
+/// `agt snapshot save`.
+fn chunk_threshold() -> u64 {
+    std::env::var("AGT_SNAPSHOT_CHUNK_THRESHOLD")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_CHUNK_THRESHOLD)
+}
+
+fn store_chunks(
     repo: &Repository,
+    reader: impl Read,
+    write_blobs: bool,
+) -> Result<Vec<SnapshotChunk>> {
+    let mut chunks = Vec::new();
+    chunking::for_each_chunk(io::BufReader::new(reader), |data| {
+        let object_id = if write_blobs {
+            repo.write_blob(data)?.to_string()
+        } else {
+            compute_hash(repo.object_hash(), Kind::Blob, data).to_string()
+        };
+        chunks.push(SnapshotChunk {
+            object_id,
+            size: data.len() as u64,
+        });
+        Ok(())
+    })?;
+    Ok(chunks)
+}
+
+/// The payload entry of a chunked file is this index, one `<oid> <size>` line per chunk.
+fn chunk_index_bytes(chunks: &[SnapshotChunk]) -> Vec<u8> {
+    let mut index = Vec::new();
+    for chunk in chunks {
+        index.extend_from_slice(format!("{} {}\n", chunk.object_id, chunk.size).as_bytes());
+    }
+    index
+}
+
+/// Streams the chunks of a file, in order, into `out`.
+fn write_chunks(repo: &Repository, chunks: &[SnapshotChunk], out: &mut impl Write) -> Result<()> {
+    for chunk in chunks {
+        let blob = repo
+            .find_object(gix::ObjectId::from_hex(chunk.object_id.as_bytes())?)?
+            .try_into_blob()?;
+        out.write_all(&blob.data)?;
+    }
+    Ok(())
+}
+
+/// Chunk lists keyed by payload path, for reassembling chunked files on restore.
+fn chunked_files(manifest: &SnapshotManifest) -> HashMap<PathBuf, &[SnapshotChunk]> {
+    manifest
+        .records
+        .iter()
+        .filter(|record| !record.chunks.is_empty())
+        .map(|record| (PathBuf::from(&record.path), record.chunks.as_slice()))
+        .collect()
+}
+
+fn write_snapshot_commit(
+    store: &SnapshotStore,
     config: &AgtConfig,
     manifest: &SnapshotManifest,
+    provenance: &SnapshotProvenance,
     message: &str,
 ) -> Result<gix::ObjectId> {
+    let repo = &store.repo;
+    let branch_ref = store.branch_ref();
     let empty_tree = repo.write_object(Tree::empty())?.detach();
     let mut editor = repo.edit_tree(empty_tree)?;
 
@@ -631,14 +1472,27 @@ fn write_snapshot_commit(
             record.kind.entry_kind(),
             gix::ObjectId::from_hex(record.object_id.as_bytes())?,
         )?;
+        // Keep chunk blobs reachable from the snapshot commit.
+        for chunk in &record.chunks {
+            let (fanout, rest) = chunk.object_id.split_at(2);
+            editor.upsert(
+                format!("{CHUNKS_PREFIX}/{fanout}/{rest}"),
+                EntryKind::Blob,
+                gix::ObjectId::from_hex(chunk.object_id.as_bytes())?,
+            )?;
+        }
     }
 
     let manifest_bytes = manifest.encode()?;
     let manifest_id = repo.write_blob(&manifest_bytes)?.detach();
     editor.upsert(MANIFEST_PATH, EntryKind::Blob, manifest_id)?;
+    let provenance_id = repo
+        .write_blob(serde_json::to_vec_pretty(provenance)?)?
+        .detach();
+    editor.upsert(PROVENANCE_PATH, EntryKind::Blob, provenance_id)?;
     let tree_id = editor.write()?.detach();
 
-    let parents = if let Ok(mut existing) = repo.find_reference(SNAPSHOT_REF) {
+    let parents = if let Ok(mut existing) = repo.find_reference(branch_ref.as_str()) {
         vec![existing.peel_to_commit()?.id]
     } else {
         Vec::new()
@@ -646,40 +1500,10 @@ fn write_snapshot_commit(
 
     let sig = signature(config);
     Ok(repo
-        .commit_as(sig, sig, SNAPSHOT_REF, message, tree_id, parents)?
+        .commit_as(sig, sig, branch_ref.as_str(), message, tree_id, parents)?
         .detach())
 }
 
-fn next_tag_name(repo: &Repository, created_at_ns: u128) -> Result<String> {
-    let mut candidate = created_at_ns;
-    loop {
-        let name = format!("{candidate:020}");
-        let ref_name = format!("refs/tags/{name}");
-        if repo.find_reference(ref_name.as_str()).is_err() {
-            return Ok(name);
-        }
-        candidate += 1;
-    }
-}
-
-fn latest_snapshot_tag(repo: &Repository) -> Result<Option<String>> {
-    let mut latest = None;
-    for reference in repo.references()?.tags()? {
-        let reference = reference.map_err(|err| anyhow::anyhow!(err.to_string()))?;
-        let full_name = reference.name().as_bstr().to_string();
-        let Some(short_name) = full_name.strip_prefix("refs/tags/") else {
-            continue;
-        };
-        if latest
-            .as_ref()
-            .is_none_or(|current: &String| short_name > current.as_str())
-        {
-            latest = Some(short_name.to_string());
-        }
-    }
-    Ok(latest)
-}
-
 fn load_manifest_for_tag(repo: &Repository, tag: &str) -> Result<SnapshotManifest> {
     let ref_name = format!("refs/tags/{tag}");
     let mut tag_ref = repo.find_reference(ref_name.as_str())?;
@@ -694,7 +1518,25 @@ fn load_manifest_for_tag(repo: &Repository, tag: &str) -> Result<SnapshotManifes
     SnapshotManifest::decode(&blob.data)
 }
 
-fn diff_manifests(before: &SnapshotManifest, after: &SnapshotManifest) -> SnapshotDiff {
+/// Snapshots saved before provenance was recorded have no provenance blob.
+fn load_provenance_for_tag(repo: &Repository, tag: &str) -> Result<Option<SnapshotProvenance>> {
+    let ref_name = format!("refs/tags/{tag}");
+    let mut tag_ref = repo.find_reference(ref_name.as_str())?;
+    let tree = tag_ref.peel_to_commit()?.tree()?;
+    let Some(entry) = tree.lookup_entry_by_path(Path::new(PROVENANCE_PATH))? else {
+        return Ok(None);
+    };
+    let blob = repo.find_object(entry.object_id())?.try_into_blob()?;
+    Ok(Some(
+        serde_json::from_slice(&blob.data).context("Failed to parse snapshot provenance")?,
+    ))
+}
+
+fn diff_manifests(
+    repo: &Repository,
+    before: &SnapshotManifest,
+    after: &SnapshotManifest,
+) -> SnapshotDiff {
     let before_map: HashMap<&str, &SnapshotRecord> = before
         .records
         .iter()
@@ -715,7 +1557,7 @@ fn diff_manifests(before: &SnapshotManifest, after: &SnapshotManifest) -> Snapsh
             None => {
                 deleted.insert((*path).to_string());
             }
-            Some(after_record) if *before_map[path] != **after_record => {
+            Some(after_record) if !same_record(repo, before_map[path], after_record) => {
                 modified.insert((*path).to_string());
             }
             Some(_) => {}
@@ -735,7 +1577,63 @@ fn diff_manifests(before: &SnapshotManifest, after: &SnapshotManifest) -> Snapsh
     }
 }
 
-fn emit_diff(diff: &SnapshotDiff) {
+/// Whether two records describe the same file. A file stored whole in one
+/// snapshot and chunked in another, saved with a different chunk threshold,
+/// is compared by the id of its full content.
+fn same_record(repo: &Repository, before: &SnapshotRecord, after: &SnapshotRecord) -> bool {
+    if before == after {
+        return true;
+    }
+    if before.chunks.is_empty() == after.chunks.is_empty() {
+        return false;
+    }
+    match (content_id(repo, before), content_id(repo, after)) {
+        (Ok(before_id), Ok(after_id)) if before_id == after_id => {
+            let without_content = |record: &SnapshotRecord| SnapshotRecord {
+                object_id: String::new(),
+                chunks: Vec::new(),
+                ..record.clone()
+            };
+            without_content(before) == without_content(after)
+        }
+        _ => false,
+    }
+}
+
+/// The id a record's content has as one blob; chunked records hash their
+/// chunks, which must be in the store.
+fn content_id(repo: &Repository, record: &SnapshotRecord) -> Result<gix::ObjectId> {
+    if record.chunks.is_empty() {
+        return Ok(gix::ObjectId::from_hex(record.object_id.as_bytes())?);
+    }
+    let mut hasher = gix_features::hash::hasher(repo.object_hash());
+    hasher.update(&gix_object::encode::loose_header(Kind::Blob, record.size));
+    for chunk in &record.chunks {
+        let blob = repo.find_object(gix::ObjectId::from_hex(chunk.object_id.as_bytes())?)?;
+        hasher.update(&blob.data);
+    }
+    Ok(hasher.digest().into())
+}
+
+/// Compares two file listings by entry kind and content id.
+pub fn diff_entries(before: &FileEntries, after: &FileEntries) -> SnapshotDiff {
+    let mut diff = SnapshotDiff::default();
+    for (path, entry) in before {
+        match after.get(path) {
+            None => diff.deleted.push(path.clone()),
+            Some(after_entry) if after_entry != entry => diff.modified.push(path.clone()),
+            Some(_) => {}
+        }
+    }
+    diff.added = after
+        .keys()
+        .filter(|path| !before.contains_key(*path))
+        .cloned()
+        .collect();
+    diff
+}
+
+pub fn emit_diff(diff: &SnapshotDiff) {
     for path in &diff.added {
         println!("A {path}");
     }
@@ -747,6 +1645,64 @@ fn emit_diff(diff: &SnapshotDiff) {
     }
 }
 
+/// Files under `root` as `agt snapshot save` sees them: ignored files are
+/// included, `.git` and the snapshot store are skipped.
+pub fn filesystem_entries(root: &Path) -> Result<FileEntries> {
+    let current_dir = std::env::current_dir()?;
+    let store_path = discover_store_path(None, false, root, &current_dir)?;
+    let mut entries = FileEntries::new();
+
+    for path in tree_files(root, &store_path) {
+        let metadata = fs::symlink_metadata(&path)?;
+        let rel_path = normalize_rel_path(path.strip_prefix(root)?);
+        let file_entry = if metadata.file_type().is_symlink() {
+            let target = fs::read_link(&path)?;
+            let data = target.as_os_str().to_string_lossy().into_owned();
+            (
+                EntryKind::Link,
+                compute_hash(gix::hash::Kind::Sha1, Kind::Blob, data.as_bytes()),
+            )
+        } else {
+            let mut file = io::BufReader::new(fs::File::open(&path)?);
+            let id = gix_object::compute_stream_hash(
+                gix::hash::Kind::Sha1,
+                Kind::Blob,
+                &mut file,
+                metadata.len(),
+                &mut gix_features::progress::Discard,
+                &std::sync::atomic::AtomicBool::new(false),
+            )
+            .with_context(|| format!("Failed to hash {}", path.display()))?;
+            (file_record_kind(&metadata).entry_kind(), id)
+        };
+        entries.insert(rel_path, file_entry);
+    }
+
+    Ok(entries)
+}
+
+/// Non-directory paths under `dir`, skipping `.git` and snapshot stores.
+fn tree_files(dir: &Path, store_path: &Path) -> Vec<PathBuf> {
+    let store_for_walk = store_path.to_path_buf();
+    jwalk::WalkDir::new(dir)
+        .skip_hidden(false)
+        .process_read_dir(move |_depth, path, _state, children| {
+            children.retain(|entry| {
+                entry.as_ref().map_or(true, |dir_entry| {
+                    dir_entry.file_name != OsStr::new(".git")
+                        && dir_entry.file_name != OsStr::new(DEFAULT_STORE_DIR)
+                        && path.join(&dir_entry.file_name) != store_for_walk
+                })
+            });
+        })
+        .into_iter()
+        .filter_map(std::result::Result::ok)
+        .filter(|entry| !entry.file_type().is_dir())
+        .map(|entry| entry.path())
+        .filter(|path| !path.starts_with(store_path))
+        .collect()
+}
+
 fn has_changes_against_manifest(
     repo: &Repository,
     manifest: &SnapshotManifest,
@@ -793,6 +1749,7 @@ fn has_changes_against_manifest(
             Path::new(&rel_path),
             &path,
             &metadata,
+            manifest.chunk_threshold,
             false,
         )?;
         if &current != expected_record {
@@ -803,11 +1760,11 @@ fn has_changes_against_manifest(
     Ok(!expected.is_empty())
 }
 
-fn ensure_latest_snapshot_is_clean_backup(repo: &Repository, store_path: &Path) -> Result<()> {
-    let latest_tag = latest_snapshot_tag(repo)?.context("No snapshots found in store")?;
-    let manifest = load_manifest_for_tag(repo, &latest_tag)?;
+fn ensure_latest_snapshot_is_clean_backup(store: &SnapshotStore, store_path: &Path) -> Result<()> {
+    let latest_tag = store.latest_tag()?.context("No snapshots found in store")?;
+    let manifest = load_manifest_for_tag(&store.repo, &latest_tag)?;
     let target_root = PathBuf::from(&manifest.target_root);
-    if has_changes_against_manifest(repo, &manifest, &target_root, store_path)? {
+    if has_changes_against_manifest(&store.repo, &manifest, &target_root, store_path)? {
         bail!(
             "Full restore requires the latest snapshot ({latest_tag}) to match the current filesystem; run `agt snapshot save` first"
         );
@@ -859,6 +1816,7 @@ fn restore_tree_to_disk(
     tree_id: gix::ObjectId,
     prefix: &Path,
     disk_root: &Path,
+    chunked: &HashMap<PathBuf, &[SnapshotChunk]>,
 ) -> Result<()> {
     use gix_object::TreeRefIter;
     use gix_path::from_byte_slice;
@@ -873,6 +1831,7 @@ fn restore_tree_to_disk(
             entry.mode.kind(),
             &entry_path,
             disk_root,
+            chunked,
         )?;
     }
     Ok(())
@@ -884,12 +1843,13 @@ fn restore_entry_to_disk(
     kind: EntryKind,
     relative_path: &Path,
     disk_root: &Path,
+    chunked: &HashMap<PathBuf, &[SnapshotChunk]>,
 ) -> Result<()> {
     let disk_path = disk_root.join(relative_path);
     match kind {
         EntryKind::Tree => {
             fs::create_dir_all(&disk_path)?;
-            restore_tree_to_disk(repo, object_id, relative_path, disk_root)
+            restore_tree_to_disk(repo, object_id, relative_path, disk_root, chunked)
         }
         EntryKind::Link => {
             let blob = repo.find_object(object_id)?.try_into_blob()?;
@@ -907,11 +1867,17 @@ fn restore_entry_to_disk(
             Ok(())
         }
         EntryKind::Blob | EntryKind::BlobExecutable => {
-            let blob = repo.find_object(object_id)?.try_into_blob()?;
             if let Some(parent) = disk_path.parent() {
                 fs::create_dir_all(parent)?;
             }
-            fs::write(&disk_path, &blob.data)?;
+            if let Some(chunks) = chunked.get(relative_path) {
+                let mut file = io::BufWriter::new(fs::File::create(&disk_path)?);
+                write_chunks(repo, chunks, &mut file)?;
+                file.flush()?;
+            } else {
+                let blob = repo.find_object(object_id)?.try_into_blob()?;
+                fs::write(&disk_path, &blob.data)?;
+            }
             #[cfg(unix)]
             if kind == EntryKind::BlobExecutable {
                 use std::os::unix::fs::PermissionsExt;
@@ -1131,6 +2097,7 @@ impl SnapshotManifest {
         out.extend_from_slice(&MANIFEST_VERSION.to_le_bytes());
         out.extend_from_slice(&self.created_at_ns.to_le_bytes());
         write_string(&mut out, &self.target_root)?;
+        out.extend_from_slice(&self.chunk_threshold.to_le_bytes());
         out.extend_from_slice(&(self.records.len() as u32).to_le_bytes());
         for record in &self.records {
             out.push(record.kind.as_u8());
@@ -1146,6 +2113,11 @@ impl SnapshotManifest {
             write_opt_u32(&mut out, record.uid);
             write_opt_u32(&mut out, record.gid);
             write_opt_u32(&mut out, record.flags);
+            out.extend_from_slice(&(record.chunks.len() as u32).to_le_bytes());
+            for chunk in &record.chunks {
+                write_string(&mut out, &chunk.object_id)?;
+                out.extend_from_slice(&chunk.size.to_le_bytes());
+            }
         }
         Ok(out)
     }
@@ -1159,19 +2131,25 @@ impl SnapshotManifest {
         }
 
         let version = read_u32(&mut cursor)?;
-        if version != MANIFEST_VERSION {
+        if version == 0 || version > MANIFEST_VERSION {
             bail!("Unsupported snapshot manifest version {version}");
         }
 
         let created_at_ns = read_u128(&mut cursor)?;
         let target_root = read_string(&mut cursor)?;
+        let chunk_threshold = if version >= 2 {
+            read_u64(&mut cursor)?
+        } else {
+            // Nothing was chunked before version 2.
+            u64::MAX
+        };
         let record_count = read_u32(&mut cursor)?;
         let mut records = Vec::with_capacity(record_count as usize);
 
         for _ in 0..record_count {
             let mut kind = [0_u8; 1];
             cursor.read_exact(&mut kind)?;
-            records.push(SnapshotRecord {
+            let mut record = SnapshotRecord {
                 path: read_string(&mut cursor)?,
                 kind: RecordKind::from_u8(kind[0])?,
                 object_id: read_string(&mut cursor)?,
@@ -1185,22 +2163,34 @@ impl SnapshotManifest {
                 uid: read_opt_u32(&mut cursor)?,
                 gid: read_opt_u32(&mut cursor)?,
                 flags: read_opt_u32(&mut cursor)?,
-            });
+                chunks: Vec::new(),
+            };
+            if version >= 2 {
+                let chunk_count = read_u32(&mut cursor)?;
+                for _ in 0..chunk_count {
+                    record.chunks.push(SnapshotChunk {
+                        object_id: read_string(&mut cursor)?,
+                        size: read_u64(&mut cursor)?,
+                    });
+                }
+            }
+            records.push(record);
         }
 
         Ok(Self {
             target_root,
             created_at_ns,
+            chunk_threshold,
             records,
         })
     }
 }
 
 #[derive(Default)]
-struct SnapshotDiff {
-    added: Vec<String>,
-    deleted: Vec<String>,
-    modified: Vec<String>,
+pub struct SnapshotDiff {
+    pub added: Vec<String>,
+    pub deleted: Vec<String>,
+    pub modified: Vec<String>,
 }
 
 impl SnapshotDiff {
@@ -1365,7 +2355,7 @@ fn confirm_overwrite(path: &Path) -> Result<bool> {
 
 #[cfg(test)]
 mod tests {
-    use super::{RecordKind, SnapshotManifest, SnapshotRecord};
+    use super::{RecordKind, SnapshotChunk, SnapshotManifest, SnapshotRecord};
     use anyhow::Result;
 
     #[test]
@@ -1373,6 +2363,7 @@ mod tests {
         let manifest = SnapshotManifest {
             target_root: "/tmp/example".to_string(),
             created_at_ns: 42,
+            chunk_threshold: 1024,
             records: vec![SnapshotRecord {
                 path: "file.txt".to_string(),
                 kind: RecordKind::Executable,
@@ -1387,6 +2378,10 @@ mod tests {
                 uid: Some(501),
                 gid: Some(20),
                 flags: Some(7),
+                chunks: vec![SnapshotChunk {
+                    object_id: "def456".to_string(),
+                    size: 5,
+                }],
             }],
         };
 
@@ -1209,238 +1209,403 @@ fn test_snapshot_save_honors_env_store_override() -> Result<(), Box<dyn std::err
     Ok(())
 }
 
+#[cfg(unix)]
 #[test]
-fn test_clone_sets_default_config() -> Result<(), Box<dyn std::error::Error>> {
+fn test_snapshot_works_outside_git_repository() -> Result<(), Box<dyn std::error::Error>> {
     let tmp = TempDir::new()?;
-
-    let source = tmp.path().join("source");
-    init_bare_repo_with_commit(&source)?;
-
-    let target = tmp.path().join("target");
+    let target = tmp.path().join("plain");
     fs::create_dir_all(&target)?;
+    fs::write(target.join("data.txt"), "one")?;
 
     agt_cmd_with_git()?
-        .args(["clone", source.to_str().unwrap()])
+        .args(["snapshot", "save"])
         .current_dir(&target)
         .assert()
         .success();
+    assert!(target.join(".agt-snapshots").exists());
 
-    let config_path = target.join("source/main/.agt/config");
-    let config_contents = fs::read_to_string(&config_path)?;
-    // Verify agt config is set
-    assert!(config_contents.contains("[agt]"));
-    assert!(config_contents.contains("agentEmail = agt@local"));
-    assert!(config_contents.contains("branchPrefix = agtsessions/"));
-
-    // Verify bare repo does not have bare=false (remains bare)
-    let bare_config_path = target.join("source.git/config");
-    let bare_config_contents = fs::read_to_string(&bare_config_path)?;
-    assert!(!bare_config_contents.contains("bare = false"));
-
-    // Verify worktree is usable (gix can open it and sees work_dir)
-    let worktree = target.join("source/main");
-    let repo = gix::open(&worktree)?;
-    assert!(repo.work_dir().is_some());
+    agt_cmd_with_git()?
+        .args(["snapshot", "status", "-q"])
+        .current_dir(&target)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("clean"));
 
     Ok(())
 }
 
+#[cfg(unix)]
 #[test]
-fn test_session_new_creates_shadow_branch_and_session() -> Result<(), Box<dyn std::error::Error>> {
-    let repo = setup_basic_repo()?;
+fn test_snapshot_commands_discover_store_from_target() -> Result<(), Box<dyn std::error::Error>> {
+    let tmp = TempDir::new()?;
+    let target = tmp.path().join("project");
+    fs::create_dir_all(target.join("nested"))?;
+    fs::write(target.join("nested/data.txt"), "one")?;
 
     agt_cmd_with_git()?
-        .args(["session", "new", "--id", "test-session"])
-        .current_dir(repo.worktree())
+        .args(["snapshot", "save"])
+        .current_dir(&target)
         .assert()
         .success();
 
-    // Verify shadow branch exists
-    let gix_repo = gix::open(repo.worktree())?;
-    let branch_name = "agtsessions/test-session";
-    assert!(gix_repo
-        .find_reference(&format!("refs/heads/{branch_name}"))
-        .is_ok());
+    fs::write(target.join("nested/data.txt"), "two")?;
 
-    // Verify session folder and sandbox exist
-    let session_root = repo.repo_root().join("sessions/test-session");
-    assert!(session_root.exists());
-    assert!(session_root.join("sandbox").exists());
+    agt_cmd_with_git()?
+        .args(["snapshot", "status", "--target", "project"])
+        .current_dir(tmp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("M nested/data.txt"));
 
-    // Verify timestamp file exists
-    let timestamp_file = repo.bare.join("agt/timestamps/test-session");
-    assert!(timestamp_file.exists());
+    agt_cmd_with_git()?
+        .args(["snapshot", "list", "--target", "project/nested"])
+        .current_dir(tmp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 snapshot(s)"));
 
-    // Verify session metadata exists
-    let session_file = repo.bare.join("agt/sessions/test-session.json");
-    assert!(session_file.exists());
+    // The store's snapshot is of project, not of project/nested.
+    agt_cmd_with_git()?
+        .args(["snapshot", "status", "--target", "project/nested"])
+        .current_dir(tmp.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No snapshot of"));
+    agt_cmd_with_git()?
+        .args(["snapshot", "save", "--target", "project/nested"])
+        .current_dir(tmp.path())
+        .assert()
+        .success();
+    agt_cmd_with_git()?
+        .args(["snapshot", "status", "--target", "project/nested"])
+        .current_dir(tmp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Clean"));
+    // The newer nested snapshot does not become project's baseline.
+    agt_cmd_with_git()?
+        .args(["snapshot", "status", "--target", "project"])
+        .current_dir(tmp.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("M nested/data.txt"));
 
+    assert!(!tmp.path().join(".agt-snapshots").exists());
     Ok(())
 }
 
+#[cfg(unix)]
 #[test]
-fn test_autocommit_with_timestamp_override() -> Result<(), Box<dyn std::error::Error>> {
-    let repo = setup_repo_with_session()?;
-
-    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
-    fs::write(sandbox_path.join("test.txt"), "test content")?;
-
-    // Get current timestamp
-    let now = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)?
-        .as_secs() as i64;
+fn test_snapshot_shared_store_namespaces_targets() -> Result<(), Box<dyn std::error::Error>> {
+    let tmp = TempDir::new()?;
+    let data_home = tmp.path().join("data");
+    let alpha = tmp.path().join("alpha");
+    let beta = tmp.path().join("beta");
+    for target in [&alpha, &beta] {
+        fs::create_dir_all(target)?;
+        fs::write(target.join("toolchain.bin"), "same bytes")?;
+    }
 
-    // Test agt autocommit with timestamp override
     agt_cmd_with_git()?
-        .args([
-            "autocommit",
-            "--session-id",
-            "test-session",
-            "--timestamp",
-            &(now - 3600).to_string(), // Force inclusion
-        ])
-        .current_dir(&sandbox_path)
+        .args(["setup", "--shared"])
+        .env("XDG_DATA_HOME", &data_home)
+        .current_dir(tmp.path())
         .assert()
         .success();
 
-    // Verify shadow commit has two parents and contains the file in sandbox/
-    let repo = gix::open(repo.worktree())?;
-    let mut branch_ref = repo.find_reference("refs/heads/agtsessions/test-session")?;
-    let commit = branch_ref.peel_to_commit()?;
-    assert_eq!(commit.parent_ids().count(), 2);
+    let mut tags = Vec::new();
+    for target in [&alpha, &beta] {
+        let output = agt_cmd_with_git()?
+            .args(["snapshot", "save", "--shared"])
+            .env("XDG_DATA_HOME", &data_home)
+            .current_dir(target)
+            .output()?;
+        assert!(output.status.success());
+        tags.push(parse_snapshot_tag(&String::from_utf8(output.stdout)?));
+    }
+    assert!(tags[0].starts_with("alpha-"), "unexpected tag {}", tags[0]);
+    assert!(tags[1].starts_with("beta-"), "unexpected tag {}", tags[1]);
+    assert!(!alpha.join(".agt-snapshots").exists());
 
-    let tree = commit.tree()?;
-    let entry = tree.lookup_entry_by_path(std::path::Path::new("sandbox/test.txt"))?;
-    assert!(entry.is_some());
+    agt_cmd_with_git()?
+        .args(["snapshot", "list", "--shared", "-q"])
+        .env("XDG_DATA_HOME", &data_home)
+        .current_dir(&alpha)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(&tags[0]))
+        .stdout(predicate::str::contains(&tags[1]).not());
+
+    agt_cmd_with_git()?
+        .args(["snapshot", "list", "--shared", "--all-targets"])
+        .env("XDG_DATA_HOME", &data_home)
+        .current_dir(&alpha)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            alpha.canonicalize()?.display().to_string(),
+        ))
+        .stdout(predicate::str::contains(
+            beta.canonicalize()?.display().to_string(),
+        ))
+        .stdout(predicate::str::contains("2 snapshot(s)"));
+
+    fs::write(alpha.join("toolchain.bin"), "changed")?;
+    agt_cmd_with_git()?
+        .args(["snapshot", "status", "--shared", "-q"])
+        .env("XDG_DATA_HOME", &data_home)
+        .current_dir(&alpha)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("changed"));
+    agt_cmd_with_git()?
+        .args(["snapshot", "status", "--shared", "-q"])
+        .env("XDG_DATA_HOME", &data_home)
+        .current_dir(&beta)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("clean"));
 
     Ok(())
 }
 
+#[cfg(unix)]
 #[test]
-fn test_autocommit_dry_run_output_includes_sandbox() -> Result<(), Box<dyn std::error::Error>> {
-    let repo = setup_repo_with_session()?;
-
-    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
-    fs::write(sandbox_path.join("dryrun.txt"), "x")?;
+fn test_snapshot_save_if_changed_and_provenance() -> Result<(), Box<dyn std::error::Error>> {
+    let tmp = TempDir::new()?;
+    let target = tmp.path().join("plain");
+    fs::create_dir_all(&target)?;
+    fs::write(target.join("data.txt"), "one")?;
 
     let output = agt_cmd_with_git()?
-        .args([
-            "autocommit",
-            "--session-id",
-            "test-session",
-            "--timestamp",
-            "0",
-            "--dry-run",
-        ])
-        .current_dir(&sandbox_path)
+        .args(["snapshot", "save", "-m", "first"])
+        .env("HOSTNAME", "build-host")
+        .current_dir(&target)
         .output()?;
-
-    let stdout = String::from_utf8(output.stdout)?;
-    assert!(stdout.contains("Dry run: session test-session"));
-    assert!(stdout.contains("M "));
-
-    Ok(())
-}
-
-#[test]
-fn test_autocommit_parent2_is_user_branch_head() -> Result<(), Box<dyn std::error::Error>> {
-    let repo = setup_repo_with_session()?;
-
-    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
-    fs::write(sandbox_path.join("p2.txt"), "p2")?;
+    assert!(output.status.success());
+    let tag = parse_snapshot_tag(&String::from_utf8(output.stdout)?);
 
     agt_cmd_with_git()?
-        .args([
-            "autocommit",
-            "--session-id",
-            "test-session",
-            "--timestamp",
-            "0",
-        ])
-        .current_dir(&sandbox_path)
+        .args(["snapshot", "save", "--if-changed"])
+        .current_dir(&target)
         .assert()
-        .success();
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "Unchanged snapshot {tag}"
+        )));
 
-    // A second autocommit ensures parent1 differs from parent2 after the shadow branch advances.
-    fs::write(sandbox_path.join("p2b.txt"), "p2b")?;
     agt_cmd_with_git()?
-        .args([
-            "autocommit",
-            "--session-id",
-            "test-session",
-            "--timestamp",
-            "0",
-        ])
-        .current_dir(&sandbox_path)
+        .args(["snapshot", "show", &tag])
+        .current_dir(&target)
         .assert()
-        .success();
-
-    let repo = gix::open(repo.worktree())?;
-    let user_head = repo.find_reference("refs/heads/main")?.peel_to_commit()?.id;
+        .success()
+        .stdout(predicate::str::contains("Message: first"))
+        .stdout(predicate::str::contains("Host: build-host"))
+        .stdout(predicate::str::contains(format!(
+            "Cwd: {}",
+            target.canonicalize()?.display()
+        )))
+        .stdout(predicate::str::contains("agt version: "));
+
+    fs::write(target.join("data.txt"), "two")?;
+    let output = agt_cmd_with_git()?
+        .args(["snapshot", "save", "--if-changed"])
+        .current_dir(&target)
+        .output()?;
+    assert!(output.status.success());
+    let second = parse_snapshot_tag(&String::from_utf8(output.stdout)?);
+    assert_ne!(tag, second);
 
-    let mut branch_ref = repo.find_reference("refs/heads/agtsessions/test-session")?;
-    let commit = branch_ref.peel_to_commit()?;
-    let parents: Vec<_> = commit.parent_ids().map(|id| id.to_owned()).collect();
-    assert_eq!(parents.len(), 2);
-    assert_eq!(parents[1], user_head);
-    assert_ne!(parents[0], parents[1]);
+    agt_cmd_with_git()?
+        .args(["snapshot", "list", "-v"])
+        .current_dir(&target)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("    Command: "));
 
     Ok(())
 }
 
+#[cfg(unix)]
 #[test]
-fn test_autocommit_records_deletions() -> Result<(), Box<dyn std::error::Error>> {
-    let repo = setup_repo_with_session()?;
-    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+fn test_snapshot_chunks_large_files_and_reassembles_them() -> Result<(), Box<dyn std::error::Error>>
+{
+    let tmp = TempDir::new()?;
+    let target = tmp.path().join("models");
+    fs::create_dir_all(&target)?;
+    let mut state: u32 = 17;
+    let original: Vec<u8> = (0..6 * 1024 * 1024)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            state.to_be_bytes()[1]
+        })
+        .collect();
+    fs::write(target.join("model.bin"), &original)?;
+    fs::write(target.join("small.txt"), "tiny")?;
 
-    fs::write(sandbox_path.join("delete-me.txt"), "to be deleted")?;
+    let output = agt_cmd_with_git()?
+        .args(["snapshot", "save"])
+        .env("AGT_SNAPSHOT_CHUNK_THRESHOLD", "1048576")
+        .current_dir(&target)
+        .output()?;
+    assert!(output.status.success());
+    let tag = parse_snapshot_tag(&String::from_utf8(output.stdout)?);
 
     agt_cmd_with_git()?
-        .args([
-            "autocommit",
-            "--session-id",
-            "test-session",
-            "--timestamp",
-            "0",
-        ])
-        .current_dir(&sandbox_path)
+        .args(["snapshot", "show", &tag])
+        .current_dir(&target)
         .assert()
-        .success();
+        .success()
+        .stdout(predicate::str::contains("Chunked files: 1"));
 
-    fs::remove_file(sandbox_path.join("delete-me.txt"))?;
+    let output = agt_cmd_with_git()?
+        .args(["snapshot", "cat", &tag, "model.bin"])
+        .current_dir(&target)
+        .output()?;
+    assert!(output.status.success());
+    assert!(output.stdout == original, "cat did not reassemble the file");
 
+    let exported = tmp.path().join("exported");
     agt_cmd_with_git()?
-        .args([
-            "autocommit",
-            "--session-id",
-            "test-session",
-            "--timestamp",
-            "0",
-        ])
-        .current_dir(&sandbox_path)
+        .args(["snapshot", "export", &tag, "--to"])
+        .arg(&exported)
+        .current_dir(&target)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("(2 files)"));
+    assert!(fs::read(exported.join("model.bin"))? == original);
+    assert_eq!(fs::read_to_string(exported.join("small.txt"))?, "tiny");
+    agt_cmd_with_git()?
+        .args(["snapshot", "export", &tag, "--to"])
+        .arg(&exported)
+        .current_dir(&target)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is not empty"));
+
+    let mut edited = original.clone();
+    edited[3 * 1024 * 1024] ^= 0xff;
+    fs::write(target.join("model.bin"), &edited)?;
+    agt_cmd_with_git()?
+        .args(["snapshot", "status", "-q"])
+        .env("AGT_SNAPSHOT_CHUNK_THRESHOLD", "1048576")
+        .current_dir(&target)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("changed"));
+    agt_cmd_with_git()?
+        .args(["snapshot", "save"])
+        .env("AGT_SNAPSHOT_CHUNK_THRESHOLD", "1048576")
+        .current_dir(&target)
         .assert()
         .success();
 
-    let repo = gix::open(repo.worktree())?;
-    let mut branch_ref = repo.find_reference("refs/heads/agtsessions/test-session")?;
-    let commit = branch_ref.peel_to_commit()?;
-    let tree = commit.tree()?;
-    let entry = tree.lookup_entry_by_path(std::path::Path::new("sandbox/delete-me.txt"))?;
-    assert!(entry.is_none());
+    agt_cmd_with_git()?
+        .args(["snapshot", "restore", "--snapshot", &tag])
+        .env("AGT_SNAPSHOT_CHUNK_THRESHOLD", "1048576")
+        .current_dir(&target)
+        .assert()
+        .success();
+    assert!(fs::read(target.join("model.bin"))? == original);
+    assert_eq!(fs::read_to_string(target.join("small.txt"))?, "tiny");
+
+    // The threshold is recorded at save time, so a different one later does
+    // not make the chunked file look modified.
+    let output = agt_cmd_with_git()?
+        .args(["snapshot", "save"])
+        .env("AGT_SNAPSHOT_CHUNK_THRESHOLD", "1048576")
+        .current_dir(&target)
+        .output()?;
+    assert!(output.status.success());
+    let chunked_tag = parse_snapshot_tag(&String::from_utf8(output.stdout)?);
+    agt_cmd_with_git()?
+        .args(["snapshot", "status", "-q"])
+        .current_dir(&target)
+        .assert()
+        .success()
+        .stdout("clean\n");
+    agt_cmd_with_git()?
+        .args(["snapshot", "save", "--if-changed"])
+        .env("AGT_SNAPSHOT_CHUNK_THRESHOLD", "4096")
+        .current_dir(&target)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Unchanged snapshot"));
+    fs::write(target.join("small.txt"), "tiny, edited")?;
+    let output = agt_cmd_with_git()?
+        .args(["snapshot", "save"])
+        .current_dir(&target)
+        .output()?;
+    assert!(output.status.success());
+    let whole_tag = parse_snapshot_tag(&String::from_utf8(output.stdout)?);
+    agt_cmd_with_git()?
+        .args(["snapshot", "diff", &chunked_tag, &whole_tag])
+        .current_dir(&target)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("M small.txt"))
+        .stdout(predicate::str::contains("model.bin").not());
 
     Ok(())
 }
 
 #[cfg(unix)]
 #[test]
-fn test_autocommit_preserves_symlink_entries() -> Result<(), Box<dyn std::error::Error>> {
+fn test_diff_across_git_snapshot_session_and_filesystem() -> Result<(), Box<dyn std::error::Error>>
+{
     let repo = setup_repo_with_session()?;
-    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+    let worktree = repo.worktree();
+    fs::create_dir_all(worktree.join("build"))?;
+    fs::write(worktree.join("build/out.txt"), "generated\n")?;
 
-    fs::write(sandbox_path.join("target.txt"), "target")?;
-    #[cfg(unix)]
-    std::os::unix::fs::symlink("target.txt", sandbox_path.join("link.txt"))?;
+    agt_cmd_with_git()?
+        .args(["diff", "git:HEAD", "fs"])
+        .current_dir(worktree)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("A build/out.txt"))
+        .stdout(predicate::str::contains("README.md").not());
+    // From a subdirectory, fs still means the whole work dir.
+    agt_cmd_with_git()?
+        .args(["diff", "git:HEAD", "fs"])
+        .current_dir(worktree.join("build"))
+        .assert()
+        .success()
+        .stdout("A build/out.txt\n");
+    // Arguments that merely look like specs are git diff arguments.
+    agt_cmd_with_git()?
+        .args(["diff", "--cached", "--", "fs", "fs:x"])
+        .current_dir(worktree)
+        .assert()
+        .success()
+        .stdout("");
+
+    let output = agt_cmd_with_git()?
+        .args(["snapshot", "save"])
+        .current_dir(worktree)
+        .output()?;
+    assert!(output.status.success());
+    let tag = parse_snapshot_tag(&String::from_utf8(output.stdout)?);
+
+    fs::write(worktree.join("build/out.txt"), "generated\nagain\n")?;
+    agt_cmd_with_git()?
+        .args(["diff", &format!("snapshot:{tag}"), "fs", "-p"])
+        .current_dir(worktree)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("M build/out.txt"))
+        .stdout(predicate::str::contains("+again"));
+
+    // Before its first autocommit, a session is its plain start commit.
+    agt_cmd_with_git()?
+        .args(["diff", "git:HEAD", "session:test-session"])
+        .current_dir(worktree)
+        .assert()
+        .success()
+        .stdout("");
 
+    let sandbox = repo.repo_root().join("sessions/test-session/sandbox");
+    fs::write(sandbox.join("agent.txt"), "agent")?;
     agt_cmd_with_git()?
         .args([
             "autocommit",
@@ -1449,98 +1614,453 @@ fn test_autocommit_preserves_symlink_entries() -> Result<(), Box<dyn std::error:
             "--timestamp",
             "0",
         ])
-        .current_dir(&sandbox_path)
+        .current_dir(&sandbox)
         .assert()
         .success();
 
-    let repo = gix::open(repo.worktree())?;
-    let mut branch_ref = repo.find_reference("refs/heads/agtsessions/test-session")?;
-    let commit = branch_ref.peel_to_commit()?;
-    let tree = commit.tree()?;
-    let entry = tree.lookup_entry_by_path(std::path::Path::new("sandbox/link.txt"))?;
-    let entry = entry.expect("expected symlink entry in sandbox/");
-    assert_eq!(entry.mode().kind(), gix::object::tree::EntryKind::Link);
+    agt_cmd_with_git()?
+        .args(["diff", "git:HEAD", "session:test-session"])
+        .current_dir(worktree)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("A agent.txt"))
+        .stdout(predicate::str::contains("README.md").not());
+
+    // A commit made in the sandbox is a plain commit on the shadow branch.
+    fs::write(sandbox.join("direct.txt"), "direct")?;
+    let git_path = find_real_git()?;
+    for args in [
+        &["add", "direct.txt"][..],
+        &[
+            "-c",
+            "user.name=Agent",
+            "-c",
+            "user.email=agent@example.com",
+            "commit",
+            "-m",
+            "agent commit",
+        ],
+    ] {
+        assert!(Command::new(&git_path)
+            .current_dir(&sandbox)
+            .args(args)
+            .status()?
+            .success());
+    }
+    agt_cmd_with_git()?
+        .args(["diff", "git:HEAD", "session:test-session"])
+        .current_dir(worktree)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("A direct.txt"));
 
     Ok(())
 }
 
+#[cfg(target_os = "linux")]
 #[test]
-fn test_git_mode_filters_shadow_branches() -> Result<(), Box<dyn std::error::Error>> {
-    let repo = setup_repo_with_shadow_branch()?;
+fn test_snapshot_watch_reports_changes_and_stops_on_violation(
+) -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+    use std::time::{Duration, Instant};
 
-    // Test git branch command (should filter shadow branches)
-    let output = git_mode_cmd(repo.tmp())?
-        .args(["branch"])
-        .current_dir(repo.worktree())
-        .output()?;
+    let tmp = TempDir::new()?;
+    let target = tmp.path().join("plain");
+    fs::create_dir_all(target.join("build"))?;
+    fs::write(target.join("keep.txt"), "one")?;
+    agt_cmd_with_git()?
+        .args(["snapshot", "save"])
+        .current_dir(&target)
+        .assert()
+        .success();
+
+    let mut child = Command::new(agt_bin())
+        .args([
+            "snapshot",
+            "watch",
+            "--json",
+            "--allow",
+            "build/**",
+            "--exit-on-violation",
+        ])
+        .current_dir(&target)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let mut ready = String::new();
+    BufReader::new(child.stderr.take().unwrap()).read_line(&mut ready)?;
+    assert!(ready.starts_with("Watching"), "unexpected: {ready}");
+
+    fs::create_dir_all(target.join("build/deep"))?;
+    fs::write(target.join("build/deep/out.txt"), "generated")?;
+    std::thread::sleep(Duration::from_millis(300));
+    fs::write(target.join("keep.txt"), "two")?;
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() > deadline {
+            child.kill()?;
+            panic!("watch did not stop on violation");
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    assert!(!status.success());
 
+    let output = child.wait_with_output()?;
     let stdout = String::from_utf8(output.stdout)?;
-    assert!(!stdout.contains("agtsessions/"));
+    let events: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    assert!(events
+        .iter()
+        .any(|event| event["path"] == "build/deep/out.txt"
+            && event["change"] == "A"
+            && event["allowed"] == true));
+    let last = events.last().expect("at least one event");
+    assert_eq!(last["path"], "keep.txt");
+    assert_eq!(last["change"], "M");
+    assert_eq!(last["allowed"], false);
 
     Ok(())
 }
 
 #[test]
-fn test_git_mode_add_and_commit() -> Result<(), Box<dyn std::error::Error>> {
-    let repo = setup_basic_repo()?;
-    let worktree = repo.worktree();
+fn test_clone_sets_default_config() -> Result<(), Box<dyn std::error::Error>> {
+    let tmp = TempDir::new()?;
 
-    fs::write(worktree.join("git-add.txt"), "hello")?;
+    let source = tmp.path().join("source");
+    init_bare_repo_with_commit(&source)?;
 
-    git_mode_cmd(repo.tmp())?
-        .args(["add", "git-add.txt"])
-        .current_dir(worktree)
+    let target = tmp.path().join("target");
+    fs::create_dir_all(&target)?;
+
+    agt_cmd_with_git()?
+        .args(["clone", source.to_str().unwrap()])
+        .current_dir(&target)
         .assert()
         .success();
 
-    git_mode_cmd(repo.tmp())?
-        .args(["commit", "-m", "add via git mode"])
-        .current_dir(worktree)
+    let config_path = target.join("source/main/.agt/config");
+    let config_contents = fs::read_to_string(&config_path)?;
+    // Verify agt config is set
+    assert!(config_contents.contains("[agt]"));
+    assert!(config_contents.contains("agentEmail = agt@local"));
+    assert!(config_contents.contains("branchPrefix = agtsessions/"));
+
+    // Verify bare repo does not have bare=false (remains bare)
+    let bare_config_path = target.join("source.git/config");
+    let bare_config_contents = fs::read_to_string(&bare_config_path)?;
+    assert!(!bare_config_contents.contains("bare = false"));
+
+    // Verify worktree is usable (gix can open it and sees work_dir)
+    let worktree = target.join("source/main");
+    let repo = gix::open(&worktree)?;
+    assert!(repo.work_dir().is_some());
+
+    Ok(())
+}
+
+#[test]
+fn test_session_new_creates_shadow_branch_and_session() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_basic_repo()?;
+
+    agt_cmd_with_git()?
+        .args(["session", "new", "--id", "test-session"])
+        .current_dir(repo.worktree())
         .assert()
         .success();
 
-    let repo = gix::open(worktree)?;
-    let mut branch_ref = repo.find_reference("refs/heads/main")?;
+    // Verify shadow branch exists
+    let gix_repo = gix::open(repo.worktree())?;
+    let branch_name = "agtsessions/test-session";
+    assert!(gix_repo
+        .find_reference(&format!("refs/heads/{branch_name}"))
+        .is_ok());
+
+    // Verify session folder and sandbox exist
+    let session_root = repo.repo_root().join("sessions/test-session");
+    assert!(session_root.exists());
+    assert!(session_root.join("sandbox").exists());
+
+    // Verify timestamp file exists
+    let timestamp_file = repo.bare.join("agt/timestamps/test-session");
+    assert!(timestamp_file.exists());
+
+    // Verify session metadata exists
+    let session_file = repo.bare.join("agt/sessions/test-session.json");
+    assert!(session_file.exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_autocommit_with_timestamp_override() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
+
+    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+    fs::write(sandbox_path.join("test.txt"), "test content")?;
+
+    // Get current timestamp
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs() as i64;
+
+    // Test agt autocommit with timestamp override
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            &(now - 3600).to_string(), // Force inclusion
+        ])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+
+    // Verify shadow commit has two parents and contains the file in sandbox/
+    let repo = gix::open(repo.worktree())?;
+    let mut branch_ref = repo.find_reference("refs/heads/agtsessions/test-session")?;
     let commit = branch_ref.peel_to_commit()?;
+    assert_eq!(commit.parent_ids().count(), 2);
+
     let tree = commit.tree()?;
-    let entry = tree.lookup_entry_by_path(std::path::Path::new("git-add.txt"))?;
+    let entry = tree.lookup_entry_by_path(std::path::Path::new("sandbox/test.txt"))?;
     assert!(entry.is_some());
 
     Ok(())
 }
 
 #[test]
-fn test_agt_mode_shows_all_branches() -> Result<(), Box<dyn std::error::Error>> {
-    let repo = setup_repo_with_shadow_branch()?;
+fn test_autocommit_dry_run_output_includes_sandbox() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
+
+    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+    fs::write(sandbox_path.join("dryrun.txt"), "x")?;
 
-    // Test agt branch command (should show all branches including shadow branches)
     let output = agt_cmd_with_git()?
-        .args(["branch", "-a"])
-        .current_dir(repo.worktree())
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+            "--dry-run",
+        ])
+        .current_dir(&sandbox_path)
         .output()?;
 
     let stdout = String::from_utf8(output.stdout)?;
-    assert!(stdout.contains("agtsessions/test-agent"));
+    assert!(stdout.contains("Dry run: session test-session"));
+    assert!(stdout.contains("M "));
 
     Ok(())
 }
 
-// Helper functions
-struct TestRepo {
-    tmp: TempDir,
-    worktree: PathBuf,
-    bare: PathBuf,
-    root: PathBuf,
-}
+#[test]
+fn test_autocommit_parent2_is_user_branch_head() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
 
-impl TestRepo {
-    fn worktree(&self) -> &Path {
-        &self.worktree
-    }
+    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+    fs::write(sandbox_path.join("p2.txt"), "p2")?;
 
-    fn tmp(&self) -> &TempDir {
-        &self.tmp
-    }
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+
+    // A second autocommit ensures parent1 differs from parent2 after the shadow branch advances.
+    fs::write(sandbox_path.join("p2b.txt"), "p2b")?;
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+
+    let repo = gix::open(repo.worktree())?;
+    let user_head = repo.find_reference("refs/heads/main")?.peel_to_commit()?.id;
+
+    let mut branch_ref = repo.find_reference("refs/heads/agtsessions/test-session")?;
+    let commit = branch_ref.peel_to_commit()?;
+    let parents: Vec<_> = commit.parent_ids().map(|id| id.to_owned()).collect();
+    assert_eq!(parents.len(), 2);
+    assert_eq!(parents[1], user_head);
+    assert_ne!(parents[0], parents[1]);
+
+    Ok(())
+}
+
+#[test]
+fn test_autocommit_records_deletions() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
+    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+
+    fs::write(sandbox_path.join("delete-me.txt"), "to be deleted")?;
+
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+
+    fs::remove_file(sandbox_path.join("delete-me.txt"))?;
+
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+
+    let repo = gix::open(repo.worktree())?;
+    let mut branch_ref = repo.find_reference("refs/heads/agtsessions/test-session")?;
+    let commit = branch_ref.peel_to_commit()?;
+    let tree = commit.tree()?;
+    let entry = tree.lookup_entry_by_path(std::path::Path::new("sandbox/delete-me.txt"))?;
+    assert!(entry.is_none());
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_autocommit_preserves_symlink_entries() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
+    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+
+    fs::write(sandbox_path.join("target.txt"), "target")?;
+    #[cfg(unix)]
+    std::os::unix::fs::symlink("target.txt", sandbox_path.join("link.txt"))?;
+
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+
+    let repo = gix::open(repo.worktree())?;
+    let mut branch_ref = repo.find_reference("refs/heads/agtsessions/test-session")?;
+    let commit = branch_ref.peel_to_commit()?;
+    let tree = commit.tree()?;
+    let entry = tree.lookup_entry_by_path(std::path::Path::new("sandbox/link.txt"))?;
+    let entry = entry.expect("expected symlink entry in sandbox/");
+    assert_eq!(entry.mode().kind(), gix::object::tree::EntryKind::Link);
+
+    Ok(())
+}
+
+#[test]
+fn test_git_mode_filters_shadow_branches() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_shadow_branch()?;
+
+    // Test git branch command (should filter shadow branches)
+    let output = git_mode_cmd(repo.tmp())?
+        .args(["branch"])
+        .current_dir(repo.worktree())
+        .output()?;
+
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(!stdout.contains("agtsessions/"));
+
+    Ok(())
+}
+
+#[test]
+fn test_git_mode_add_and_commit() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_basic_repo()?;
+    let worktree = repo.worktree();
+
+    fs::write(worktree.join("git-add.txt"), "hello")?;
+
+    git_mode_cmd(repo.tmp())?
+        .args(["add", "git-add.txt"])
+        .current_dir(worktree)
+        .assert()
+        .success();
+
+    git_mode_cmd(repo.tmp())?
+        .args(["commit", "-m", "add via git mode"])
+        .current_dir(worktree)
+        .assert()
+        .success();
+
+    let repo = gix::open(worktree)?;
+    let mut branch_ref = repo.find_reference("refs/heads/main")?;
+    let commit = branch_ref.peel_to_commit()?;
+    let tree = commit.tree()?;
+    let entry = tree.lookup_entry_by_path(std::path::Path::new("git-add.txt"))?;
+    assert!(entry.is_some());
+
+    Ok(())
+}
+
+#[test]
+fn test_agt_mode_shows_all_branches() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_shadow_branch()?;
+
+    // Test agt branch command (should show all branches including shadow branches)
+    let output = agt_cmd_with_git()?
+        .args(["branch", "-a"])
+        .current_dir(repo.worktree())
+        .output()?;
+
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("agtsessions/test-agent"));
+
+    Ok(())
+}
+
+// Helper functions
+struct TestRepo {
+    tmp: TempDir,
+    worktree: PathBuf,
+    bare: PathBuf,
+    root: PathBuf,
+}
+
+impl TestRepo {
+    fn worktree(&self) -> &Path {
+        &self.worktree
+    }
+
+    fn tmp(&self) -> &TempDir {
+        &self.tmp
+    }
 
     fn repo_root(&self) -> &Path {
         &self.root
@@ -1830,433 +2350,2560 @@ fn ensure_worktree_tool() -> Result<PathBuf, Box<dyn std::error::Error>> {
     let release = root.join("target/release").join(&name);
     let debug = root.join("target/debug").join(&name);
 
-    if release.exists() {
-        return Ok(release);
-    }
-    if debug.exists() {
-        return Ok(debug);
-    }
+    if release.exists() {
+        return Ok(release);
+    }
+    if debug.exists() {
+        return Ok(debug);
+    }
+
+    let status = Command::new("cargo")
+        .args(["build", "-p", "agt-worktree"])
+        .status()?;
+    if !status.success() {
+        return Err("failed to build agt-worktree".into());
+    }
+
+    if debug.exists() {
+        Ok(debug)
+    } else if release.exists() {
+        Ok(release)
+    } else {
+        Err("agt-worktree binary not found after build".into())
+    }
+}
+
+#[test]
+fn test_restore_resets_sandbox_to_shadow_commit() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
+    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+
+    fs::write(sandbox_path.join("file-a.txt"), "version 1")?;
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+
+    let gix_repo = gix::open(repo.worktree())?;
+    let mut branch_ref = gix_repo.find_reference("refs/heads/agtsessions/test-session")?;
+    let first_commit = branch_ref.peel_to_commit()?.id;
+
+    fs::write(sandbox_path.join("file-a.txt"), "version 2")?;
+    fs::write(sandbox_path.join("file-b.txt"), "new file")?;
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+
+    assert!(sandbox_path.join("file-b.txt").exists());
+    assert_eq!(
+        fs::read_to_string(sandbox_path.join("file-a.txt"))?,
+        "version 2"
+    );
+
+    agt_cmd_with_git()?
+        .args([
+            "session",
+            "restore",
+            "--session-id",
+            "test-session",
+            "--commit",
+            &first_commit.to_string(),
+        ])
+        .current_dir(repo.worktree())
+        .assert()
+        .success();
+
+    assert!(!sandbox_path.join("file-b.txt").exists());
+    assert_eq!(
+        fs::read_to_string(sandbox_path.join("file-a.txt"))?,
+        "version 1"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_restore_resets_agent_state_folders() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
+    let session_folder = repo.repo_root().join("sessions/test-session");
+    let sandbox_path = session_folder.join("sandbox");
+    let config_dir = session_folder.join("config");
+    let xdg_dir = session_folder.join("xdg");
+
+    fs::create_dir_all(&config_dir)?;
+    fs::create_dir_all(&xdg_dir)?;
+    fs::write(config_dir.join("agent.json"), r#"{"model": "gpt-4"}"#)?;
+    fs::write(xdg_dir.join("state.db"), "initial state")?;
+
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+
+    let gix_repo = gix::open(repo.worktree())?;
+    let mut branch_ref = gix_repo.find_reference("refs/heads/agtsessions/test-session")?;
+    let first_commit = branch_ref.peel_to_commit()?.id;
+
+    fs::write(config_dir.join("agent.json"), r#"{"model": "claude-3"}"#)?;
+    fs::write(xdg_dir.join("state.db"), "modified state")?;
+    fs::write(xdg_dir.join("new-file.txt"), "new")?;
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+
+    agt_cmd_with_git()?
+        .args([
+            "session",
+            "restore",
+            "--session-id",
+            "test-session",
+            "--commit",
+            &first_commit.to_string(),
+        ])
+        .current_dir(repo.worktree())
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(config_dir.join("agent.json"))?,
+        r#"{"model": "gpt-4"}"#
+    );
+    assert_eq!(
+        fs::read_to_string(xdg_dir.join("state.db"))?,
+        "initial state"
+    );
+    assert!(!xdg_dir.join("new-file.txt").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_restore_continues_autocommit_with_correct_parent() -> Result<(), Box<dyn std::error::Error>>
+{
+    let repo = setup_repo_with_session()?;
+    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+
+    fs::write(sandbox_path.join("a.txt"), "a")?;
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+
+    let gix_repo = gix::open(repo.worktree())?;
+    let mut branch_ref = gix_repo.find_reference("refs/heads/agtsessions/test-session")?;
+    let first_commit_id = branch_ref.peel_to_commit()?.id;
+
+    fs::write(sandbox_path.join("b.txt"), "b")?;
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+
+    agt_cmd_with_git()?
+        .args([
+            "session",
+            "restore",
+            "--session-id",
+            "test-session",
+            "--commit",
+            &first_commit_id.to_string(),
+        ])
+        .current_dir(repo.worktree())
+        .assert()
+        .success();
+
+    fs::write(sandbox_path.join("c.txt"), "c")?;
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+
+    let gix_repo = gix::open(repo.worktree())?;
+    let mut branch_ref = gix_repo.find_reference("refs/heads/agtsessions/test-session")?;
+    let new_commit = branch_ref.peel_to_commit()?;
+    let parents: Vec<_> = new_commit.parent_ids().map(|id| id.to_owned()).collect();
+
+    assert_eq!(parents.len(), 2);
+    assert_eq!(parents[0], first_commit_id);
+
+    Ok(())
+}
+
+#[test]
+fn test_restore_deletes_files_not_in_shadow_tree() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
+    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+
+    fs::write(sandbox_path.join("keep.txt"), "keep")?;
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+
+    let gix_repo = gix::open(repo.worktree())?;
+    let mut branch_ref = gix_repo.find_reference("refs/heads/agtsessions/test-session")?;
+    let first_commit = branch_ref.peel_to_commit()?.id;
+
+    fs::write(sandbox_path.join("extra.txt"), "extra")?;
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+
+    assert!(sandbox_path.join("extra.txt").exists());
+
+    agt_cmd_with_git()?
+        .args([
+            "session",
+            "restore",
+            "--session-id",
+            "test-session",
+            "--commit",
+            &first_commit.to_string(),
+        ])
+        .current_dir(repo.worktree())
+        .assert()
+        .success();
+
+    assert!(sandbox_path.join("keep.txt").exists());
+    assert!(!sandbox_path.join("extra.txt").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_restore_preserves_tracked_file_content() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
+    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+
+    let bare_repo = gix::open(&repo.bare)?;
+    let main_worktree = repo.worktree();
+
+    fs::write(main_worktree.join("tracked.txt"), "original tracked")?;
+    commit_worktree(
+        &bare_repo,
+        main_worktree,
+        "refs/heads/main",
+        "Add tracked file",
+        "agt@local",
+    )?;
+
+    fs::write(sandbox_path.join("tracked.txt"), "original tracked")?;
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+
+    fs::write(sandbox_path.join("tracked.txt"), "modified tracked")?;
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+
+    let gix_repo = gix::open(repo.worktree())?;
+    let mut branch_ref = gix_repo.find_reference("refs/heads/agtsessions/test-session")?;
+    let target_commit = branch_ref.peel_to_commit()?.id;
+
+    fs::write(sandbox_path.join("tracked.txt"), "drift")?;
+
+    agt_cmd_with_git()?
+        .args([
+            "session",
+            "restore",
+            "--session-id",
+            "test-session",
+            "--commit",
+            &target_commit.to_string(),
+        ])
+        .current_dir(repo.worktree())
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(sandbox_path.join("tracked.txt"))?,
+        "modified tracked"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_restore_saves_current_state_and_can_be_undone() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
+    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+
+    fs::write(sandbox_path.join("agent-work.txt"), "committed")?;
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+    let gix_repo = gix::open(repo.worktree())?;
+    let first_commit = gix_repo
+        .find_reference("refs/heads/agtsessions/test-session")?
+        .peel_to_commit()?
+        .id;
+
+    // Work after the last autocommit must survive the restore.
+    fs::write(sandbox_path.join("agent-work.txt"), "not yet committed")?;
+    fs::write(sandbox_path.join("scratch.txt"), "scratch")?;
+
+    agt_cmd_with_git()?
+        .args([
+            "session",
+            "restore",
+            "--session-id",
+            "test-session",
+            "--commit",
+            &first_commit.to_string(),
+        ])
+        .current_dir(repo.worktree())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("--undo"));
+    assert_eq!(
+        fs::read_to_string(sandbox_path.join("agent-work.txt"))?,
+        "committed"
+    );
+    assert!(!sandbox_path.join("scratch.txt").exists());
+
+    let metadata = fs::read_to_string(repo.bare.join("agt/sessions/test-session.json"))?;
+    let metadata: serde_json::Value = serde_json::from_str(&metadata)?;
+    let saved = metadata["pre_restore_commit"]
+        .as_str()
+        .ok_or("missing pre_restore_commit")?
+        .to_string();
+    assert_ne!(saved, first_commit.to_string());
+
+    agt_cmd_with_git()?
+        .args([
+            "session",
+            "restore",
+            "--session-id",
+            "test-session",
+            "--undo",
+        ])
+        .current_dir(repo.worktree())
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(sandbox_path.join("agent-work.txt"))?,
+        "not yet committed"
+    );
+    assert_eq!(
+        fs::read_to_string(sandbox_path.join("scratch.txt"))?,
+        "scratch"
+    );
+    let gix_repo = gix::open(repo.worktree())?;
+    assert_eq!(
+        gix_repo
+            .find_reference("refs/heads/agtsessions/test-session")?
+            .peel_to_commit()?
+            .id
+            .to_string(),
+        saved
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_restore_undo_without_prior_restore_fails() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
+
+    agt_cmd_with_git()?
+        .args([
+            "session",
+            "restore",
+            "--session-id",
+            "test-session",
+            "--undo",
+        ])
+        .current_dir(repo.worktree())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("has no restore to undo"));
+
+    Ok(())
+}
+
+#[test]
+fn test_restore_selects_shadow_commit_by_steps_label_and_time(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
+    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+    let autocommit = |label: Option<&str>| -> Result<(), Box<dyn std::error::Error>> {
+        let mut cmd = agt_cmd_with_git()?;
+        cmd.args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ]);
+        if let Some(label) = label {
+            cmd.args(["--label", label]);
+        }
+        cmd.current_dir(&sandbox_path).assert().success();
+        Ok(())
+    };
+    let restore =
+        |args: &[&str]| -> Result<assert_cmd::assert::Assert, Box<dyn std::error::Error>> {
+            Ok(agt_cmd_with_git()?
+                .args(["session", "restore", "--session-id", "test-session"])
+                .args(args)
+                .current_dir(repo.worktree())
+                .assert())
+        };
+    let work = || fs::read_to_string(sandbox_path.join("agent-work.txt"));
+
+    fs::write(sandbox_path.join("agent-work.txt"), "one")?;
+    autocommit(None)?;
+    fs::write(sandbox_path.join("agent-work.txt"), "two")?;
+    autocommit(Some("before-refactor"))?;
+    fs::write(sandbox_path.join("agent-work.txt"), "three")?;
+    autocommit(None)?;
+
+    restore(&["--label", "before-refactor", "--yes"])?
+        .success()
+        .stdout(predicate::str::contains("label before-refactor"));
+    assert_eq!(work()?, "two");
+
+    // Restoring moves the shadow branch, so history is counted from "two" now.
+    restore(&["--steps-back", "1", "--yes"])?
+        .success()
+        .stdout(predicate::str::contains("1 steps back"));
+    assert_eq!(work()?, "one");
+
+    restore(&["--label", "no-such-label", "--yes"])?
+        .failure()
+        .stderr(predicate::str::contains(
+            "No shadow commit labeled no-such-label",
+        ));
+    restore(&["--before", "100w", "--yes"])?
+        .failure()
+        .stderr(predicate::str::contains("No shadow commit that old"));
+
+    // Selection other than by SHA asks first; declining leaves the session alone.
+    restore(&["--steps-back", "0"])?
+        .failure()
+        .stderr(predicate::str::contains("Restore cancelled"));
+    assert_eq!(work()?, "one");
+    agt_cmd_with_git()?
+        .args([
+            "session",
+            "restore",
+            "--session-id",
+            "test-session",
+            "--at",
+            "2999-01-01 00:00",
+        ])
+        .current_dir(repo.worktree())
+        .write_stdin("y\n")
+        .assert()
+        .success();
+    assert_eq!(work()?, "one");
+
+    restore(&["--steps-back", "1", "--label", "before-refactor"])?.failure();
+
+    Ok(())
+}
+
+#[test]
+fn test_shadow_history_walks_past_sandbox_commits() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
+    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+    let autocommit = |label: &str| -> Result<(), Box<dyn std::error::Error>> {
+        agt_cmd_with_git()?
+            .args([
+                "autocommit",
+                "--session-id",
+                "test-session",
+                "--timestamp",
+                "0",
+                "--label",
+                label,
+            ])
+            .current_dir(&sandbox_path)
+            .assert()
+            .success();
+        Ok(())
+    };
+
+    fs::write(sandbox_path.join("agent-work.txt"), "one")?;
+    autocommit("first")?;
+    fs::write(sandbox_path.join("agent-work.txt"), "two")?;
+    let git_path = find_real_git()?;
+    for args in [
+        &["add", "agent-work.txt"][..],
+        &[
+            "-c",
+            "user.name=Agent",
+            "-c",
+            "user.email=agent@example.com",
+            "commit",
+            "-m",
+            "agent commit",
+        ],
+    ] {
+        assert!(Command::new(&git_path)
+            .current_dir(&sandbox_path)
+            .args(args)
+            .status()?
+            .success());
+    }
+    fs::write(sandbox_path.join("agent-work.txt"), "three")?;
+    autocommit("second")?;
+
+    agt_cmd_with_git()?
+        .args(["session", "status", "--session-id", "test-session"])
+        .current_dir(repo.worktree())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Shadow commits: 2"));
+    agt_cmd_with_git()?
+        .args([
+            "session",
+            "restore",
+            "--session-id",
+            "test-session",
+            "--steps-back",
+            "1",
+            "--yes",
+        ])
+        .current_dir(repo.worktree())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("label first"));
+    assert_eq!(
+        fs::read_to_string(sandbox_path.join("agent-work.txt"))?,
+        "one"
+    );
+
+    agt_cmd_with_git()?
+        .args([
+            "session",
+            "restore",
+            "--session-id",
+            "test-session",
+            "--undo",
+        ])
+        .current_dir(repo.worktree())
+        .assert()
+        .success();
+    agt_cmd_with_git()?
+        .args(["rewind", "first"])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(sandbox_path.join("agent-work.txt"))?,
+        "one"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_restore_selected_paths_leaves_the_rest_alone() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
+    let session_folder = repo.repo_root().join("sessions/test-session");
+    let sandbox_path = session_folder.join("sandbox");
+
+    fs::create_dir_all(sandbox_path.join("src"))?;
+    fs::write(sandbox_path.join("src/foo.rs"), "fn foo() {}")?;
+    fs::write(sandbox_path.join("src/bar.rs"), "fn bar() {}")?;
+    fs::create_dir_all(session_folder.join("config"))?;
+    fs::write(session_folder.join("config/agent.json"), "{}")?;
+    fs::create_dir_all(session_folder.join("xdg"))?;
+    fs::write(session_folder.join("xdg/state.db"), "old state")?;
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+    let gix_repo = gix::open(repo.worktree())?;
+    let good_commit = gix_repo
+        .find_reference("refs/heads/agtsessions/test-session")?
+        .peel_to_commit()?
+        .id;
+
+    fs::remove_dir_all(sandbox_path.join("src"))?;
+    fs::write(session_folder.join("config/agent.json"), "broken")?;
+    fs::write(session_folder.join("xdg/state.db"), "new state")?;
+    fs::write(sandbox_path.join("later.txt"), "later work")?;
+
+    agt_cmd_with_git()?
+        .args([
+            "session",
+            "restore",
+            "--session-id",
+            "test-session",
+            "--commit",
+            &good_commit.to_string(),
+            "--path",
+            "sandbox/src",
+            "--path",
+            "config/agent.json",
+        ])
+        .current_dir(repo.worktree())
+        .assert()
+        .success();
+
+    assert_eq!(
+        fs::read_to_string(sandbox_path.join("src/foo.rs"))?,
+        "fn foo() {}"
+    );
+    assert_eq!(
+        fs::read_to_string(sandbox_path.join("src/bar.rs"))?,
+        "fn bar() {}"
+    );
+    assert_eq!(
+        fs::read_to_string(session_folder.join("config/agent.json"))?,
+        "{}"
+    );
+    assert_eq!(
+        fs::read_to_string(session_folder.join("xdg/state.db"))?,
+        "new state"
+    );
+    assert_eq!(
+        fs::read_to_string(sandbox_path.join("later.txt"))?,
+        "later work"
+    );
+
+    let gix_repo = gix::open(repo.worktree())?;
+    let tip = gix_repo
+        .find_reference("refs/heads/agtsessions/test-session")?
+        .peel_to_commit()?;
+    assert_ne!(tip.id, good_commit);
+    assert_eq!(
+        tip.parent_ids().next().map(|id| id.detach()),
+        Some(good_commit)
+    );
+
+    agt_cmd_with_git()?
+        .args([
+            "session",
+            "restore",
+            "--session-id",
+            "test-session",
+            "--commit",
+            &good_commit.to_string(),
+            "--path",
+            "sandbox/missing.rs",
+        ])
+        .current_dir(repo.worktree())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Path not found"));
+
+    Ok(())
+}
+
+#[test]
+fn test_restore_as_new_leaves_original_session_untouched() -> Result<(), Box<dyn std::error::Error>>
+{
+    let repo = setup_repo_with_session()?;
+    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+
+    fs::write(sandbox_path.join("agent-work.txt"), "first")?;
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+    let gix_repo = gix::open(repo.worktree())?;
+    let first_commit = gix_repo
+        .find_reference("refs/heads/agtsessions/test-session")?
+        .peel_to_commit()?
+        .id;
+
+    fs::write(sandbox_path.join("agent-work.txt"), "second")?;
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+    let gix_repo = gix::open(repo.worktree())?;
+    let latest_commit = gix_repo
+        .find_reference("refs/heads/agtsessions/test-session")?
+        .peel_to_commit()?
+        .id;
+    fs::write(sandbox_path.join("uncommitted.txt"), "in progress")?;
+
+    agt_cmd_with_git()?
+        .args([
+            "session",
+            "restore",
+            "--session-id",
+            "test-session",
+            "--commit",
+            &first_commit.to_string(),
+            "--as-new",
+            "retry",
+        ])
+        .current_dir(repo.worktree())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Created session: retry"));
+
+    let retry_sandbox = repo.repo_root().join("sessions/retry/sandbox");
+    assert_eq!(
+        fs::read_to_string(retry_sandbox.join("agent-work.txt"))?,
+        "first"
+    );
+    assert!(!retry_sandbox.join("uncommitted.txt").exists());
+
+    assert_eq!(
+        fs::read_to_string(sandbox_path.join("agent-work.txt"))?,
+        "second"
+    );
+    assert!(sandbox_path.join("uncommitted.txt").exists());
+    let gix_repo = gix::open(repo.worktree())?;
+    assert_eq!(
+        gix_repo
+            .find_reference("refs/heads/agtsessions/test-session")?
+            .peel_to_commit()?
+            .id,
+        latest_commit
+    );
+    assert_eq!(
+        gix_repo
+            .find_reference("refs/heads/agtsessions/retry")?
+            .peel_to_commit()?
+            .id,
+        first_commit
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_shadow_commits_describe_session_and_recover_metadata(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
+    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+
+    fs::write(sandbox_path.join("agent-work.txt"), "work")?;
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+
+    let gix_repo = gix::open(repo.worktree())?;
+    let tip = gix_repo
+        .find_reference("refs/heads/agtsessions/test-session")?
+        .peel_to_commit()?;
+    let message = tip.message_raw()?.to_string();
+    assert!(message.contains("Agt-Session: test-session"));
+    assert!(message.contains("Agt-User-Branch: refs/heads/main"));
+    let info = tip
+        .tree()?
+        .lookup_entry_by_path("_agt/session.json")?
+        .ok_or("missing _agt/session.json")?;
+    let info: serde_json::Value = serde_json::from_slice(&gix_repo.find_object(info.oid())?.data)?;
+    assert_eq!(info["session_id"], "test-session");
+    assert!(!repo.repo_root().join("sessions/test-session/_agt").exists());
+
+    let metadata_file = repo.bare.join("agt/sessions/test-session.json");
+    let original: serde_json::Value = serde_json::from_str(&fs::read_to_string(&metadata_file)?)?;
+    fs::remove_file(&metadata_file)?;
+    fs::remove_file(repo.bare.join("agt/timestamps/test-session"))?;
+
+    agt_cmd_with_git()?
+        .args(["session", "recover"])
+        .current_dir(repo.worktree())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Recovered session test-session"));
+    let recovered: serde_json::Value = serde_json::from_str(&fs::read_to_string(&metadata_file)?)?;
+    for field in [
+        "session_id",
+        "branch",
+        "user_branch",
+        "from_commit",
+        "profile",
+    ] {
+        assert_eq!(recovered[field], original[field], "{field}");
+    }
+    assert!(recovered.get("archived_at").is_none());
+
+    fs::write(sandbox_path.join("agent-work.txt"), "more work")?;
+    agt_cmd_with_git()?
+        .args(["autocommit", "--session-id", "test-session"])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Created shadow commit"));
+
+    // Existing metadata is left alone.
+    agt_cmd_with_git()?
+        .args(["session", "recover"])
+        .current_dir(repo.worktree())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Recovered 0 sessions"));
+
+    Ok(())
+}
+
+#[test]
+fn test_concurrent_autocommits_keep_metadata_and_history_intact(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
+    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+
+    // A file from an older agt, with a field this version does not know.
+    let metadata_file = repo.bare.join("agt/sessions/test-session.json");
+    let mut metadata: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&metadata_file)?)?;
+    let fields = metadata
+        .as_object_mut()
+        .ok_or("metadata is not an object")?;
+    fields.remove("schema_version");
+    fields.insert("ticket".to_string(), "ENG-7".into());
+    fs::write(&metadata_file, serde_json::to_string_pretty(&metadata)?)?;
+
+    let mut commands = Vec::new();
+    for index in 0..4 {
+        fs::write(sandbox_path.join(format!("work-{index}.txt")), "work")?;
+        let mut cmd = agt_cmd_with_git()?;
+        cmd.args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--label",
+            &format!("run-{index}"),
+        ])
+        .current_dir(&sandbox_path);
+        commands.push(cmd);
+    }
+    let outputs: Vec<_> = std::thread::scope(|scope| {
+        let handles: Vec<_> = commands
+            .iter_mut()
+            .map(|cmd| scope.spawn(move || cmd.output()))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("autocommit thread panicked"))
+            .collect()
+    });
+    for output in outputs {
+        let output = output?;
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    let gix_repo = gix::open(repo.worktree())?;
+    let mut commit = gix_repo
+        .find_reference("refs/heads/agtsessions/test-session")?
+        .peel_to_commit()?;
+    let mut shadow_commits = 0;
+    while commit.parent_ids().count() == 2 {
+        shadow_commits += 1;
+        let parent = commit.parent_ids().next().ok_or("missing parent")?.detach();
+        commit = gix_repo.find_object(parent)?.try_into_commit()?;
+    }
+    assert_eq!(shadow_commits, 4);
+
+    let metadata: serde_json::Value = serde_json::from_str(&fs::read_to_string(&metadata_file)?)?;
+    assert_eq!(metadata["session_id"], "test-session");
+    assert_eq!(metadata["ticket"], "ENG-7");
+    let tip = gix_repo
+        .find_reference("refs/heads/agtsessions/test-session")?
+        .peel_to_commit()?;
+    let info = tip
+        .tree()?
+        .lookup_entry_by_path("_agt/session.json")?
+        .ok_or("missing _agt/session.json")?;
+    let info: serde_json::Value = serde_json::from_slice(&gix_repo.find_object(info.oid())?.data)?;
+    assert_eq!(info["schema_version"], 2);
+    assert_eq!(info["ticket"], "ENG-7");
+
+    Ok(())
+}
+
+#[test]
+fn test_archive_and_rehydrate_session_folder() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
+    let session_folder = repo.repo_root().join("sessions/test-session");
+    let sandbox_path = session_folder.join("sandbox");
+
+    fs::write(sandbox_path.join(".gitignore"), "target/\n")?;
+    fs::create_dir_all(sandbox_path.join("target"))?;
+    fs::write(sandbox_path.join("target/build.out"), "built")?;
+    fs::write(sandbox_path.join("agent-work.txt"), "work")?;
+    fs::create_dir_all(session_folder.join("xdg"))?;
+    fs::write(session_folder.join("xdg/state.db"), "agent state")?;
+
+    agt_cmd_with_git()?
+        .args(["session", "archive", "--session-id", "test-session"])
+        .current_dir(repo.worktree())
+        .assert()
+        .success();
+    assert!(!session_folder.exists());
+    assert!(!repo.bare.join("worktrees/test-session").exists());
+    let metadata = fs::read_to_string(repo.bare.join("agt/sessions/test-session.json"))?;
+    assert!(metadata.contains("archived_at"));
+
+    agt_cmd_with_git()?
+        .args(["session", "archive", "--session-id", "test-session"])
+        .current_dir(repo.worktree())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("already archived"));
+    agt_cmd_with_git()?
+        .args([
+            "session",
+            "restore",
+            "--session-id",
+            "test-session",
+            "--steps-back",
+            "0",
+            "--yes",
+        ])
+        .current_dir(repo.worktree())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("agt session rehydrate"));
+
+    agt_cmd_with_git()?
+        .args(["session", "rehydrate", "--session-id", "test-session"])
+        .current_dir(repo.worktree())
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(sandbox_path.join("agent-work.txt"))?,
+        "work"
+    );
+    assert_eq!(
+        fs::read_to_string(sandbox_path.join("target/build.out"))?,
+        "built"
+    );
+    assert_eq!(
+        fs::read_to_string(session_folder.join("xdg/state.db"))?,
+        "agent state"
+    );
+    assert!(sandbox_path.join("README.md").exists());
+    let metadata = fs::read_to_string(repo.bare.join("agt/sessions/test-session.json"))?;
+    assert!(!metadata.contains("archived_at"));
+
+    let status = std::process::Command::new("git")
+        .args(["status", "--porcelain"])
+        .current_dir(&sandbox_path)
+        .output()?;
+    assert!(status.status.success());
+
+    // A folder deleted by hand can be rehydrated as well.
+    fs::write(sandbox_path.join("agent-work.txt"), "more work")?;
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+    fs::remove_dir_all(&session_folder)?;
+    agt_cmd_with_git()?
+        .args(["session", "rehydrate", "--session-id", "test-session"])
+        .current_dir(repo.worktree())
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(sandbox_path.join("agent-work.txt"))?,
+        "more work"
+    );
+
+    agt_cmd_with_git()?
+        .args(["session", "rehydrate", "--session-id", "test-session"])
+        .current_dir(repo.worktree())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("already has a sandbox"));
+
+    Ok(())
+}
+
+#[test]
+fn test_fork_clones_session_folder_and_shadow_history() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
+    let source_folder = repo.repo_root().join("sessions/test-session");
+    let source_sandbox = source_folder.join("sandbox");
+
+    fs::write(source_sandbox.join(".gitignore"), "target/\n")?;
+    fs::create_dir_all(source_sandbox.join("target"))?;
+    fs::write(source_sandbox.join("target/build.out"), "built")?;
+    fs::write(source_sandbox.join("agent-work.txt"), "first")?;
+    fs::create_dir_all(source_folder.join("xdg"))?;
+    fs::write(source_folder.join("xdg/state.db"), "agent state")?;
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ])
+        .current_dir(&source_sandbox)
+        .assert()
+        .success();
+
+    let gix_repo = gix::open(repo.worktree())?;
+    let first_commit = gix_repo
+        .find_reference("refs/heads/agtsessions/test-session")?
+        .peel_to_commit()?
+        .id;
+
+    fs::write(source_sandbox.join("agent-work.txt"), "second")?;
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--timestamp",
+            "0",
+        ])
+        .current_dir(&source_sandbox)
+        .assert()
+        .success();
+
+    agt_cmd_with_git()?
+        .args([
+            "session",
+            "fork",
+            "--from",
+            "test-session",
+            "--id",
+            "latest",
+        ])
+        .current_dir(repo.worktree())
+        .assert()
+        .success();
+    let latest_folder = repo.repo_root().join("sessions/latest");
+    assert_eq!(
+        fs::read_to_string(latest_folder.join("sandbox/agent-work.txt"))?,
+        "second"
+    );
+    assert_eq!(
+        fs::read_to_string(latest_folder.join("sandbox/target/build.out"))?,
+        "built"
+    );
+    assert_eq!(
+        fs::read_to_string(latest_folder.join("xdg/state.db"))?,
+        "agent state"
+    );
+    assert!(latest_folder.join("sandbox/README.md").exists());
+
+    agt_cmd_with_git()?
+        .args([
+            "session",
+            "fork",
+            "--from",
+            "test-session",
+            "--id",
+            "earlier",
+            "--commit",
+            &first_commit.to_string(),
+        ])
+        .current_dir(repo.worktree())
+        .assert()
+        .success();
+    let earlier_sandbox = repo.repo_root().join("sessions/earlier/sandbox");
+    assert_eq!(
+        fs::read_to_string(earlier_sandbox.join("agent-work.txt"))?,
+        "first"
+    );
+
+    let gix_repo = gix::open(repo.worktree())?;
+    let fork_tip = gix_repo
+        .find_reference("refs/heads/agtsessions/earlier")?
+        .peel_to_commit()?
+        .id;
+    assert_eq!(fork_tip, first_commit);
+
+    let metadata = fs::read_to_string(repo.bare.join("agt/sessions/earlier.json"))?;
+    let metadata: serde_json::Value = serde_json::from_str(&metadata)?;
+    assert_eq!(metadata["forked_from"], "test-session");
+    assert_eq!(metadata["forked_from_commit"], first_commit.to_string());
+
+    fs::write(earlier_sandbox.join("agent-work.txt"), "diverged")?;
+    agt_cmd_with_git()?
+        .args(["autocommit", "--session-id", "earlier", "--timestamp", "0"])
+        .current_dir(&earlier_sandbox)
+        .assert()
+        .success();
+    let gix_repo = gix::open(repo.worktree())?;
+    let fork_commit = gix_repo
+        .find_reference("refs/heads/agtsessions/earlier")?
+        .peel_to_commit()?;
+    assert_eq!(
+        fork_commit.parent_ids().next().map(|id| id.detach()),
+        Some(first_commit)
+    );
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_fork_autocommits_fresh_source_and_rolls_back_on_failure(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
+    agt_cmd_with_git()?
+        .args(["session", "new", "--id", "fresh"])
+        .current_dir(repo.worktree())
+        .assert()
+        .success();
+    let fresh_sandbox = repo.repo_root().join("sessions/fresh/sandbox");
+    fs::write(fresh_sandbox.join("agent-work.txt"), "unsaved")?;
+
+    agt_cmd_with_git()?
+        .args(["session", "fork", "--from", "fresh", "--id", "copy"])
+        .current_dir(repo.worktree())
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(
+            repo.repo_root()
+                .join("sessions/copy/sandbox/agent-work.txt")
+        )?,
+        "unsaved"
+    );
+
+    let failing_tool = repo.repo_root().join("failing-worktree");
+    write_shell_script(
+        &failing_tool,
+        &format!("\"{}\" \"$@\"\nexit 1", ensure_worktree_tool()?.display()),
+    )?;
+    agt_cmd_with_git()?
+        .env("AGT_WORKTREE_PATH", &failing_tool)
+        .args(["session", "fork", "--from", "fresh", "--id", "broken"])
+        .current_dir(repo.worktree())
+        .assert()
+        .failure();
+    let gix_repo = gix::open(&repo.bare)?;
+    assert!(gix_repo
+        .find_reference("refs/heads/agtsessions/broken")
+        .is_err());
+    assert!(!repo.bare.join("worktrees/broken").exists());
+    assert!(!repo.bare.join("agt/sessions/broken.json").exists());
+    assert!(!repo.repo_root().join("sessions/broken").exists());
+
+    agt_cmd_with_git()?
+        .args(["session", "fork", "--from", "fresh", "--id", "broken"])
+        .current_dir(repo.worktree())
+        .assert()
+        .success();
+
+    Ok(())
+}
+
+#[test]
+fn test_session_list_shows_state_filters_sorts_and_warns() -> Result<(), Box<dyn std::error::Error>>
+{
+    let repo = setup_repo_with_session()?;
+    agt_cmd_with_git()?
+        .args(["session", "new", "--id", "quiet-session"])
+        .current_dir(repo.worktree())
+        .assert()
+        .success();
+    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+    fs::write(sandbox_path.join("agent-work.txt"), "work")?;
+
+    let gix_repo = gix::open(&repo.bare)?;
+    let main = gix_repo
+        .find_reference("refs/heads/main")?
+        .peel_to_commit()?
+        .id;
+    gix_repo.reference(
+        "refs/heads/agtsessions/ghost",
+        main,
+        gix::refs::transaction::PreviousValue::MustNotExist,
+        "orphan",
+    )?;
+
+    let output = agt_cmd_with_git()?
+        .args(["session", "list"])
+        .current_dir(repo.worktree())
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stdout.starts_with("ID "), "{stdout}");
+    for column in [
+        "PROFILE",
+        "USER BRANCH",
+        "PENDING",
+        "SANDBOX",
+        "AHEAD/BEHIND",
+    ] {
+        assert!(stdout.contains(column), "{column}: {stdout}");
+    }
+    let row = stdout
+        .lines()
+        .find(|line| line.starts_with("test-session "))
+        .ok_or("missing test-session row")?;
+    let cells: Vec<_> = row.split_whitespace().collect();
+    assert!(cells.contains(&"main"), "{row}");
+    assert!(cells.contains(&"dirty"), "{row}");
+    assert!(cells.contains(&"+0/-0"), "{row}");
+    assert!(stderr.contains("shadow branch agtsessions/ghost has no session metadata"));
+
+    let output = agt_cmd_with_git()?
+        .args(["session", "list", "--filter", "sandbox=dirty"])
+        .current_dir(repo.worktree())
+        .output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("test-session"));
+    assert!(!stdout.contains("quiet-session"));
+
+    let output = agt_cmd_with_git()?
+        .args(["session", "list", "--sort", "id", "--reverse"])
+        .current_dir(repo.worktree())
+        .output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+    let ids: Vec<_> = stdout
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().next())
+        .collect();
+    assert_eq!(ids, ["test-session", "quiet-session"]);
+
+    fs::remove_dir_all(repo.repo_root().join("sessions/quiet-session"))?;
+    agt_cmd_with_git()?
+        .args(["session", "list", "--filter", "id=quiet*"])
+        .current_dir(repo.worktree())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("missing"))
+        .stderr(predicate::str::contains(
+            "sandbox of session quiet-session is missing",
+        ));
+
+    agt_cmd_with_git()?
+        .args(["session", "list", "--filter", "color=red"])
+        .current_dir(repo.worktree())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown filter key color"));
+
+    Ok(())
+}
+
+#[test]
+fn test_session_status_shows_pending_changes_and_divergence(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
+    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+
+    fs::write(repo.worktree().join("upstream.txt"), "upstream")?;
+    let gix_repo = gix::open(&repo.bare)?;
+    commit_worktree(
+        &gix_repo,
+        repo.worktree(),
+        "refs/heads/main",
+        "Upstream work",
+        "user@example.com",
+    )?;
+    fs::write(sandbox_path.join("agent-work.txt"), "work")?;
+
+    agt_cmd_with_git()?
+        .args(["session", "status"])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Session: test-session"))
+        .stdout(predicate::str::contains("Shadow commits: 0"))
+        .stdout(predicate::str::contains(
+            "Divergence: main has 1 commits since the session started",
+        ))
+        .stdout(predicate::str::contains("Pending autocommit:"))
+        .stdout(predicate::str::contains("M sandbox/agent-work.txt"))
+        .stdout(predicate::str::contains("?? agent-work.txt"))
+        .stdout(predicate::str::contains("Lock: free"));
+
+    agt_cmd_with_git()?
+        .args(["status"])
+        .current_dir(repo.worktree())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Sessions with pending changes: 1"));
+
+    agt_cmd_with_git()?
+        .args([
+            "autocommit",
+            "--session-id",
+            "test-session",
+            "--label",
+            "done",
+        ])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+    agt_cmd_with_git()?
+        .args(["session", "status", "--session-id", "test-session"])
+        .current_dir(repo.worktree())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Shadow commits: 1"))
+        .stdout(predicate::str::contains(", label done"))
+        .stdout(predicate::str::contains("Last restore: none"));
+
+    agt_cmd_with_git()?
+        .args(["session", "status", "--session-id", "missing"])
+        .current_dir(repo.worktree())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Session not found: missing"));
+
+    Ok(())
+}
+
+#[test]
+fn test_session_gc_reports_then_removes_orphans() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
+    let gix_repo = gix::open(&repo.bare)?;
+    let main = gix_repo
+        .find_reference("refs/heads/main")?
+        .peel_to_commit()?
+        .id;
+    gix_repo.reference(
+        "refs/heads/feature",
+        main,
+        gix::refs::transaction::PreviousValue::MustNotExist,
+        "feature",
+    )?;
+    gix_repo.reference(
+        "refs/heads/agtsessions/ghost",
+        main,
+        gix::refs::transaction::PreviousValue::MustNotExist,
+        "orphan",
+    )?;
+    gix_repo.reference(
+        "refs/heads/idle",
+        main,
+        gix::refs::transaction::PreviousValue::MustNotExist,
+        "idle",
+    )?;
+    for (id, from) in [
+        ("gone", "main"),
+        ("merged", "feature"),
+        ("busy", "feature"),
+        ("unstarted", "idle"),
+    ] {
+        agt_cmd_with_git()?
+            .args(["session", "new", "--id", id, "--from", from])
+            .current_dir(repo.worktree())
+            .assert()
+            .success();
+    }
+    fs::remove_dir_all(repo.repo_root().join("sessions/gone"))?;
+    fs::write(repo.bare.join("agt/timestamps/old"), "0")?;
+    // A live worktree recorded with a relative path, and a locked one whose
+    // worktree is on a missing drive; neither is stale.
+    let relative_admin = repo.bare.join("worktrees/relative");
+    fs::create_dir_all(&relative_admin)?;
+    fs::create_dir_all(repo.repo_root().join("relative-wt"))?;
+    fs::write(repo.repo_root().join("relative-wt/.git"), "")?;
+    fs::write(relative_admin.join("gitdir"), "../../../relative-wt/.git\n")?;
+    let locked_admin = repo.bare.join("worktrees/locked");
+    fs::create_dir_all(&locked_admin)?;
+    fs::write(locked_admin.join("gitdir"), "/nonexistent/drive/.git\n")?;
+    fs::write(locked_admin.join("locked"), "on a removable drive")?;
+    fs::write(
+        repo.repo_root()
+            .join("sessions/busy/sandbox/agent-work.txt"),
+        "unsaved",
+    )?;
+
+    // Land a commit on feature and fast-forward main to it.
+    let git_path = find_real_git()?;
+    let output = Command::new(&git_path)
+        .arg("--git-dir")
+        .arg(&repo.bare)
+        .args([
+            "-c",
+            "user.name=User",
+            "-c",
+            "user.email=user@example.com",
+            "commit-tree",
+            "main^{tree}",
+            "-p",
+            "main",
+            "-m",
+            "feature work",
+        ])
+        .output()?;
+    assert!(output.status.success());
+    let merged = String::from_utf8(output.stdout)?.trim().to_string();
+    for branch in ["refs/heads/feature", "refs/heads/main"] {
+        assert!(Command::new(&git_path)
+            .arg("--git-dir")
+            .arg(&repo.bare)
+            .args(["update-ref", branch, &merged])
+            .status()?
+            .success());
+    }
+
+    agt_cmd_with_git()?
+        .args(["session", "gc"])
+        .current_dir(repo.worktree())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("session gone: sandbox"))
+        .stdout(predicate::str::contains(
+            "shadow branch agtsessions/ghost has no session metadata",
+        ))
+        .stdout(predicate::str::contains("worktree admin dir"))
+        .stdout(predicate::str::contains("stray file"))
+        .stdout(predicate::str::contains("session merged").not())
+        .stdout(predicate::str::contains("Run agt session gc --apply"));
+    assert!(repo.bare.join("agt/timestamps/old").exists());
+    assert!(gix_repo
+        .find_reference("refs/heads/agtsessions/ghost")
+        .is_ok());
+
+    agt_cmd_with_git()?
+        .args(["session", "gc", "--merged-into", "main", "--apply"])
+        .current_dir(repo.worktree())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "session merged: user branch feature is merged into main",
+        ))
+        .stdout(predicate::str::contains("session unstarted").not())
+        .stderr(predicate::str::contains(
+            "session busy: user branch feature is merged into main, but its sandbox has uncommitted changes; left alone",
+        ))
+        .stdout(predicate::str::contains("Removed 5 items"));
+    assert!(repo.bare.join("agt/sessions/busy.json").exists());
+    assert!(repo.bare.join("agt/sessions/unstarted.json").exists());
+
+    assert!(!repo.bare.join("agt/timestamps/old").exists());
+    assert!(!repo.bare.join("agt/sessions/gone.json").exists());
+    assert!(!repo.bare.join("agt/sessions/merged.json").exists());
+    assert!(!repo.bare.join("worktrees/gone").exists());
+    assert!(relative_admin.exists());
+    assert!(locked_admin.exists());
+    assert!(!repo.repo_root().join("sessions/merged").exists());
+    let gix_repo = gix::open(&repo.bare)?;
+    assert!(gix_repo
+        .find_reference("refs/heads/agtsessions/ghost")
+        .is_err());
+    assert!(gix_repo
+        .find_reference("refs/heads/agtsessions/merged")
+        .is_err());
+    assert!(repo.bare.join("agt/sessions/test-session.json").exists());
+
+    agt_cmd_with_git()?
+        .args(["session", "gc", "--older-than", "1d"])
+        .current_dir(repo.worktree())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Nothing to collect"));
+    agt_cmd_with_git()?
+        .args(["session", "gc", "--older-than", "0s"])
+        .current_dir(repo.worktree())
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+fn commit_in_sandbox(
+    sandbox: &Path,
+    file: &str,
+    contents: &str,
+    author_email: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    fs::write(sandbox.join(file), contents)?;
+    let git_path = find_real_git()?;
+    assert!(Command::new(&git_path)
+        .current_dir(sandbox)
+        .args(["add", file])
+        .status()?
+        .success());
+    assert!(Command::new(&git_path)
+        .current_dir(sandbox)
+        .env("GIT_AUTHOR_EMAIL", author_email)
+        .args(["commit", "-q", "-m", &format!("add {file}")])
+        .status()?
+        .success());
+    Ok(())
+}
+
+fn git_stdout(dir: &Path, args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let output = Command::new(find_real_git()?)
+        .current_dir(dir)
+        .args(args)
+        .output()?;
+    assert!(output.status.success(), "git {args:?} failed");
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+#[test]
+fn test_land_rebases_sandbox_commits_without_shadow_commits(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
+    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+
+    commit_in_sandbox(&sandbox_path, "feature.txt", "feature", "user@example.com")?;
+    agt_cmd_with_git()?
+        .args(["autocommit", "--session-id", "test-session"])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success();
+    commit_in_sandbox(&sandbox_path, "generated.txt", "generated", "agt@local")?;
+
+    fs::write(sandbox_path.join("scratch.txt"), "scratch")?;
+    agt_cmd_with_git()?
+        .args([
+            "session",
+            "land",
+            "--session-id",
+            "test-session",
+            "--rebase",
+        ])
+        .current_dir(repo.worktree())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Sandbox has uncommitted changes"));
+    fs::remove_file(sandbox_path.join("scratch.txt"))?;
+
+    agt_cmd_with_git()?
+        .args([
+            "session",
+            "land",
+            "--session-id",
+            "test-session",
+            "--rebase",
+            "--strip-agent-commits",
+            "--remove",
+        ])
+        .current_dir(repo.worktree())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Landed session test-session into main",
+        ));
+
+    let subjects = git_stdout(repo.worktree(), &["log", "--format=%s", "main"])?;
+    let subjects: Vec<_> = subjects.lines().collect();
+    assert_eq!(
+        subjects[..2],
+        [
+            "Agent changes from agt session test-session",
+            "add feature.txt"
+        ]
+    );
+    assert!(!subjects.contains(&"agt autocommit"));
+    assert_eq!(
+        fs::read_to_string(repo.worktree().join("generated.txt"))?,
+        "generated"
+    );
+    assert!(!repo.bare.join("agt/sessions/test-session.json").exists());
+    assert!(gix::open(&repo.bare)?
+        .find_reference("refs/heads/agtsessions/test-session")
+        .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_land_conflict_leaves_main_worktree_unchanged() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
+    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+    commit_in_sandbox(
+        &sandbox_path,
+        "README.md",
+        "from the agent\n",
+        "user@example.com",
+    )?;
+
+    fs::write(repo.worktree().join("README.md"), "from upstream\n")?;
+    git_stdout(repo.worktree(), &["commit", "-q", "-am", "upstream edit"])?;
+    let before = git_stdout(repo.worktree(), &["rev-parse", "HEAD"])?;
+
+    // The positional session ID of older releases is still accepted.
+    agt_cmd_with_git()?
+        .args(["session", "land", "test-session"])
+        .current_dir(repo.worktree())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("conflicts in: README.md"));
+
+    assert_eq!(git_stdout(repo.worktree(), &["rev-parse", "HEAD"])?, before);
+    assert_eq!(
+        git_stdout(
+            repo.worktree(),
+            &["status", "--porcelain", "--untracked-files=no"]
+        )?,
+        ""
+    );
+    assert!(!repo.bare.join("worktrees/repo/MERGE_HEAD").exists());
+    assert!(sandbox_path.exists());
+
+    agt_cmd_with_git()?
+        .args([
+            "session",
+            "land",
+            "--session-id",
+            "test-session",
+            "--into",
+            "feature",
+        ])
+        .current_dir(repo.worktree())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("The main worktree is on main"));
+
+    git_stdout(repo.worktree(), &["reset", "-q", "--hard", "HEAD~1"])?;
+    agt_cmd_with_git()?
+        .args([
+            "session",
+            "land",
+            "--session-id",
+            "test-session",
+            "-m",
+            "Land README rewrite",
+        ])
+        .current_dir(repo.worktree())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Archived session test-session"));
+    assert_eq!(
+        git_stdout(repo.worktree(), &["log", "-1", "--format=%s %p"])?
+            .split_whitespace()
+            .count(),
+        5
+    );
+    assert_eq!(
+        fs::read_to_string(repo.worktree().join("README.md"))?,
+        "from the agent\n"
+    );
+    assert!(!sandbox_path.exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_export_requires_clean_worktree() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
+    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+
+    fs::write(sandbox_path.join("uncommitted.txt"), "dirty")?;
+
+    let output = agt_cmd_with_git()?
+        .args(["session", "export", "--session-id", "test-session"])
+        .current_dir(repo.worktree())
+        .output()?;
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("uncommitted") || stderr.contains("commit"));
+
+    Ok(())
+}
+
+#[test]
+fn test_export_pushes_user_branch() -> Result<(), Box<dyn std::error::Error>> {
+    let tmp = TempDir::new()?;
+
+    let remote_bare = tmp.path().join("remote.git");
+    init_bare_repo_with_commit(&remote_bare)?;
+
+    let local = tmp.path().join("local");
+    fs::create_dir_all(&local)?;
+
+    agt_cmd_with_git()?
+        .args(["clone", remote_bare.to_str().unwrap()])
+        .current_dir(&local)
+        .assert()
+        .success();
+
+    let worktree = local.join("remote/main");
+    write_agt_config(&worktree, "agt@local", "agtsessions/")?;
+
+    agt_cmd_with_git()?
+        .args(["session", "new", "--id", "export-test"])
+        .current_dir(&worktree)
+        .assert()
+        .success();
+
+    let sandbox_path = local.join("remote/sessions/export-test/sandbox");
+    fs::write(sandbox_path.join("exported.txt"), "content")?;
 
-    let status = Command::new("cargo")
-        .args(["build", "-p", "agt-worktree"])
+    let git_path = find_real_git()?;
+    Command::new(&git_path)
+        .current_dir(&sandbox_path)
+        .args(["add", "exported.txt"])
+        .status()?;
+    Command::new(&git_path)
+        .current_dir(&sandbox_path)
+        .args(["commit", "-m", "add exported file"])
         .status()?;
-    if !status.success() {
-        return Err("failed to build agt-worktree".into());
-    }
 
-    if debug.exists() {
-        Ok(debug)
-    } else if release.exists() {
-        Ok(release)
-    } else {
-        Err("agt-worktree binary not found after build".into())
-    }
+    agt_cmd_with_git()?
+        .args(["session", "export", "--session-id", "export-test"])
+        .current_dir(&worktree)
+        .assert()
+        .success();
+
+    let output = Command::new(&git_path)
+        .current_dir(&remote_bare)
+        .args(["branch", "-a"])
+        .output()?;
+    let branches = String::from_utf8_lossy(&output.stdout);
+
+    assert!(branches.contains("agtsessions/export-test"));
+    assert!(!branches.contains("main") || branches.contains("main"));
+
+    Ok(())
 }
 
 #[test]
-fn test_restore_resets_sandbox_to_shadow_commit() -> Result<(), Box<dyn std::error::Error>> {
+fn test_session_compare_reports_each_session_and_pairwise_diff(
+) -> Result<(), Box<dyn std::error::Error>> {
     let repo = setup_repo_with_session()?;
-    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
-
-    fs::write(sandbox_path.join("file-a.txt"), "version 1")?;
+    let gix_repo = gix::open(&repo.bare)?;
+    let main = gix_repo
+        .find_reference("refs/heads/main")?
+        .peel_to_commit()?
+        .id;
+    gix_repo.reference(
+        "refs/heads/rival-work",
+        main,
+        gix::refs::transaction::PreviousValue::MustNotExist,
+        "rival",
+    )?;
     agt_cmd_with_git()?
-        .args([
-            "autocommit",
-            "--session-id",
-            "test-session",
-            "--timestamp",
-            "0",
-        ])
-        .current_dir(&sandbox_path)
+        .args(["session", "new", "--id", "rival", "--from", "rival-work"])
+        .current_dir(repo.worktree())
         .assert()
         .success();
 
-    let gix_repo = gix::open(repo.worktree())?;
-    let mut branch_ref = gix_repo.find_reference("refs/heads/agtsessions/test-session")?;
-    let first_commit = branch_ref.peel_to_commit()?.id;
-
-    fs::write(sandbox_path.join("file-a.txt"), "version 2")?;
-    fs::write(sandbox_path.join("file-b.txt"), "new file")?;
+    let first = repo.repo_root().join("sessions/test-session/sandbox");
+    fs::write(first.join("answer.txt"), "one\ntwo\n")?;
     agt_cmd_with_git()?
-        .args([
-            "autocommit",
-            "--session-id",
-            "test-session",
-            "--timestamp",
-            "0",
-        ])
-        .current_dir(&sandbox_path)
+        .args(["autocommit", "--session-id", "test-session"])
+        .current_dir(&first)
         .assert()
         .success();
 
-    assert!(sandbox_path.join("file-b.txt").exists());
+    let rival = repo.repo_root().join("sessions/rival/sandbox");
+    commit_in_sandbox(&rival, "extra.txt", "extra\n", "user@example.com")?;
+    commit_in_sandbox(&rival, "answer.txt", "one\n", "user@example.com")?;
+    // Stands in for landing the rival's work on its user branch.
+    git_stdout(&rival, &["update-ref", "refs/heads/rival-work", "HEAD"])?;
+
+    let output = agt_cmd_with_git()?
+        .args(["session", "compare", "test-session", "rival"])
+        .current_dir(repo.worktree())
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("Comparing 2 sessions from"));
+    let row = |id: &str| {
+        stdout
+            .lines()
+            .find(|line| line.starts_with(id))
+            .map(|line| line.split_whitespace().take(6).collect::<Vec<_>>())
+    };
     assert_eq!(
-        fs::read_to_string(sandbox_path.join("file-a.txt"))?,
-        "version 2"
+        row("test-session "),
+        Some(vec!["test-session", "main", "0", "+0", "-0", "1"])
     );
+    assert_eq!(
+        row("rival "),
+        Some(vec!["rival", "rival-work", "2", "+2", "-0", "0"])
+    );
+    assert!(stdout.contains("test-session: main changed no files"));
+    assert!(stdout.contains("Files changed on rival-work (rival):"));
+    assert!(stdout.contains("test-session vs rival: 2 files differ, +1 -1"));
 
     agt_cmd_with_git()?
-        .args([
-            "session",
-            "restore",
-            "--session-id",
-            "test-session",
-            "--commit",
-            &first_commit.to_string(),
-        ])
+        .args(["session", "compare", "test-session", "rival", "--patch"])
         .current_dir(repo.worktree())
         .assert()
-        .success();
+        .success()
+        .stdout(predicate::str::contains("-two"));
 
-    assert!(!sandbox_path.join("file-b.txt").exists());
-    assert_eq!(
-        fs::read_to_string(sandbox_path.join("file-a.txt"))?,
-        "version 1"
-    );
+    agt_cmd_with_git()?
+        .args(["session", "compare", "test-session"])
+        .current_dir(repo.worktree())
+        .assert()
+        .failure();
 
     Ok(())
 }
 
 #[test]
-fn test_restore_resets_agent_state_folders() -> Result<(), Box<dyn std::error::Error>> {
+fn test_session_check_records_results_and_prints_scoreboard(
+) -> Result<(), Box<dyn std::error::Error>> {
     let repo = setup_repo_with_session()?;
-    let session_folder = repo.repo_root().join("sessions/test-session");
-    let sandbox_path = session_folder.join("sandbox");
-    let config_dir = session_folder.join("config");
-    let xdg_dir = session_folder.join("xdg");
-
-    fs::create_dir_all(&config_dir)?;
-    fs::create_dir_all(&xdg_dir)?;
-    fs::write(config_dir.join("agent.json"), r#"{"model": "gpt-4"}"#)?;
-    fs::write(xdg_dir.join("state.db"), "initial state")?;
-
     agt_cmd_with_git()?
+        .args(["session", "new", "--id", "rival"])
+        .current_dir(repo.worktree())
+        .assert()
+        .success();
+    let passing = repo.repo_root().join("sessions/test-session/sandbox");
+    fs::write(passing.join("ok.txt"), "ok")?;
+
+    let output = agt_cmd_with_git()?
         .args([
-            "autocommit",
-            "--session-id",
-            "test-session",
-            "--timestamp",
-            "0",
+            "session",
+            "check",
+            "--cmd",
+            "echo checking; test -f ok.txt",
+            "--all",
+            "--parallel",
         ])
-        .current_dir(&sandbox_path)
+        .current_dir(repo.worktree())
+        .output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("1 of 2 sessions failed"));
+    let stdout = String::from_utf8(output.stdout)?;
+    let scoreboard: Vec<_> = stdout
+        .lines()
+        .skip_while(|line| !line.starts_with("SESSION"))
+        .map(|line| line.split_whitespace().take(3).collect::<Vec<_>>())
+        .collect();
+    assert_eq!(
+        scoreboard[1..],
+        [
+            vec!["test-session", "pass", "0"],
+            vec!["rival", "fail", "1"]
+        ]
+    );
+
+    let metadata: serde_json::Value = serde_json::from_str(&fs::read_to_string(
+        repo.bare.join("agt/sessions/test-session.json"),
+    )?)?;
+    let check = &metadata["last_check"];
+    assert_eq!(check["exit_code"], 0);
+    let shadow_tip = git_stdout(
+        repo.worktree(),
+        &["rev-parse", "refs/heads/agtsessions/test-session"],
+    )?;
+    assert_eq!(check["commit"].as_str(), Some(shadow_tip.trim()));
+    let log = repo
+        .repo_root()
+        .join("sessions/test-session")
+        .join(check["log"].as_str().unwrap_or_default());
+    assert_eq!(fs::read_to_string(log)?, "checking\n");
+
+    agt_cmd_with_git()?
+        .args(["session", "status", "--session-id", "rival"])
+        .current_dir(repo.worktree())
         .assert()
-        .success();
+        .success()
+        .stdout(predicate::str::contains(
+            "Last check: `echo checking; test -f ok.txt` failed with exit code 1",
+        ));
+
+    // A session that cannot be checked is reported without losing the
+    // other session's result.
+    let rival_checks = repo.repo_root().join("sessions/rival/checks");
+    fs::remove_dir_all(&rival_checks)?;
+    fs::write(&rival_checks, "in the way")?;
+    let output = agt_cmd_with_git()?
+        .args(["session", "check", "--cmd", "true", "--all"])
+        .current_dir(repo.worktree())
+        .output()?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("Error: session rival"), "{stderr}");
+    assert!(stderr.contains("1 of 2 sessions failed"), "{stderr}");
+    let stdout = String::from_utf8(output.stdout)?;
+    let scoreboard: Vec<_> = stdout
+        .lines()
+        .skip_while(|line| !line.starts_with("SESSION"))
+        .map(|line| line.split_whitespace().take(2).collect::<Vec<_>>())
+        .collect();
+    assert_eq!(
+        scoreboard[1..],
+        [vec!["test-session", "pass"], vec!["rival", "error"]]
+    );
+    let metadata: serde_json::Value = serde_json::from_str(&fs::read_to_string(
+        repo.bare.join("agt/sessions/test-session.json"),
+    )?)?;
+    assert_eq!(metadata["last_check"]["command"], "true");
 
-    let gix_repo = gix::open(repo.worktree())?;
-    let mut branch_ref = gix_repo.find_reference("refs/heads/agtsessions/test-session")?;
-    let first_commit = branch_ref.peel_to_commit()?.id;
+    // Check logs stay out of shadow history.
+    let files = git_stdout(
+        repo.worktree(),
+        &[
+            "ls-tree",
+            "-r",
+            "--name-only",
+            "refs/heads/agtsessions/test-session",
+        ],
+    )?;
+    assert!(files.contains("sandbox/ok.txt"), "{files}");
+    assert!(!files.contains("checks/"), "{files}");
+
+    Ok(())
+}
+
+#[test]
+fn test_session_bisect_finds_first_bad_shadow_commit() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_repo_with_session()?;
+    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+    for (index, value) in ["ok", "still ok", "broken", "broken"].iter().enumerate() {
+        fs::write(sandbox_path.join("value.txt"), value)?;
+        fs::write(sandbox_path.join("step.txt"), index.to_string())?;
+        agt_cmd_with_git()?
+            .args(["autocommit", "--session-id", "test-session"])
+            .current_dir(&sandbox_path)
+            .assert()
+            .success();
+    }
+    let shadow = |rev: &str| git_stdout(repo.worktree(), &["rev-parse", rev]);
+    let good = shadow("agtsessions/test-session~3")?;
+    let first_bad = shadow("agtsessions/test-session~1")?;
 
-    fs::write(config_dir.join("agent.json"), r#"{"model": "claude-3"}"#)?;
-    fs::write(xdg_dir.join("state.db"), "modified state")?;
-    fs::write(xdg_dir.join("new-file.txt"), "new")?;
     agt_cmd_with_git()?
         .args([
-            "autocommit",
+            "session",
+            "bisect",
             "--session-id",
             "test-session",
-            "--timestamp",
-            "0",
+            "--good",
+            good.trim(),
+            "--cmd",
+            "test \"$(cat value.txt)\" != broken",
         ])
-        .current_dir(&sandbox_path)
+        .current_dir(repo.worktree())
         .assert()
-        .success();
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "First bad commit: {}",
+            first_bad.trim()
+        )))
+        .stdout(predicate::str::contains("value.txt"));
+    assert_eq!(
+        fs::read_to_string(sandbox_path.join("value.txt"))?,
+        "broken"
+    );
 
     agt_cmd_with_git()?
         .args([
             "session",
-            "restore",
+            "bisect",
             "--session-id",
             "test-session",
-            "--commit",
-            &first_commit.to_string(),
+            "--good",
+            first_bad.trim(),
+            "--bad",
+            good.trim(),
+            "--cmd",
+            "true",
         ])
         .current_dir(repo.worktree())
         .assert()
-        .success();
-
-    assert_eq!(
-        fs::read_to_string(config_dir.join("agent.json"))?,
-        r#"{"model": "gpt-4"}"#
-    );
-    assert_eq!(
-        fs::read_to_string(xdg_dir.join("state.db"))?,
-        "initial state"
-    );
-    assert!(!xdg_dir.join("new-file.txt").exists());
+        .failure()
+        .stderr(predicate::str::contains(
+            "is not on the first-parent history",
+        ));
 
     Ok(())
 }
 
 #[test]
-fn test_restore_continues_autocommit_with_correct_parent() -> Result<(), Box<dyn std::error::Error>>
-{
+fn test_checkpoint_and_rewind_from_inside_sandbox() -> Result<(), Box<dyn std::error::Error>> {
     let repo = setup_repo_with_session()?;
     let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+    fs::write(sandbox_path.join("plan.txt"), "before refactor")?;
 
-    fs::write(sandbox_path.join("a.txt"), "a")?;
     agt_cmd_with_git()?
-        .args([
-            "autocommit",
-            "--session-id",
-            "test-session",
-            "--timestamp",
-            "0",
-        ])
+        .args(["checkpoint", "-m", "before-refactor"])
         .current_dir(&sandbox_path)
         .assert()
-        .success();
-
-    let gix_repo = gix::open(repo.worktree())?;
-    let mut branch_ref = gix_repo.find_reference("refs/heads/agtsessions/test-session")?;
-    let first_commit_id = branch_ref.peel_to_commit()?.id;
+        .success()
+        .stdout(predicate::str::contains(
+            "Checkpoint before-refactor of session test-session",
+        ));
+    agt_cmd_with_git()?
+        .args(["checkpoint", "-m", "before-refactor"])
+        .current_dir(&sandbox_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("already exists"));
 
-    fs::write(sandbox_path.join("b.txt"), "b")?;
+    fs::write(sandbox_path.join("plan.txt"), "after refactor")?;
+    fs::write(sandbox_path.join("new.txt"), "new")?;
     agt_cmd_with_git()?
-        .args([
-            "autocommit",
-            "--session-id",
-            "test-session",
-            "--timestamp",
-            "0",
-        ])
+        .args(["autocommit", "--session-id", "test-session"])
         .current_dir(&sandbox_path)
         .assert()
         .success();
 
+    let checkpoint = git_stdout(
+        repo.worktree(),
+        &[
+            "rev-parse",
+            "refs/agt/sessions/test-session/checkpoints/before-refactor",
+        ],
+    )?;
     agt_cmd_with_git()?
-        .args([
-            "session",
-            "restore",
-            "--session-id",
-            "test-session",
-            "--commit",
-            &first_commit_id.to_string(),
-        ])
-        .current_dir(repo.worktree())
+        .args(["checkpoint", "list"])
+        .current_dir(&sandbox_path)
         .assert()
-        .success();
+        .success()
+        .stdout(predicate::str::contains(&checkpoint[..12]))
+        .stdout(predicate::str::contains("before-refactor"));
+
+    agt_cmd_with_git()?
+        .args(["rewind", "no-such-label"])
+        .current_dir(&sandbox_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No checkpoint no-such-label"));
+    agt_cmd_with_git()?
+        .args(["rewind", "before-refactor"])
+        .current_dir(&sandbox_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Rewound session test-session to checkpoint before-refactor",
+        ));
+    assert_eq!(
+        fs::read_to_string(sandbox_path.join("plan.txt"))?,
+        "before refactor"
+    );
+    assert!(!sandbox_path.join("new.txt").exists());
 
-    fs::write(sandbox_path.join("c.txt"), "c")?;
     agt_cmd_with_git()?
         .args([
-            "autocommit",
+            "session",
+            "remove",
             "--session-id",
             "test-session",
-            "--timestamp",
-            "0",
+            "--delete-branch",
         ])
-        .current_dir(&sandbox_path)
+        .current_dir(repo.worktree())
         .assert()
         .success();
-
-    let gix_repo = gix::open(repo.worktree())?;
-    let mut branch_ref = gix_repo.find_reference("refs/heads/agtsessions/test-session")?;
-    let new_commit = branch_ref.peel_to_commit()?;
-    let parents: Vec<_> = new_commit.parent_ids().map(|id| id.to_owned()).collect();
-
-    assert_eq!(parents.len(), 2);
-    assert_eq!(parents[0], first_commit_id);
+    assert!(git_stdout(
+        repo.worktree(),
+        &["for-each-ref", "refs/agt/sessions/test-session/"]
+    )?
+    .is_empty());
 
     Ok(())
 }
 
 #[test]
-fn test_restore_deletes_files_not_in_shadow_tree() -> Result<(), Box<dyn std::error::Error>> {
+fn test_session_resolution_order_and_session_env() -> Result<(), Box<dyn std::error::Error>> {
     let repo = setup_repo_with_session()?;
-    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+    let session_folder = repo.repo_root().join("sessions/test-session");
 
-    fs::write(sandbox_path.join("keep.txt"), "keep")?;
     agt_cmd_with_git()?
-        .args([
-            "autocommit",
-            "--session-id",
-            "test-session",
-            "--timestamp",
-            "0",
-        ])
-        .current_dir(&sandbox_path)
+        .args(["session", "env", "--session-id", "test-session"])
+        .current_dir(repo.worktree())
         .assert()
-        .success();
-
-    let gix_repo = gix::open(repo.worktree())?;
-    let mut branch_ref = gix_repo.find_reference("refs/heads/agtsessions/test-session")?;
-    let first_commit = branch_ref.peel_to_commit()?.id;
+        .success()
+        .stdout(predicate::str::contains(
+            "export AGT_SESSION_ID='test-session'",
+        ))
+        .stdout(predicate::str::contains(
+            "export AGT_SHADOW_BRANCH='agtsessions/test-session'",
+        ));
+    // The session can also be named by argument, or by `remove --id`.
+    agt_cmd_with_git()?
+        .args(["session", "env", "test-session"])
+        .current_dir(repo.worktree())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "export AGT_SESSION_ID='test-session'",
+        ));
+    agt_cmd_with_git()?
+        .args(["session", "remove", "--id", "no-such-session"])
+        .current_dir(repo.worktree())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Pruned session: no-such-session"));
 
-    fs::write(sandbox_path.join("extra.txt"), "extra")?;
+    // Inside the session folder, outside the sandbox.
     agt_cmd_with_git()?
-        .args([
-            "autocommit",
-            "--session-id",
-            "test-session",
-            "--timestamp",
-            "0",
-        ])
-        .current_dir(&sandbox_path)
+        .args(["session", "status"])
+        .current_dir(&session_folder)
         .assert()
-        .success();
+        .success()
+        .stdout(predicate::str::contains("Session: test-session"));
 
-    assert!(sandbox_path.join("extra.txt").exists());
+    fs::write(session_folder.join("sandbox/env.txt"), "env")?;
+    agt_cmd_with_git()?
+        .args(["autocommit"])
+        .env("AGT_SESSION_ID", "test-session")
+        .current_dir(repo.worktree())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Created shadow commit"));
 
     agt_cmd_with_git()?
-        .args([
-            "session",
-            "restore",
-            "--session-id",
-            "test-session",
-            "--commit",
-            &first_commit.to_string(),
-        ])
+        .args(["session", "status"])
+        .env("AGT_SESSION_ID", "no-such-session")
+        .current_dir(repo.worktree())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Session not found: no-such-session (from AGT_SESSION_ID)",
+        ));
+    agt_cmd_with_git()?
+        .args(["session", "status", "--session-id", "test-session"])
+        .env("AGT_SESSION_ID", "no-such-session")
         .current_dir(repo.worktree())
         .assert()
         .success();
+    agt_cmd_with_git()?
+        .args(["session", "status"])
+        .current_dir(repo.worktree())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unable to determine the session"));
 
-    assert!(sandbox_path.join("keep.txt").exists());
-    assert!(!sandbox_path.join("extra.txt").exists());
+    // A sandbox moved out of its session folder is found by worktree name.
+    let moved = repo.repo_root().join("moved-sandbox");
+    git_stdout(
+        repo.worktree(),
+        &[
+            "worktree",
+            "move",
+            session_folder.join("sandbox").to_str().unwrap_or_default(),
+            moved.to_str().unwrap_or_default(),
+        ],
+    )?;
+    agt_cmd_with_git()?
+        .args(["session", "env"])
+        .current_dir(&moved)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "export AGT_SESSION_ID='test-session'",
+        ));
 
     Ok(())
 }
 
 #[test]
-fn test_restore_preserves_tracked_file_content() -> Result<(), Box<dyn std::error::Error>> {
+fn test_session_rename_and_move_with_rollback() -> Result<(), Box<dyn std::error::Error>> {
     let repo = setup_repo_with_session()?;
-    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
+    let sessions = repo.repo_root().join("sessions");
 
-    let bare_repo = gix::open(&repo.bare)?;
-    let main_worktree = repo.worktree();
-
-    fs::write(main_worktree.join("tracked.txt"), "original tracked")?;
-    commit_worktree(
-        &bare_repo,
-        main_worktree,
-        "refs/heads/main",
-        "Add tracked file",
-        "agt@local",
-    )?;
+    fs::write(sessions.join("test-session/sandbox/work.txt"), "work")?;
+    agt_cmd_with_git()?
+        .args(["checkpoint", "-m", "before-rename"])
+        .current_dir(sessions.join("test-session/sandbox"))
+        .assert()
+        .success();
 
-    fs::write(sandbox_path.join("tracked.txt"), "original tracked")?;
+    // A directory in the way of the timestamp file makes the rename fail
+    // after the branch, refs and folders have already moved.
+    let blocker = repo.bare.join("agt/timestamps/renamed/blocker");
+    fs::create_dir_all(&blocker)?;
     agt_cmd_with_git()?
         .args([
-            "autocommit",
+            "session",
+            "rename",
             "--session-id",
             "test-session",
-            "--timestamp",
-            "0",
+            "renamed",
         ])
-        .current_dir(&sandbox_path)
+        .current_dir(repo.worktree())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("changes were rolled back"));
+    assert!(sessions.join("test-session/sandbox/work.txt").exists());
+    assert!(!sessions.join("renamed").exists());
+    assert!(!repo.bare.join("worktrees/renamed").exists());
+    let branches = git_stdout(repo.worktree(), &["branch", "--list", "agtsessions/*"])?;
+    assert!(branches.contains("agtsessions/test-session"));
+    assert!(!branches.contains("agtsessions/renamed"));
+    agt_cmd_with_git()?
+        .args(["session", "status", "--session-id", "test-session"])
+        .current_dir(repo.worktree())
         .assert()
         .success();
+    fs::remove_dir_all(repo.bare.join("agt/timestamps/renamed"))?;
 
-    fs::write(sandbox_path.join("tracked.txt"), "modified tracked")?;
     agt_cmd_with_git()?
         .args([
-            "autocommit",
+            "session",
+            "rename",
             "--session-id",
             "test-session",
-            "--timestamp",
-            "0",
+            "renamed",
         ])
-        .current_dir(&sandbox_path)
+        .current_dir(repo.worktree())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Renamed session test-session to renamed",
+        ));
+    let sandbox = sessions.join("renamed/sandbox");
+    assert!(!sessions.join("test-session").exists());
+    assert_eq!(
+        git_stdout(&sandbox, &["rev-parse", "--abbrev-ref", "HEAD"])?.trim(),
+        "agtsessions/renamed"
+    );
+    assert!(
+        !git_stdout(repo.worktree(), &["branch", "--list", "agtsessions/*"])?
+            .contains("test-session")
+    );
+    assert!(repo.bare.join("agt/timestamps/renamed").exists());
+    agt_cmd_with_git()?
+        .args(["checkpoint", "list"])
+        .current_dir(&sandbox)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("before-rename"));
+    agt_cmd_with_git()?
+        .args(["session", "rename", "renamed", "renamed"])
+        .current_dir(repo.worktree())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "The old and new IDs are the same: renamed",
+        ));
+    agt_cmd_with_git()?
+        .args(["session", "new", "--id", "other"])
+        .current_dir(repo.worktree())
         .assert()
         .success();
-
-    let gix_repo = gix::open(repo.worktree())?;
-    let mut branch_ref = gix_repo.find_reference("refs/heads/agtsessions/test-session")?;
-    let target_commit = branch_ref.peel_to_commit()?.id;
-
-    fs::write(sandbox_path.join("tracked.txt"), "drift")?;
-
+    agt_cmd_with_git()?
+        .args(["session", "rename", "--session-id", "renamed", "other"])
+        .current_dir(repo.worktree())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Session other already exists"));
     agt_cmd_with_git()?
         .args([
             "session",
-            "restore",
+            "rename",
             "--session-id",
-            "test-session",
-            "--commit",
-            &target_commit.to_string(),
+            "renamed",
+            "renamed",
+            "fresh",
         ])
         .current_dir(repo.worktree())
         .assert()
-        .success();
+        .failure()
+        .stderr(predicate::str::contains("not both"));
 
-    assert_eq!(
-        fs::read_to_string(sandbox_path.join("tracked.txt"))?,
-        "modified tracked"
-    );
+    #[cfg(unix)]
+    {
+        // A symlink to the session folder does not hide that the
+        // destination is inside it.
+        let link = repo.repo_root().join("link-to-session");
+        std::os::unix::fs::symlink(sessions.join("renamed"), &link)?;
+        agt_cmd_with_git()?
+            .args(["session", "move", "--session-id", "renamed", "--to"])
+            .arg(link.join("nested"))
+            .current_dir(repo.worktree())
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "Cannot move session renamed into its own folder",
+            ));
+    }
+
+    let moved = repo.repo_root().join("elsewhere/renamed");
+    agt_cmd_with_git()?
+        .args(["session", "move", "--session-id", "renamed", "--to"])
+        .arg(&moved)
+        .current_dir(repo.worktree())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Moved session renamed"));
+    assert!(!sessions.join("renamed").exists());
+    assert_eq!(fs::read_to_string(moved.join("sandbox/work.txt"))?, "work");
+    git_stdout(&moved.join("sandbox"), &["status", "--short"])?;
+    fs::write(moved.join("sandbox/after-move.txt"), "moved")?;
+    agt_cmd_with_git()?
+        .args(["autocommit"])
+        .current_dir(moved.join("sandbox"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Created shadow commit"));
+    let worktrees = git_stdout(repo.worktree(), &["worktree", "list", "--porcelain"])?;
+    assert!(worktrees.contains(&moved.join("sandbox").display().to_string()));
 
     Ok(())
 }
 
+#[cfg(unix)]
 #[test]
-fn test_export_requires_clean_worktree() -> Result<(), Box<dyn std::error::Error>> {
-    let repo = setup_repo_with_session()?;
-    let sandbox_path = repo.repo_root().join("sessions/test-session/sandbox");
-
-    fs::write(sandbox_path.join("uncommitted.txt"), "dirty")?;
+fn test_session_new_rolls_back_on_failure() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_basic_repo()?;
+    write_agt_config(repo.worktree(), "agt@local", "agtsessions/")?;
+    let failing_tool = repo.repo_root().join("failing-worktree");
+    write_shell_script(
+        &failing_tool,
+        &format!("\"{}\" \"$@\"\nexit 1", ensure_worktree_tool()?.display()),
+    )?;
 
-    let output = agt_cmd_with_git()?
-        .args(["session", "export", "--session-id", "test-session"])
+    agt_cmd_with_git()?
+        .env("AGT_WORKTREE_PATH", &failing_tool)
+        .args(["session", "new", "--ticket", "XYZ-9", "--ticket-branch"])
         .current_dir(repo.worktree())
-        .output()?;
+        .assert()
+        .failure();
+    // The sandbox worktree was added before the failure.
+    let gix_repo = gix::open(&repo.bare)?;
+    assert!(gix_repo.find_reference("refs/heads/xyz-9").is_err());
+    assert!(gix_repo
+        .find_reference("refs/heads/agtsessions/xyz-9")
+        .is_err());
+    assert!(!repo.bare.join("worktrees/xyz-9").exists());
+    assert!(!repo.bare.join("agt/timestamps/xyz-9").exists());
+    assert!(!repo.bare.join("agt/sessions/xyz-9.json").exists());
+    assert!(!repo.repo_root().join("sessions/xyz-9").exists());
 
-    assert!(!output.status.success());
-    let stderr = String::from_utf8_lossy(&output.stderr);
-    assert!(stderr.contains("uncommitted") || stderr.contains("commit"));
+    agt_cmd_with_git()?
+        .args(["session", "new", "--ticket", "XYZ-9", "--ticket-branch"])
+        .current_dir(repo.worktree())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Created session: xyz-9"));
 
     Ok(())
 }
 
 #[test]
-fn test_export_pushes_user_branch() -> Result<(), Box<dyn std::error::Error>> {
-    let tmp = TempDir::new()?;
-
-    let remote_bare = tmp.path().join("remote.git");
-    init_bare_repo_with_commit(&remote_bare)?;
-
-    let local = tmp.path().join("local");
-    fs::create_dir_all(&local)?;
+fn test_session_ticket_fields_meta_and_list_filters() -> Result<(), Box<dyn std::error::Error>> {
+    let repo = setup_basic_repo()?;
+    write_agt_config(repo.worktree(), "agt@local", "agtsessions/")?;
 
     agt_cmd_with_git()?
-        .args(["clone", remote_bare.to_str().unwrap()])
-        .current_dir(&local)
+        .args([
+            "session",
+            "new",
+            "--ticket",
+            "ABC-123",
+            "--title",
+            "Fix login redirect",
+            "--label",
+            "api",
+            "--label",
+            "urgent",
+            "--meta",
+            "reviewer=alice",
+            "--ticket-branch",
+        ])
+        .current_dir(repo.worktree())
         .assert()
-        .success();
-
-    let worktree = local.join("remote/main");
-    write_agt_config(&worktree, "agt@local", "agtsessions/")?;
-
+        .success()
+        .stdout(predicate::str::contains("Created session: abc-123"))
+        .stdout(predicate::str::contains("Ticket: ABC-123"))
+        .stdout(predicate::str::contains("User branch: abc-123 (created)"));
+    let sandbox = repo.repo_root().join("sessions/abc-123/sandbox");
+    assert_eq!(
+        git_stdout(repo.worktree(), &["rev-parse", "abc-123"])?,
+        git_stdout(repo.worktree(), &["rev-parse", "main"])?
+    );
+    fs::write(sandbox.join("fix.txt"), "fix")?;
     agt_cmd_with_git()?
-        .args(["session", "new", "--id", "export-test"])
-        .current_dir(&worktree)
+        .args(["autocommit"])
+        .current_dir(&sandbox)
         .assert()
-        .success();
+        .success()
+        .stdout(predicate::str::contains("Created shadow commit"));
 
-    let sandbox_path = local.join("remote/sessions/export-test/sandbox");
-    fs::write(sandbox_path.join("exported.txt"), "content")?;
+    // A second attempt at the same ticket gets the next free ID.
+    agt_cmd_with_git()?
+        .args(["session", "new", "--ticket", "ABC-123", "--label", "retry"])
+        .current_dir(repo.worktree())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Created session: abc-123-2"));
 
-    let git_path = find_real_git()?;
-    Command::new(&git_path)
-        .current_dir(&sandbox_path)
-        .args(["add", "exported.txt"])
-        .status()?;
-    Command::new(&git_path)
-        .current_dir(&sandbox_path)
-        .args(["commit", "-m", "add exported file"])
-        .status()?;
+    agt_cmd_with_git()?
+        .args(["session", "meta", "get", "title"])
+        .current_dir(&sandbox)
+        .assert()
+        .success()
+        .stdout("Fix login redirect\n");
+    agt_cmd_with_git()?
+        .args(["session", "meta", "set", "reviewer", "bob"])
+        .current_dir(&sandbox)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Set reviewer of session abc-123 to bob",
+        ));
+    agt_cmd_with_git()?
+        .args(["session", "meta", "get"])
+        .current_dir(&sandbox)
+        .assert()
+        .success()
+        .stdout("ticket=ABC-123\ntitle=Fix login redirect\nlabels=api,urgent\nreviewer=bob\n");
+    agt_cmd_with_git()?
+        .args(["session", "status"])
+        .current_dir(&sandbox)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Labels: api, urgent"))
+        .stdout(predicate::str::contains("reviewer: bob"));
+
+    let list = |filter: &str| -> Result<String, Box<dyn std::error::Error>> {
+        let output = agt_cmd_with_git()?
+            .args(["session", "list", "--filter", filter])
+            .current_dir(repo.worktree())
+            .output()?;
+        assert!(output.status.success(), "{filter}");
+        Ok(String::from_utf8(output.stdout)?)
+    };
+    let ids = |stdout: &str| -> Vec<String> {
+        stdout
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split_whitespace().next())
+            .map(str::to_string)
+            .collect()
+    };
+    let stdout = list("ticket=ABC-*")?;
+    assert!(
+        stdout.starts_with("ID ") && stdout.contains("TICKET"),
+        "{stdout}"
+    );
+    assert_eq!(ids(&stdout), ["abc-123", "abc-123-2"]);
+    assert_eq!(ids(&list("label=urgent")?), ["abc-123"]);
+    assert_eq!(ids(&list("label=re*")?), ["abc-123-2"]);
+    assert_eq!(ids(&list("meta.reviewer=bob")?), ["abc-123"]);
+    assert_eq!(list("title=Nothing")?, "No agent sessions found\n");
 
     agt_cmd_with_git()?
-        .args(["session", "export", "--session-id", "export-test"])
-        .current_dir(&worktree)
+        .args(["session", "meta", "set", "reviewer", ""])
+        .current_dir(&sandbox)
         .assert()
         .success();
-
-    let output = Command::new(&git_path)
-        .current_dir(&remote_bare)
-        .args(["branch", "-a"])
-        .output()?;
-    let branches = String::from_utf8_lossy(&output.stdout);
-
-    assert!(branches.contains("agtsessions/export-test"));
-    assert!(!branches.contains("main") || branches.contains("main"));
+    agt_cmd_with_git()?
+        .args(["session", "meta", "get", "reviewer"])
+        .current_dir(&sandbox)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Session abc-123 has no field reviewer",
+        ));
 
     Ok(())
 }